@@ -0,0 +1,472 @@
+//! Measuring the structure of a [`Graph`].
+//!
+//! Every function here treats edge weights as strengths: a heavier edge means a closer
+//! relationship, and for path lengths an edge of weight `w` is `1 / w` long. Edges with a weight of
+//! zero or less are ignored, as if they didn't exist. Only edges whose weight has been set are
+//! visited (see [`Graph::sparse_edges`]), so these scale with the number of interactions rather
+//! than the number of pairs of nodes.
+//!
+//! Scores are returned as a map from node key to value. They can be used to size or colour the
+//! nodes of a [`System`](crate::System) with [`System::size_by`](crate::System::size_by) and
+//! [`System::colour_by`](crate::System::colour_by), whose graph is available from
+//! [`System::graph`](crate::System::graph).
+use crate::community::{by_size, split_disconnected};
+use crate::graph::{Graph, HasKey};
+use hashbrown::HashMap;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+const MAX_ITERATIONS: usize = 1000;
+const TOLERANCE: f64 = 1e-10;
+
+/// A graph with its nodes numbered, for algorithms which are simpler over indices.
+pub(crate) struct Indexed<K> {
+    pub(crate) keys: Vec<K>,
+    pub(crate) indices: HashMap<K, usize>,
+    // The edges with a positive weight between different nodes, listed from both ends.
+    pub(crate) adjacency: Vec<Vec<(usize, f64)>>,
+}
+
+impl<K: std::hash::Hash + Eq + Clone> Indexed<K> {
+    pub(crate) fn new<N, W>(graph: &Graph<N, W>) -> Self
+    where
+        N: HasKey<Key = K>,
+        W: Clone + Default + Into<f64>,
+    {
+        let keys = graph.nodes().map(HasKey::key).collect::<Vec<_>>();
+        let indices = keys
+            .iter()
+            .enumerate()
+            .map(|(i, key)| (key.clone(), i))
+            .collect::<HashMap<_, _>>();
+        let adjacency = keys
+            .iter()
+            .map(|key| {
+                // A self-loop links a node to nothing else, so it is ignored, as in the layout.
+                let mut edges = graph
+                    .sparse_edges(key)
+                    .filter(|(other, _)| other.key() != *key)
+                    .map(|(other, weight)| (indices[&other.key()], weight.clone().into()))
+                    .filter(|&(_, weight)| weight > 0.)
+                    .collect::<Vec<_>>();
+                // The graph's edges are in hash order, which doesn't need to be repeatable, but
+                // the order of floating point sums should be.
+                edges.sort_unstable_by_key(|&(other, _)| other);
+                edges
+            })
+            .collect();
+        Self {
+            keys,
+            indices,
+            adjacency,
+        }
+    }
+
+    fn scores(&self, values: Vec<f64>) -> HashMap<K, f64> {
+        self.keys.iter().cloned().zip(values).collect()
+    }
+
+    fn strength(&self, node: usize) -> f64 {
+        self.adjacency[node].iter().map(|(_, weight)| weight).sum()
+    }
+}
+
+/// A distance in a priority queue, ordered so that the shortest comes out first.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Distance(f64, usize);
+
+impl Eq for Distance {}
+
+impl PartialOrd for Distance {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Distance {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0).then(self.1.cmp(&other.1))
+    }
+}
+
+/// Get the weighted degree (or strength) of every node: the total weight of its edges.
+#[must_use]
+pub fn weighted_degree<N, W>(graph: &Graph<N, W>) -> HashMap<N::Key, f64>
+where
+    N: HasKey,
+    W: Clone + Default + Into<f64>,
+{
+    let indexed = Indexed::new(graph);
+    let degrees = (0..indexed.keys.len())
+        .map(|node| indexed.strength(node))
+        .collect();
+    indexed.scores(degrees)
+}
+
+/// Get the betweenness centrality of every node: the number of shortest paths between other pairs
+/// of nodes which pass through it.
+///
+/// Where there are several shortest paths between a pair, each counts for an equal share.
+///
+/// This uses Brandes' algorithm, which takes O(n e + n² log n) time.
+#[must_use]
+pub fn betweenness<N, W>(graph: &Graph<N, W>) -> HashMap<N::Key, f64>
+where
+    N: HasKey,
+    W: Clone + Default + Into<f64>,
+{
+    let indexed = Indexed::new(graph);
+    let count = indexed.keys.len();
+    let mut centrality = vec![0.; count];
+    for source in 0..count {
+        let search = ShortestPaths::from(&indexed, source);
+        // Accumulate dependencies from the furthest nodes back towards the source.
+        let mut dependency = vec![0.; count];
+        for &node in search.order.iter().rev() {
+            for &previous in &search.previous[node] {
+                dependency[previous] +=
+                    search.paths[previous] / search.paths[node] * (1. + dependency[node]);
+            }
+            if node != source {
+                centrality[node] += dependency[node];
+            }
+        }
+    }
+    // Every path was counted once from each end.
+    indexed.scores(centrality.into_iter().map(|value| value / 2.).collect())
+}
+
+/// Get the eigenvector centrality of every node, scaled so that the scores have a Euclidean norm
+/// of 1. A node scores highly if it has strong edges to other nodes which score highly.
+#[must_use]
+pub fn eigenvector<N, W>(graph: &Graph<N, W>) -> HashMap<N::Key, f64>
+where
+    N: HasKey,
+    W: Clone + Default + Into<f64>,
+{
+    let indexed = Indexed::new(graph);
+    let count = indexed.keys.len();
+    let mut scores = vec![1. / (count as f64).sqrt(); count];
+    for _ in 0..MAX_ITERATIONS {
+        // Power iteration on A + I, which has the same eigenvectors as A, but converges even when
+        // the graph is bipartite.
+        let mut next = scores.clone();
+        for (node, edges) in indexed.adjacency.iter().enumerate() {
+            for &(other, weight) in edges {
+                next[node] += weight * scores[other];
+            }
+        }
+        let norm = next.iter().map(|value| value * value).sum::<f64>().sqrt();
+        if norm == 0. {
+            break;
+        }
+        for value in &mut next {
+            *value /= norm;
+        }
+        let change = next
+            .iter()
+            .zip(&scores)
+            .map(|(a, b)| (a - b).abs())
+            .sum::<f64>();
+        scores = next;
+        if change < TOLERANCE * count as f64 {
+            break;
+        }
+    }
+    indexed.scores(scores)
+}
+
+/// Get the [PageRank](https://en.wikipedia.org/wiki/PageRank) of every node, with the given damping factor (usually 0.85). The scores add up
+/// to 1.
+///
+/// Each node passes its score on to its neighbours in proportion to the weights of its edges.
+/// Nodes without any edges share theirs with every node.
+#[must_use]
+pub fn pagerank<N, W>(graph: &Graph<N, W>, damping: f64) -> HashMap<N::Key, f64>
+where
+    N: HasKey,
+    W: Clone + Default + Into<f64>,
+{
+    let indexed = Indexed::new(graph);
+    let count = indexed.keys.len();
+    let strengths = (0..count)
+        .map(|node| indexed.strength(node))
+        .collect::<Vec<_>>();
+    let mut ranks = vec![1. / count as f64; count];
+    for _ in 0..MAX_ITERATIONS {
+        let isolated = (0..count)
+            .filter(|&node| strengths[node] == 0.)
+            .map(|node| ranks[node])
+            .sum::<f64>();
+        let base = (1. - damping).mul_add(1. / count as f64, damping * isolated / count as f64);
+        let mut next = vec![base; count];
+        for (node, edges) in indexed.adjacency.iter().enumerate() {
+            for &(other, weight) in edges {
+                next[other] += damping * ranks[node] * weight / strengths[node];
+            }
+        }
+        let change = next
+            .iter()
+            .zip(&ranks)
+            .map(|(a, b)| (a - b).abs())
+            .sum::<f64>();
+        ranks = next;
+        if change < TOLERANCE {
+            break;
+        }
+    }
+    indexed.scores(ranks)
+}
+
+/// Assign every node to a connected component, numbered from 0 in order of decreasing size.
+#[must_use]
+pub fn components<N, W>(graph: &Graph<N, W>) -> HashMap<N::Key, usize>
+where
+    N: HasKey,
+    W: Clone + Default + Into<f64>,
+{
+    let indexed = Indexed::new(graph);
+    // A single community split into its connected parts is exactly the connected components.
+    let parts = split_disconnected(&indexed.adjacency, &vec![0; indexed.keys.len()]);
+    indexed.keys.into_iter().zip(by_size(&parts)).collect()
+}
+
+/// A shortest path between two nodes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Path<K> {
+    /// The total length of the path, where each edge of weight `w` is `1 / w` long.
+    pub length: f64,
+    /// Every node along the path, starting and ending with the two nodes it joins.
+    pub nodes: Vec<K>,
+}
+
+/// Find the shortest path between two nodes, using Dijkstra's algorithm.
+///
+/// Returns `None` if either node does not exist, or there is no path between them.
+#[must_use]
+pub fn shortest_path<N, W>(graph: &Graph<N, W>, from: &N::Key, to: &N::Key) -> Option<Path<N::Key>>
+where
+    N: HasKey,
+    W: Clone + Default + Into<f64>,
+{
+    let indexed = Indexed::new(graph);
+    let (&source, &target) = (indexed.indices.get(from)?, indexed.indices.get(to)?);
+    let search = ShortestPaths::from(&indexed, source);
+    let length = search.distances[target];
+    if length.is_infinite() {
+        return None;
+    }
+    let mut nodes = vec![indexed.keys[target].clone()];
+    let mut node = target;
+    while node != source {
+        node = *search.previous[node].first()?;
+        nodes.push(indexed.keys[node].clone());
+    }
+    nodes.reverse();
+    Some(Path { length, nodes })
+}
+
+/// Get the length of the shortest path from a node to every node it is connected to, including
+/// itself.
+#[must_use]
+pub fn distances<N, W>(graph: &Graph<N, W>, from: &N::Key) -> HashMap<N::Key, f64>
+where
+    N: HasKey,
+    W: Clone + Default + Into<f64>,
+{
+    let indexed = Indexed::new(graph);
+    let Some(&source) = indexed.indices.get(from) else {
+        return HashMap::new();
+    };
+    let search = ShortestPaths::from(&indexed, source);
+    search
+        .order
+        .into_iter()
+        .map(|node| (indexed.keys[node].clone(), search.distances[node]))
+        .collect()
+}
+
+/// The result of Dijkstra's algorithm from one node, keeping every shortest path.
+struct ShortestPaths {
+    distances: Vec<f64>,
+    // The number of shortest paths to each node.
+    paths: Vec<f64>,
+    // The nodes before each node on any of its shortest paths.
+    previous: Vec<Vec<usize>>,
+    // Every node reached, in order of distance.
+    order: Vec<usize>,
+}
+
+impl ShortestPaths {
+    fn from<K>(indexed: &Indexed<K>, source: usize) -> Self {
+        let count = indexed.adjacency.len();
+        let mut search = Self {
+            distances: vec![f64::INFINITY; count],
+            paths: vec![0.; count],
+            previous: vec![Vec::new(); count],
+            order: Vec::new(),
+        };
+        let mut done = vec![false; count];
+        search.distances[source] = 0.;
+        search.paths[source] = 1.;
+        let mut queue = BinaryHeap::from([Reverse(Distance(0., source))]);
+        while let Some(Reverse(Distance(distance, node))) = queue.pop() {
+            if done[node] {
+                continue;
+            }
+            done[node] = true;
+            search.order.push(node);
+            for &(other, weight) in &indexed.adjacency[node] {
+                let through = distance + 1. / weight;
+                let current = search.distances[other];
+                // Paths which differ only by rounding are treated as equally short.
+                if (through - current).abs() <= 1e-9 * through {
+                    search.paths[other] += search.paths[node];
+                    search.previous[other].push(node);
+                } else if through < current {
+                    search.distances[other] = through;
+                    search.paths[other] = search.paths[node];
+                    search.previous[other] = vec![node];
+                    queue.push(Reverse(Distance(through, other)));
+                }
+            }
+        }
+        search
+    }
+}
+
+/// Scale scores linearly so that the lowest is 0 and the highest is 1. If every score is the same,
+/// they are all scaled to 1.
+#[must_use]
+pub fn normalise<K: Clone + std::hash::Hash + Eq>(scores: &HashMap<K, f64>) -> HashMap<K, f32> {
+    let min = scores.values().copied().fold(f64::INFINITY, f64::min);
+    let max = scores.values().copied().fold(f64::NEG_INFINITY, f64::max);
+    let range = max - min;
+    scores
+        .iter()
+        .map(|(key, &score)| {
+            let scaled = if range > 0. {
+                (score - min) / range
+            } else {
+                1.
+            };
+            (key.clone(), scaled as f32)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        betweenness, components, distances, eigenvector, normalise, pagerank, shortest_path,
+        weighted_degree, Path,
+    };
+    use crate::graph::{Graph, HasKey};
+    use crate::System;
+
+    struct Node(u32);
+
+    impl HasKey for Node {
+        type Key = u32;
+
+        fn key(&self) -> u32 {
+            self.0
+        }
+    }
+
+    fn graph(nodes: u32, edges: &[(u32, u32, f32)]) -> Graph<Node, f32> {
+        let mut graph = Graph::new();
+        for i in 0..nodes {
+            graph.add_node(Node(i));
+        }
+        for &(from, to, weight) in edges {
+            graph.set_weight(from, to, weight);
+        }
+        graph
+    }
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-6, "{a} != {b}");
+    }
+
+    #[test]
+    fn star() {
+        // Node 0 is the hub of a star of four, and node 5 is on its own.
+        let star = graph(6, &[(0, 1, 1.), (0, 2, 2.), (0, 3, 1.), (0, 4, 1.)]);
+        let degrees = weighted_degree(&star);
+        assert_close(degrees[&0], 5.);
+        assert_close(degrees[&2], 2.);
+        assert_close(degrees[&5], 0.);
+
+        // Every one of the 6 pairs of leaves has its only shortest path through the hub.
+        let between = betweenness(&star);
+        assert_close(between[&0], 6.);
+        assert_close(between[&1], 0.);
+
+        let eigen = eigenvector(&star);
+        assert!(eigen[&0] > eigen[&2] && eigen[&2] > eigen[&1]);
+        assert_close(eigen.values().map(|value| value * value).sum(), 1.);
+
+        let ranks = pagerank(&star, 0.85);
+        assert_close(ranks.values().sum(), 1.);
+        assert!(ranks[&0] > ranks[&2] && ranks[&2] > ranks[&1] && ranks[&1] > ranks[&5]);
+
+        let components = components(&star);
+        assert_eq!(components[&0], 0);
+        assert_eq!(components[&4], 0);
+        assert_eq!(components[&5], 1);
+
+        let sizes = normalise(&degrees);
+        assert!((sizes[&0] - 1.).abs() < 1e-6 && sizes[&5] == 0.);
+    }
+
+    #[test]
+    fn paths() {
+        // The direct edge from 0 to 3 is weak, so going round through 1 and 2 is shorter.
+        let square = graph(5, &[(0, 1, 4.), (1, 2, 4.), (2, 3, 4.), (0, 3, 1.)]);
+        assert_eq!(
+            shortest_path(&square, &0, &3),
+            Some(Path {
+                length: 0.75,
+                nodes: vec![0, 1, 2, 3]
+            })
+        );
+        assert_eq!(shortest_path(&square, &0, &4), None);
+        assert_eq!(shortest_path(&square, &0, &9), None);
+        let from_0 = distances(&square, &0);
+        assert_eq!(from_0.len(), 4);
+        assert_close(from_0[&0], 0.);
+        assert_close(from_0[&2], 0.5);
+
+        // With two equally short routes from 0 to 2, each middle node gets half the credit.
+        let diamond = graph(4, &[(0, 1, 1.), (1, 2, 1.), (0, 3, 1.), (3, 2, 1.)]);
+        let between = betweenness(&diamond);
+        for node in 0..4 {
+            assert_close(between[&node], 0.5);
+        }
+    }
+
+    #[test]
+    fn drives_system() {
+        let mut system: System = System::with_seed(1);
+        for id in 0..4 {
+            system.add_node(id, [0, 0, 0]);
+        }
+        system.set_weight(0, 1, 2.);
+        system.set_weight(0, 2, 2.);
+        system.set_weight(1, 2, 1.);
+
+        let degrees = weighted_degree(system.graph());
+        system.size_by(&degrees, 0.5, 2.).unwrap();
+        system
+            .colour_by(&degrees, [0, 0, 0], [200, 100, 0])
+            .unwrap();
+        let node = |id| system.graph.get_node(&id).unwrap();
+        assert!((node(0).size - 2.).abs() < 1e-6);
+        assert!((node(1).size - 1.625).abs() < 1e-6);
+        assert!((node(3).size - 0.5).abs() < 1e-6);
+        assert_eq!(node(0).colour, [200, 100, 0]);
+        assert_eq!(node(3).colour, [0, 0, 0]);
+        assert!(system.set_size(&9, 1.).is_err());
+    }
+}
@@ -0,0 +1,484 @@
+//! Extracting the backbone of a dense [`Graph`].
+//!
+//! On a busy server, nearly every pair of users has interacted at some point, so every node ends
+//! up joined to every other and the layout becomes a hairball. The methods here each return a copy
+//! of the graph with every node but only its most important edges, which can be laid out instead,
+//! or used to decide which edges to draw.
+//!
+//! As in [`analytics`](crate::analytics), weights are treated as strengths, and only edges whose
+//! weight has been set to something positive are considered. Other edges are never kept.
+//!
+//! A [`System`](crate::System) can also extract the backbone of its graph as it runs, and lay out
+//! or draw only those edges, using [`System::set_backbone`](crate::System::set_backbone).
+use crate::graph::{Graph, HasKey};
+use hashbrown::{HashMap, HashSet};
+use std::hash::Hash;
+
+/// One of the ways of extracting a backbone, as described on the [`Graph`] method of the same
+/// name.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Backbone {
+    /// See [`Graph::top_k_edges`].
+    TopK(usize),
+    /// See [`Graph::threshold`].
+    Threshold(f64),
+    /// See [`Graph::disparity_filter`], with the given significance level.
+    DisparityFilter(f64),
+    /// See [`Graph::maximum_spanning_tree`].
+    MaximumSpanningTree,
+}
+
+impl Backbone {
+    /// Find the edges of the backbone of a graph.
+    pub(crate) fn edges<N, W>(self, graph: &Graph<N, W>) -> EdgeSet<N::Key>
+    where
+        N: HasKey,
+        W: Clone + Default + Into<f64>,
+    {
+        match self {
+            Self::TopK(k) => top_k(graph, k),
+            Self::Threshold(min_weight) => threshold(graph, min_weight),
+            Self::DisparityFilter(alpha) => disparity(graph, alpha),
+            Self::MaximumSpanningTree => spanning_tree(graph),
+        }
+    }
+}
+
+/// How a [`System`](crate::System) uses the backbone of its graph while it runs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BackboneFilter {
+    pub backbone: Backbone,
+    /// Extract the backbone again before every this many steps, starting with the first. If
+    /// `None`, the backbone is only extracted again by
+    /// [`System::extract_backbone`](crate::System::extract_backbone).
+    pub interval: Option<u64>,
+    /// Whether only the edges of the backbone pull nodes together. Other edges are laid out as if
+    /// their weight were 0.
+    pub layout: bool,
+    /// Whether sinks are only told about the edges of the backbone, so that only those are drawn.
+    /// Edges which leave the backbone are passed on with a weight of 0.
+    pub draw: bool,
+}
+
+impl BackboneFilter {
+    /// Lay out and draw only the backbone, extracting it again every `interval` steps.
+    #[must_use]
+    pub const fn new(backbone: Backbone, interval: u64) -> Self {
+        Self {
+            backbone,
+            interval: Some(interval),
+            layout: true,
+            draw: true,
+        }
+    }
+}
+
+/// A set of edges, each stored in both directions so it can be looked up either way round.
+#[derive(Clone, Debug)]
+pub(crate) struct EdgeSet<K>(HashSet<(K, K)>);
+
+impl<K: Hash + Eq + Clone> EdgeSet<K> {
+    pub(crate) fn new() -> Self {
+        Self(HashSet::new())
+    }
+
+    pub(crate) fn insert(&mut self, from: &K, to: &K) {
+        self.0.insert((from.clone(), to.clone()));
+        self.0.insert((to.clone(), from.clone()));
+    }
+
+    pub(crate) fn contains(&self, from: &K, to: &K) -> bool {
+        self.0.contains(&(from.clone(), to.clone()))
+    }
+
+    /// Iterate over every edge, once in each direction.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &(K, K)> {
+        self.0.iter()
+    }
+
+    /// Remove every edge of a node.
+    pub(crate) fn remove_node(&mut self, key: &K) {
+        self.0.retain(|(from, to)| from != key && to != key);
+    }
+}
+
+/// Get the edges of a node with a positive weight.
+fn strong_edges<'a, N, W>(
+    graph: &'a Graph<N, W>,
+    key: &N::Key,
+) -> impl Iterator<Item = (N::Key, f64)> + 'a
+where
+    N: HasKey,
+    W: Clone + Default + Into<f64>,
+{
+    graph
+        .sparse_edges(key)
+        .map(|(other, weight)| (other.key(), weight.clone().into()))
+        .filter(|&(_, weight)| weight > 0.)
+}
+
+fn top_k<N, W>(graph: &Graph<N, W>, k: usize) -> EdgeSet<N::Key>
+where
+    N: HasKey,
+    W: Clone + Default + Into<f64>,
+{
+    let mut kept = EdgeSet::new();
+    for node in graph.nodes() {
+        let key = node.key();
+        let mut edges = strong_edges(graph, &key).collect::<Vec<_>>();
+        edges.sort_by(|a, b| b.1.total_cmp(&a.1));
+        for (other, _) in edges.into_iter().take(k) {
+            kept.insert(&key, &other);
+        }
+    }
+    kept
+}
+
+fn threshold<N, W>(graph: &Graph<N, W>, min_weight: f64) -> EdgeSet<N::Key>
+where
+    N: HasKey,
+    W: Clone + Default + Into<f64>,
+{
+    let mut kept = EdgeSet::new();
+    for (from, to, weight) in graph.edges_unique() {
+        let weight = weight.clone().into();
+        if weight > 0. && weight >= min_weight {
+            kept.insert(&from.key(), &to.key());
+        }
+    }
+    kept
+}
+
+fn disparity<N, W>(graph: &Graph<N, W>, alpha: f64) -> EdgeSet<N::Key>
+where
+    N: HasKey,
+    W: Clone + Default + Into<f64>,
+{
+    let mut kept = EdgeSet::new();
+    for node in graph.nodes() {
+        let key = node.key();
+        let edges = strong_edges(graph, &key).collect::<Vec<_>>();
+        if edges.len() < 2 {
+            continue;
+        }
+        let strength = edges.iter().map(|(_, weight)| weight).sum::<f64>();
+        let exponent = (edges.len() - 1) as f64;
+        for (other, weight) in edges {
+            // The chance of a random split giving any one edge at least this share.
+            let p_value = (1. - weight / strength).powf(exponent);
+            if p_value < alpha {
+                kept.insert(&key, &other);
+            }
+        }
+    }
+    kept
+}
+
+/// Find a maximum spanning forest with Kruskal's algorithm.
+fn spanning_tree<N, W>(graph: &Graph<N, W>) -> EdgeSet<N::Key>
+where
+    N: HasKey,
+    W: Clone + Default + Into<f64>,
+{
+    let mut edges = graph
+        .edges_unique()
+        .map(|(from, to, weight)| (from.key(), to.key(), weight.clone().into()))
+        .filter(|&(_, _, weight)| weight > 0.)
+        .collect::<Vec<_>>();
+    edges.sort_by(|a, b| b.2.total_cmp(&a.2));
+    let mut forest = Forest::new();
+    let mut kept = EdgeSet::new();
+    for (from, to, _) in edges {
+        if forest.join(&from, &to) {
+            kept.insert(&from, &to);
+        }
+    }
+    kept
+}
+
+impl<N, W> Graph<N, W>
+where
+    N: HasKey + Clone,
+    W: Clone + Default + Into<f64>,
+{
+    /// Copy the graph with only the edges of one of its backbones.
+    #[must_use]
+    pub fn backbone(&self, backbone: Backbone) -> Self {
+        let kept = backbone.edges(self);
+        let mut copy = self.clone();
+        copy.retain_edges(|from, to, _| kept.contains(&from.key(), &to.key()));
+        copy
+    }
+
+    /// Keep only the edges which are among the `k` heaviest of at least one of their nodes.
+    ///
+    /// Every node with any edges keeps at least one, so nodes don't drift away from the rest of
+    /// the layout just because all their edges are light.
+    #[must_use]
+    pub fn top_k_edges(&self, k: usize) -> Self {
+        self.backbone(Backbone::TopK(k))
+    }
+
+    /// Keep only the edges with a weight of at least `min_weight`.
+    #[must_use]
+    pub fn threshold(&self, min_weight: f64) -> Self {
+        self.backbone(Backbone::Threshold(min_weight))
+    }
+
+    /// Keep only the edges which are significant according to the disparity filter of
+    /// [Serrano, Boguñá and Vespignani](https://doi.org/10.1073/pnas.0808904106).
+    ///
+    /// An edge is kept if, for at least one of its nodes, it carries more of that node's total
+    /// weight than would be likely if the weight were spread over its edges at random. `alpha` is
+    /// the significance level: smaller values keep fewer edges, and 0.05 is typical. Only nodes
+    /// with more than one edge are considered, since a single edge carries all of its node's
+    /// weight whether it is significant or not.
+    #[must_use]
+    pub fn disparity_filter(&self, alpha: f64) -> Self {
+        self.backbone(Backbone::DisparityFilter(alpha))
+    }
+
+    /// Keep only the edges of a maximum spanning forest: the heaviest set of edges which joins
+    /// together every node that was connected, without any cycles.
+    ///
+    /// This uses Kruskal's algorithm.
+    #[must_use]
+    pub fn maximum_spanning_tree(&self) -> Self {
+        self.backbone(Backbone::MaximumSpanningTree)
+    }
+}
+
+/// A union-find structure over node keys, tracking which nodes are already connected.
+struct Forest<K> {
+    parents: HashMap<K, K>,
+}
+
+impl<K: Hash + Eq + Clone> Forest<K> {
+    fn new() -> Self {
+        Self {
+            parents: HashMap::new(),
+        }
+    }
+
+    fn root(&mut self, key: &K) -> K {
+        let mut root = key.clone();
+        while let Some(parent) = self.parents.get(&root) {
+            root = parent.clone();
+        }
+        // Point everything on the way directly at the root, so later lookups are quick.
+        let mut current = key.clone();
+        while current != root {
+            let Some(parent) = self.parents.insert(current, root.clone()) else {
+                break;
+            };
+            current = parent;
+        }
+        root
+    }
+
+    /// Connect two nodes, returning `false` if they were already connected.
+    fn join(&mut self, from: &K, to: &K) -> bool {
+        let (from, to) = (self.root(from), self.root(to));
+        if from == to {
+            false
+        } else {
+            self.parents.insert(from, to);
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Backbone, BackboneFilter};
+    use crate::graph::{Graph, HasKey};
+    use crate::{FrameSink, Result, System, Vec2d};
+    use hashbrown::HashMap;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[derive(Clone, Debug)]
+    struct Node(u32);
+
+    impl HasKey for Node {
+        type Key = u32;
+
+        fn key(&self) -> u32 {
+            self.0
+        }
+    }
+
+    fn graph(nodes: u32, edges: &[(u32, u32, f32)]) -> Graph<Node, f32> {
+        let mut graph = Graph::new();
+        for i in 0..nodes {
+            graph.add_node(Node(i));
+        }
+        for &(from, to, weight) in edges {
+            graph.set_weight(from, to, weight);
+        }
+        graph
+    }
+
+    fn edges(graph: &Graph<Node, f32>) -> Vec<(u32, u32)> {
+        let mut edges = graph
+            .edges_unique()
+            .map(|(from, to, _)| (from.0.min(to.0), from.0.max(to.0)))
+            .collect::<Vec<_>>();
+        edges.sort_unstable();
+        edges
+    }
+
+    #[test]
+    fn top_k_and_threshold() {
+        let graph = graph(
+            4,
+            &[(0, 1, 5.), (0, 2, 3.), (0, 3, 1.), (1, 2, 2.), (2, 3, -4.)],
+        );
+        // Node 3's only positive edge is its heaviest, so it is kept even though it is node 0's
+        // lightest.
+        let top = graph.top_k_edges(1);
+        assert_eq!(edges(&top), [(0, 1), (0, 2), (0, 3)]);
+        assert_eq!(top.node_count(), 4);
+        assert_eq!(edges(&graph.threshold(2.5)), [(0, 1), (0, 2)]);
+    }
+
+    #[test]
+    fn disparity_filter_keeps_dominant_edges() {
+        // Node 0 spends nearly all of its weight on node 1, and spreads the rest evenly.
+        let mut edges_list = vec![(0, 1, 100.)];
+        for other in 2..10 {
+            edges_list.push((0, other, 1.));
+            edges_list.push((1, other, 1.));
+        }
+        let graph = graph(10, &edges_list);
+        assert_eq!(edges(&graph.disparity_filter(0.05)), [(0, 1)]);
+        assert_eq!(graph.disparity_filter(1.).edge_count(), graph.edge_count());
+    }
+
+    #[test]
+    fn maximum_spanning_tree() {
+        let graph = graph(
+            6,
+            &[
+                (0, 1, 4.),
+                (1, 2, 3.),
+                (0, 2, 1.),
+                (2, 3, 2.),
+                (1, 3, 5.),
+                (4, 5, 1.),
+            ],
+        );
+        let tree = graph.maximum_spanning_tree();
+        assert_eq!(edges(&tree), [(0, 1), (1, 2), (1, 3), (4, 5)]);
+        assert!((tree.get_weight(&1, &3) - 5.).abs() < f32::EPSILON);
+    }
+
+    /// Records the weight of every edge a sink has been told about.
+    #[derive(Clone, Default)]
+    struct Weights(Rc<RefCell<HashMap<(u64, u64), f32>>>);
+
+    impl FrameSink for Weights {
+        fn weight_changed(&mut self, from: &u64, to: &u64, weight: f32) -> Result<()> {
+            let edge = (*from.min(to), *from.max(to));
+            if weight == 0. {
+                self.0.borrow_mut().remove(&edge);
+            } else {
+                self.0.borrow_mut().insert(edge, weight);
+            }
+            Ok(())
+        }
+    }
+
+    impl Weights {
+        fn edges(&self) -> Vec<(u64, u64)> {
+            let mut edges = self.0.borrow().keys().copied().collect::<Vec<_>>();
+            edges.sort_unstable();
+            edges
+        }
+    }
+
+    #[test]
+    fn system_draws_backbone() {
+        let weights = Weights::default();
+        let mut system: System = System::with_seed(1);
+        system.add_sink(weights.clone()).unwrap();
+        for id in 0..4 {
+            system.add_node(id, None);
+        }
+        system.set_weight(0, 1, 5.);
+        system.set_weight(1, 2, 3.);
+        system.set_weight(0, 2, 1.);
+        system.set_weight(2, 3, 2.);
+        assert_eq!(weights.edges(), [(0, 1), (0, 2), (1, 2), (2, 3)]);
+
+        // The lightest edge of the triangle is hidden straight away.
+        let filter = BackboneFilter::new(Backbone::MaximumSpanningTree, 10);
+        system.set_backbone(Some(filter)).unwrap();
+        assert_eq!(weights.edges(), [(0, 1), (1, 2), (2, 3)]);
+
+        // Changes to edges outside the backbone aren't drawn until it is next extracted, which
+        // is before the first step.
+        system.set_weight(0, 2, 10.);
+        assert_eq!(weights.edges(), [(0, 1), (1, 2), (2, 3)]);
+        system.step().unwrap();
+        assert_eq!(weights.edges(), [(0, 1), (0, 2), (2, 3)]);
+        assert!((weights.0.borrow()[&(0, 2)] - 10.).abs() < f32::EPSILON);
+
+        // A resumed system draws the same edges, without extracting the backbone again.
+        let mut checkpoint = Vec::new();
+        system.save_state(&mut checkpoint).unwrap();
+        let resumed_weights = Weights::default();
+        let mut resumed: System = System::new();
+        resumed.add_sink(resumed_weights.clone()).unwrap();
+        resumed.set_backbone(Some(filter)).unwrap();
+        resumed.load_state(checkpoint.as_slice()).unwrap();
+        assert_eq!(resumed_weights.edges(), weights.edges());
+
+        // Without a backbone, every edge is drawn again.
+        system.set_backbone(None).unwrap();
+        assert_eq!(weights.edges(), [(0, 1), (0, 2), (1, 2), (2, 3)]);
+    }
+
+    /// Records where every node was last moved to.
+    #[derive(Clone, Default)]
+    struct Positions(Rc<RefCell<HashMap<u64, Vec2d>>>);
+
+    impl FrameSink for Positions {
+        fn node_moved(&mut self, id: &u64, pos: Vec2d) -> Result<()> {
+            self.0.borrow_mut().insert(*id, pos);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn system_lays_out_backbone() {
+        // An edge outside the backbone is laid out as if it wasn't there at all.
+        let run = |weights: &[(u64, u64, f32)], backbone: Option<BackboneFilter>| {
+            let positions = Positions::default();
+            let mut system: System = System::with_seed(1);
+            system.add_sink(positions.clone()).unwrap();
+            for id in 0..3 {
+                system.add_node(id, None);
+            }
+            for &(from, to, weight) in weights {
+                system.set_weight(from, to, weight);
+            }
+            system.set_backbone(backbone).unwrap();
+            system.many_steps(10).unwrap();
+            let positions = positions.0.borrow().clone();
+            positions
+        };
+        let filter = BackboneFilter {
+            draw: false,
+            ..BackboneFilter::new(Backbone::Threshold(50.), 1)
+        };
+        assert_eq!(
+            run(&[(0, 1, 100.), (1, 2, 10.)], Some(filter)),
+            run(&[(0, 1, 100.)], None)
+        );
+        assert_ne!(
+            run(&[(0, 1, 100.), (1, 2, 10.)], None),
+            run(&[(0, 1, 100.)], None)
+        );
+    }
+}
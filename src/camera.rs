@@ -0,0 +1,346 @@
+//! Keeping the whole layout in view.
+//!
+//! Layouts often only fill a small corner of the canvas, or drift off it entirely. A [`Camera`]
+//! wraps any other sink, and moves every node before passing it on, zooming and panning smoothly
+//! so that the bounding box of the layout fills the canvas. This works the same way for every
+//! output:
+//!
+//! ```no_run
+//! # #[cfg(feature = "svg")]
+//! # fn main() -> snowball::Result<()> {
+//! use snowball::camera::Camera;
+//! use snowball::svg::SvgAnimation;
+//!
+//! let mut system: snowball::System = snowball::System::new();
+//! system.add_sink(Camera::new(SvgAnimation::new("layout.svg")?))?;
+//! # Ok(())
+//! # }
+//! # #[cfg(not(feature = "svg"))]
+//! # fn main() {}
+//! ```
+//!
+//! Only positions are transformed, so nodes, labels and edges are drawn at the same size however
+//! far the camera zooms.
+use crate::state::{NodeTable, ReadState, WriteState};
+use crate::{FrameSink, NodeKey, Result, Vec2d, SIZE};
+use std::io::{Read, Write};
+
+/// A zoom and pan, mapping positions in the layout to positions on the canvas.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct View {
+    /// The position in the layout shown at the centre of the canvas.
+    pub center: Vec2d,
+    /// How many pixels of the canvas each unit of the layout covers.
+    pub zoom: f32,
+}
+
+impl View {
+    /// Where a position in the layout appears on the canvas.
+    #[must_use]
+    pub fn apply(self, pos: Vec2d) -> Vec2d {
+        (pos - self.center) * self.zoom + Vec2d::new(SIZE / 2., SIZE / 2.)
+    }
+}
+
+/// A sink which fits the layout to the canvas before passing each step on to another sink.
+///
+/// The positions of the nodes are held back until the step finishes, when the camera moves
+/// part of the way towards the view which fits their bounding box, and the nodes are passed on
+/// as seen from there. Every other event is passed on unchanged.
+#[derive(Debug)]
+pub struct Camera<S, K = u64> {
+    sink: S,
+    margin: f32,
+    smoothing: f32,
+    max_zoom: f32,
+    // This is `None` until the first step, when the camera jumps straight to the layout.
+    view: Option<View>,
+    // Every node moved in the current step, with its position in the layout.
+    moves: Vec<(K, Vec2d)>,
+    // Whether the wrapped sink saved no state of its own, so has to be told about every node when
+    // resuming.
+    introduce: bool,
+}
+
+impl<S: FrameSink<K>, K> Camera<S, K> {
+    /// Wrap a sink, leaving a margin of 50 pixels around the layout, moving a tenth of the way to
+    /// the best view each step, and zooming in at most 4 times.
+    pub const fn new(sink: S) -> Self {
+        Self {
+            sink,
+            margin: 50.,
+            smoothing: 0.1,
+            max_zoom: 4.,
+            view: None,
+            moves: Vec::new(),
+            introduce: false,
+        }
+    }
+
+    /// Leave this many pixels between the layout and each edge of the canvas.
+    #[must_use]
+    pub const fn with_margin(mut self, margin: f32) -> Self {
+        self.margin = margin;
+        self
+    }
+
+    /// Move this fraction of the way to the best view each step, from just above 0 for a camera
+    /// which barely moves to 1 for one which follows the layout exactly.
+    #[must_use]
+    pub const fn with_smoothing(mut self, smoothing: f32) -> Self {
+        self.smoothing = smoothing.clamp(f32::MIN_POSITIVE, 1.);
+        self
+    }
+
+    /// Never magnify the layout by more than this, so that a layout with only one or two nodes
+    /// is not spread across the whole canvas.
+    #[must_use]
+    pub const fn with_max_zoom(mut self, max_zoom: f32) -> Self {
+        self.max_zoom = max_zoom;
+        self
+    }
+
+    /// Get the wrapped sink.
+    #[must_use]
+    pub const fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    /// Get the view the last step was shown from, if there has been one.
+    #[must_use]
+    pub const fn view(&self) -> Option<View> {
+        self.view
+    }
+
+    /// The view which fits the bounding box of the nodes moved this step to the canvas.
+    fn fit(&self) -> Option<View> {
+        let (&(_, first), rest) = self.moves.split_first()?;
+        let (min, max) = rest.iter().fold((first, first), |(min, max), &(_, pos)| {
+            (
+                Vec2d::new(min.x.min(pos.x), min.y.min(pos.y)),
+                Vec2d::new(max.x.max(pos.x), max.y.max(pos.y)),
+            )
+        });
+        let extent = max - min;
+        let room = 2f32.mul_add(-self.margin, SIZE).max(1.);
+        // A single node has no extent, so is shown at the maximum zoom.
+        let zoom = (room / extent.x.max(extent.y)).min(self.max_zoom);
+        Some(View {
+            center: (min + max) / 2.,
+            zoom,
+        })
+    }
+}
+
+impl<S: FrameSink<K>, K: NodeKey> FrameSink<K> for Camera<S, K> {
+    fn node_added(&mut self, id: &K, colour: [u8; 3]) -> Result<()> {
+        self.sink.node_added(id, colour)
+    }
+
+    fn avatar_set(&mut self, id: &K, png: &[u8]) -> Result<()> {
+        self.sink.avatar_set(id, png)
+    }
+
+    fn node_removed(&mut self, id: &K) -> Result<()> {
+        self.sink.node_removed(id)
+    }
+
+    fn node_recoloured(&mut self, id: &K, colour: [u8; 3]) -> Result<()> {
+        self.sink.node_recoloured(id, colour)
+    }
+
+    fn node_resized(&mut self, id: &K, size: f32) -> Result<()> {
+        self.sink.node_resized(id, size)
+    }
+
+    fn weight_changed(&mut self, from: &K, to: &K, weight: f32) -> Result<()> {
+        self.sink.weight_changed(from, to, weight)
+    }
+
+    fn node_moved(&mut self, id: &K, pos: Vec2d) -> Result<()> {
+        self.moves.push((id.clone(), pos));
+        Ok(())
+    }
+
+    fn step_finished(&mut self, step: u64) -> Result<()> {
+        if let Some(target) = self.fit() {
+            self.view = Some(self.view.map_or(target, |view| View {
+                center: view.center + (target.center - view.center) * self.smoothing,
+                zoom: (target.zoom - view.zoom).mul_add(self.smoothing, view.zoom),
+            }));
+        }
+        let mut result = Ok(());
+        if let Some(view) = self.view {
+            // As with the system itself, every node is passed on even if one of them fails.
+            for (id, pos) in self.moves.drain(..) {
+                let move_result = self.sink.node_moved(&id, view.apply(pos));
+                if result.is_ok() {
+                    result = move_result;
+                }
+            }
+        }
+        let step_result = self.sink.step_finished(step);
+        result.and(step_result)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.sink.finish()
+    }
+
+    fn save_state(&self, w: &mut dyn Write, nodes: &NodeTable<K>) -> Result<()> {
+        if let Some(view) = self.view {
+            w.write_u8(1)?;
+            w.write_vec2d(view.center)?;
+            w.write_f32(view.zoom)?;
+        } else {
+            w.write_u8(0)?;
+        }
+        let mut state = Vec::new();
+        self.sink.save_state(&mut state, nodes)?;
+        w.write_bytes(&state)
+    }
+
+    fn load_state(&mut self, r: &mut dyn Read, nodes: &NodeTable<K>) -> Result<()> {
+        self.view = if r.read_u8()? == 0 {
+            None
+        } else {
+            Some(View {
+                center: r.read_vec2d()?,
+                zoom: r.read_f32()?,
+            })
+        };
+        self.moves.clear();
+        // As with the system itself, the sink is only asked to load state it saved, and is
+        // otherwise introduced to every node instead.
+        let state = r.read_bytes()?;
+        self.introduce = state.is_empty();
+        if self.introduce {
+            Ok(())
+        } else {
+            self.sink.load_state(&mut state.as_slice(), nodes)
+        }
+    }
+
+    fn needs_introduction(&self) -> bool {
+        self.introduce
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Camera, View};
+    use crate::{FrameSink, Result, System, Vec2d};
+    use hashbrown::HashMap;
+
+    /// Records where every node was last shown.
+    #[derive(Default)]
+    struct Positions(HashMap<u64, Vec2d>);
+
+    impl FrameSink for Positions {
+        fn node_moved(&mut self, id: &u64, pos: Vec2d) -> Result<()> {
+            self.0.insert(*id, pos);
+            Ok(())
+        }
+    }
+
+    fn step(camera: &mut Camera<Positions>, positions: &[Vec2d]) {
+        for (id, &pos) in positions.iter().enumerate() {
+            camera.node_moved(&(id as u64), pos).unwrap();
+        }
+        camera.step_finished(0).unwrap();
+    }
+
+    #[test]
+    fn fits_layout() {
+        let mut camera = Camera::new(Positions::default())
+            .with_smoothing(0.5)
+            .with_max_zoom(100.);
+        step(&mut camera, &[Vec2d::new(-10., 0.), Vec2d::new(10., 5.)]);
+        // The first step is fitted exactly, with the widest side filling the canvas.
+        assert_eq!(
+            camera.view(),
+            Some(View {
+                center: Vec2d::new(0., 2.5),
+                zoom: 45.
+            })
+        );
+        assert_eq!(camera.sink().0[&0], Vec2d::new(50., 387.5));
+        assert_eq!(camera.sink().0[&1], Vec2d::new(950., 612.5));
+
+        // Later steps move half way to the new fit, here zooming out and panning right.
+        step(&mut camera, &[Vec2d::new(0., 0.), Vec2d::new(40., 0.)]);
+        assert_eq!(
+            camera.view(),
+            Some(View {
+                center: Vec2d::new(10., 1.25),
+                zoom: 33.75
+            })
+        );
+        // Nothing is passed on until the step finishes.
+        camera.node_moved(&0, Vec2d::new(1000., 1000.)).unwrap();
+        assert_eq!(camera.sink().0[&0], Vec2d::new(162.5, 457.8125));
+
+        // A single node is shown in the middle, at the maximum zoom.
+        let mut camera = Camera::new(Positions::default()).with_max_zoom(2.);
+        step(&mut camera, &[Vec2d::new(3., 4.)]);
+        assert_eq!(
+            camera.view(),
+            Some(View {
+                center: Vec2d::new(3., 4.),
+                zoom: 2.
+            })
+        );
+        assert_eq!(camera.sink().0[&0], Vec2d::new(500., 500.));
+    }
+
+    #[test]
+    fn resumes_view() {
+        let mut original: System = System::with_seed(1);
+        original.add_node(1, None);
+        original.add_node(2, None);
+        original
+            .add_sink(Camera::new(Positions::default()))
+            .unwrap();
+        original.many_steps(5).unwrap();
+        let mut checkpoint = Vec::new();
+        original.save_state(&mut checkpoint).unwrap();
+
+        let mut resumed: System = System::with_seed(2);
+        resumed.add_sink(Camera::new(Positions::default())).unwrap();
+        resumed.load_state(checkpoint.as_slice()).unwrap();
+        original.step().unwrap();
+        resumed.step().unwrap();
+        let mut checkpoints = [Vec::new(), Vec::new()];
+        original.save_state(&mut checkpoints[0]).unwrap();
+        resumed.save_state(&mut checkpoints[1]).unwrap();
+        assert_eq!(checkpoints[0], checkpoints[1]);
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn resumes_sink_without_state() {
+        use crate::draw::PngFrames;
+        use crate::FrameBuffers;
+
+        let mut original: System = System::with_seed(1);
+        original.add_node(1, None);
+        original.add_node(2, None);
+        original.set_weight(1, 2, 10.);
+        original
+            .add_sink(Camera::new(PngFrames::new(FrameBuffers::new())))
+            .unwrap();
+        original.many_steps(2).unwrap();
+        let mut checkpoint = Vec::new();
+        original.save_state(&mut checkpoint).unwrap();
+
+        // The drawing saves nothing, so it has to be told about the nodes again.
+        let frames = FrameBuffers::new();
+        let mut resumed: System = System::with_seed(2);
+        resumed
+            .add_sink(Camera::new(PngFrames::new(frames.clone())))
+            .unwrap();
+        resumed.load_state(checkpoint.as_slice()).unwrap();
+        resumed.step().unwrap();
+        assert_eq!(frames.take().len(), 1);
+    }
+}
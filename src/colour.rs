@@ -0,0 +1,362 @@
+//! Choosing colours for nodes.
+//!
+//! Nodes added without a colour get one derived from their key, so the same key is always shown
+//! in the same colour, from run to run of a program and across outputs. By default the key is
+//! hashed to a hue in the [Oklab](https://bottosson.github.io/posts/oklab/) colour space, where
+//! colours of the same lightness and chroma look equally bright, so no node stands out more than
+//! another. It can instead be used to pick from one of the built-in [`Palette`]s.
+//!
+//! Every automatically chosen colour is checked against [`BACKGROUND_COLOUR`], and darkened if
+//! needed so that nodes remain visible.
+use std::hash::{Hash, Hasher};
+
+/// The colour every output draws behind the nodes: the light background of the Solarized palette.
+pub const BACKGROUND_COLOUR: [u8; 3] = [238, 232, 213];
+
+/// The colour every output draws edges in by default: the secondary content colour of the
+/// Solarized palette.
+pub const EDGE_COLOUR: [u8; 3] = [147, 161, 161];
+
+/// The colour every output draws labels in by default: the emphasised content colour of the
+/// Solarized palette.
+pub const LABEL_COLOUR: [u8; 3] = [88, 110, 117];
+
+/// The lowest contrast ratio with the background that automatically chosen colours are allowed,
+/// which is what WCAG asks of graphical objects.
+pub const MIN_CONTRAST: f32 = 3.;
+
+// The lightness and chroma used for derived colours, chosen so that every hue fits in sRGB.
+const LIGHTNESS: f32 = 0.6;
+const CHROMA: f32 = 0.12;
+
+/// A named set of colours.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Palette {
+    /// The eight accent colours of [Solarized](https://ethanschoonover.com/solarized/).
+    Solarized,
+    /// The ten colours of Tableau's default categorical palette.
+    Tableau,
+    /// The eight colours suggested by Okabe and Ito, which remain distinguishable to people with
+    /// any common form of colour blindness.
+    OkabeIto,
+}
+
+impl Palette {
+    /// Every built-in palette.
+    pub const ALL: [Self; 3] = [Self::Solarized, Self::Tableau, Self::OkabeIto];
+
+    /// Get the colours in the palette.
+    #[must_use]
+    pub const fn colours(self) -> &'static [[u8; 3]] {
+        match self {
+            Self::Solarized => &[
+                [38, 139, 210],
+                [220, 50, 47],
+                [133, 153, 0],
+                [211, 54, 130],
+                [181, 137, 0],
+                [42, 161, 152],
+                [203, 75, 22],
+                [108, 113, 196],
+            ],
+            Self::Tableau => &[
+                [78, 121, 167],
+                [242, 142, 43],
+                [225, 87, 89],
+                [118, 183, 178],
+                [89, 161, 79],
+                [237, 201, 72],
+                [176, 122, 161],
+                [255, 157, 167],
+                [156, 117, 95],
+                [186, 176, 172],
+            ],
+            Self::OkabeIto => &[
+                [230, 159, 0],
+                [86, 180, 233],
+                [0, 158, 115],
+                [240, 228, 66],
+                [0, 114, 178],
+                [213, 94, 0],
+                [204, 121, 167],
+                [0, 0, 0],
+            ],
+        }
+    }
+
+    /// Get the name of the palette, as accepted by [`from_name`](Self::from_name).
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Solarized => "solarized",
+            Self::Tableau => "tableau",
+            Self::OkabeIto => "okabe-ito",
+        }
+    }
+
+    /// Find a palette by name, ignoring case. `colour-blind` is accepted for
+    /// [`OkabeIto`](Self::OkabeIto).
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase();
+        if name == "colour-blind" || name == "color-blind" {
+            return Some(Self::OkabeIto);
+        }
+        Self::ALL.into_iter().find(|palette| palette.name() == name)
+    }
+}
+
+/// How to choose a colour for a node which was added without one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum AutoColour {
+    /// Hash the key to a hue, at a fixed lightness and chroma.
+    #[default]
+    Hue,
+    /// Hash the key to pick one of the colours in a palette.
+    Palette(Palette),
+}
+
+impl AutoColour {
+    /// Get the colour for a key. This is always the same for the same key, and has at least
+    /// [`MIN_CONTRAST`] with the background.
+    #[must_use]
+    pub fn colour_for(self, key: &impl Hash) -> [u8; 3] {
+        let hash = stable_hash(key);
+        let colour = match self {
+            Self::Hue => {
+                // The top bits are the best distributed.
+                let hue = (hash >> 40) as f32 / (1u64 << 24) as f32 * std::f32::consts::TAU;
+                oklch_to_srgb(LIGHTNESS, CHROMA, hue)
+            }
+            Self::Palette(palette) => {
+                let colours = palette.colours();
+                colours[(hash % colours.len() as u64) as usize]
+            }
+        };
+        ensure_contrast(colour, BACKGROUND_COLOUR, MIN_CONTRAST)
+    }
+}
+
+/// The 64-bit FNV-1a hash. Unlike the hashers in the standard library, its output is specified, so
+/// keys hash the same way in every run.
+///
+/// Integers are hashed as little-endian bytes, and sizes as 64 bits, so that they hash the same
+/// way on every platform. The bytes other types are made of come from their `Hash`
+/// implementations, which are only stable within one build: a new version of Rust may change them,
+/// and with them the colours derived from keys such as strings.
+struct Fnv(u64);
+
+impl Hasher for Fnv {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_i16(&mut self, i: i16) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_i32(&mut self, i: i32) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_i64(&mut self, i: i64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_i128(&mut self, i: i128) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_isize(&mut self, i: isize) {
+        self.write_i64(i as i64);
+    }
+}
+
+fn stable_hash(key: &impl Hash) -> u64 {
+    let mut hasher = Fnv(0xcbf2_9ce4_8422_2325);
+    key.hash(&mut hasher);
+    // FNV mixes the last bytes poorly, so finish with a round of splitmix64.
+    let mut hash = hasher.finish();
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
+
+// The matrices for converting from Oklab to cone responses, and from those to linear sRGB.
+const OKLAB_TO_LMS: [[f32; 3]; 3] = [
+    [1., 0.396_337_78, 0.215_803_76],
+    [1., -0.105_561_346, -0.063_854_17],
+    [1., -0.089_484_18, -1.291_485_5],
+];
+const LMS_TO_LINEAR_SRGB: [[f32; 3]; 3] = [
+    [4.076_741_7, -3.307_711_6, 0.230_969_94],
+    [-1.268_438, 2.609_757_4, -0.341_319_38],
+    [-0.004_196_086_3, -0.703_418_6, 1.707_614_7],
+];
+
+fn transform(matrix: &[[f32; 3]; 3], vector: [f32; 3]) -> [f32; 3] {
+    matrix.map(|row| row[0].mul_add(vector[0], row[1].mul_add(vector[1], row[2] * vector[2])))
+}
+
+/// Convert a colour from Oklch (lightness, chroma and hue in radians) to sRGB, clamping it to the
+/// sRGB gamut.
+#[must_use]
+pub fn oklch_to_srgb(lightness: f32, chroma: f32, hue: f32) -> [u8; 3] {
+    let lab = [lightness, chroma * hue.cos(), chroma * hue.sin()];
+    let lms = transform(&OKLAB_TO_LMS, lab).map(|response| response.powi(3));
+    transform(&LMS_TO_LINEAR_SRGB, lms).map(|channel| {
+        let channel = channel.clamp(0., 1.);
+        let encoded = if channel <= 0.003_130_8 {
+            12.92 * channel
+        } else {
+            1.055f32.mul_add(channel.powf(1. / 2.4), -0.055)
+        };
+        (encoded * 255.).round() as u8
+    })
+}
+
+/// Blend two colours, from `from` when `amount` is 0 to `to` when it is 1.
+#[must_use]
+pub fn mix(from: [u8; 3], to: [u8; 3], amount: f32) -> [u8; 3] {
+    let amount = amount.clamp(0., 1.);
+    let mut mixed = from;
+    for (channel, target) in mixed.iter_mut().zip(to) {
+        let (start, end) = (f32::from(*channel), f32::from(target));
+        *channel = (end - start).mul_add(amount, start).round() as u8;
+    }
+    mixed
+}
+
+/// Get the relative luminance of an sRGB colour, as defined by WCAG.
+#[must_use]
+pub fn luminance(colour: [u8; 3]) -> f32 {
+    let [r, g, b] = colour.map(|channel| {
+        let channel = f32::from(channel) / 255.;
+        if channel <= 0.040_45 {
+            channel / 12.92
+        } else {
+            ((channel + 0.055) / 1.055).powf(2.4)
+        }
+    });
+    0.2126f32.mul_add(r, 0.7152f32.mul_add(g, 0.0722 * b))
+}
+
+/// Get the WCAG contrast ratio between two colours, from 1 (identical) to 21 (black and white).
+#[must_use]
+pub fn contrast_ratio(a: [u8; 3], b: [u8; 3]) -> f32 {
+    let (a, b) = (luminance(a), luminance(b));
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+/// Darken a colour (or lighten it, on a dark background) until its contrast ratio with the
+/// background is at least `min_ratio`. Colours which already have enough contrast are returned
+/// unchanged.
+#[must_use]
+pub fn ensure_contrast(colour: [u8; 3], background: [u8; 3], min_ratio: f32) -> [u8; 3] {
+    let target = if luminance(background) > 0.18 {
+        0.
+    } else {
+        255.
+    };
+    let mut adjusted = colour;
+    for step in 1..=20 {
+        if contrast_ratio(adjusted, background) >= min_ratio {
+            break;
+        }
+        let amount = step as f32 / 20.;
+        adjusted = colour.map(|channel| {
+            f32::from(channel)
+                .mul_add(1. - amount, target * amount)
+                .round() as u8
+        });
+    }
+    adjusted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        contrast_ratio, ensure_contrast, mix, oklch_to_srgb, AutoColour, Palette,
+        BACKGROUND_COLOUR, MIN_CONTRAST,
+    };
+
+    #[test]
+    fn derived_colours_are_stable_and_visible() {
+        let colour = AutoColour::Hue.colour_for(&"alice");
+        assert_eq!(colour, AutoColour::Hue.colour_for(&"alice"));
+        assert_ne!(colour, AutoColour::Hue.colour_for(&"bob"));
+        // Fixed so that a change to the hash, which would recolour every existing animation, is
+        // noticed.
+        assert_eq!(AutoColour::Hue.colour_for(&42_u64), [157, 104, 175]);
+
+        for id in 0..1000_u64 {
+            for auto in [AutoColour::Hue, AutoColour::Palette(Palette::Tableau)] {
+                let colour = auto.colour_for(&id);
+                assert!(contrast_ratio(colour, BACKGROUND_COLOUR) >= MIN_CONTRAST);
+            }
+        }
+    }
+
+    #[test]
+    fn palettes() {
+        for palette in Palette::ALL {
+            assert_eq!(Palette::from_name(palette.name()), Some(palette));
+            let colour = AutoColour::Palette(palette).colour_for(&7_u64);
+            // The colour comes from the palette, darkened if it was too light.
+            assert!(palette.colours().iter().any(|original| {
+                ensure_contrast(*original, BACKGROUND_COLOUR, MIN_CONTRAST) == colour
+            }));
+        }
+        assert_eq!(Palette::from_name("Colour-Blind"), Some(Palette::OkabeIto));
+        assert_eq!(Palette::from_name("rainbow"), None);
+    }
+
+    #[test]
+    fn contrast() {
+        assert!((contrast_ratio([0, 0, 0], [255, 255, 255]) - 21.).abs() < 0.01);
+        assert!((contrast_ratio([10, 20, 30], [10, 20, 30]) - 1.).abs() < 0.01);
+        // Solarized yellow is too light on the Solarized background, so it gets darkened.
+        let yellow = [181, 137, 0];
+        assert!(contrast_ratio(yellow, BACKGROUND_COLOUR) < MIN_CONTRAST);
+        let darker = ensure_contrast(yellow, BACKGROUND_COLOUR, MIN_CONTRAST);
+        assert!(contrast_ratio(darker, BACKGROUND_COLOUR) >= MIN_CONTRAST);
+        assert!(darker.iter().zip(yellow).all(|(new, old)| *new <= old));
+        // On a dark background, colours get lighter instead.
+        let lighter = ensure_contrast([0, 0, 80], [0, 0, 0], MIN_CONTRAST);
+        assert!(contrast_ratio(lighter, [0, 0, 0]) >= MIN_CONTRAST);
+
+        // The Oklab conversion matches the reference for white and a saturated red.
+        assert_eq!(oklch_to_srgb(1., 0., 0.), [255, 255, 255]);
+        assert_eq!(oklch_to_srgb(0.628, 0.2577, 0.510_2), [255, 0, 0]);
+
+        assert_eq!(mix([0, 100, 200], [200, 100, 0], 0.25), [50, 100, 150]);
+        assert_eq!(mix([0, 0, 0], [255, 255, 255], 2.), [255, 255, 255]);
+    }
+}
@@ -0,0 +1,481 @@
+//! Finding communities of closely connected nodes.
+//!
+//! Communities are found with the [Louvain method](https://arxiv.org/abs/0803.0476), which
+//! greedily moves nodes between communities to maximise modularity, then merges each community
+//! into a single node and repeats until nothing changes. Louvain can produce communities whose
+//! members are only connected through other communities, so by default each community is then
+//! split into its connected parts, which is the guarantee the
+//! [Leiden algorithm](https://arxiv.org/abs/1810.08473) adds.
+//!
+//! A [`System`](crate::System) can find communities as it runs, and colour each node by its
+//! community, using [`System::set_community_detection`](crate::System::set_community_detection).
+use crate::analytics::Indexed;
+use crate::colour::{self, Palette, BACKGROUND_COLOUR, MIN_CONTRAST};
+use crate::graph::{Graph, HasKey};
+use hashbrown::HashMap;
+use std::hash::Hash;
+
+// Moves which improve modularity by less than this are not worth making, and could otherwise be
+// repeated forever due to rounding.
+const MIN_GAIN: f64 = 1e-9;
+
+/// How a [`System`](crate::System) finds communities while it runs.
+///
+/// The default finds communities only when asked to, and leaves node colours alone.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CommunityDetection {
+    pub louvain: Louvain,
+    /// Find communities again before every this many steps, starting with the first. If `None`,
+    /// communities are only found by
+    /// [`System::detect_communities`](crate::System::detect_communities).
+    pub interval: Option<u64>,
+    /// If set, every node is drawn in the colour for its community instead of its own, cycling
+    /// through the palette if there are more communities than colours. Nodes added since
+    /// communities were last found keep their own colour until the next time.
+    pub palette: Option<Vec<[u8; 3]>>,
+}
+
+impl CommunityDetection {
+    /// Colour nodes by community with the given palette, finding communities again every
+    /// `interval` steps.
+    #[must_use]
+    pub fn colouring(palette: Palette, interval: u64) -> Self {
+        Self {
+            louvain: Louvain::default(),
+            interval: Some(interval),
+            palette: Some(palette.colours().to_vec()),
+        }
+    }
+
+    /// Get the colour for a community, if colouring by community. Colours which are too close to
+    /// the background are darkened, as for [automatic colours](crate::colour).
+    #[must_use]
+    pub fn colour(&self, community: usize) -> Option<[u8; 3]> {
+        let palette = self
+            .palette
+            .as_ref()
+            .filter(|palette| !palette.is_empty())?;
+        Some(colour::ensure_contrast(
+            palette[community % palette.len()],
+            BACKGROUND_COLOUR,
+            MIN_CONTRAST,
+        ))
+    }
+}
+
+/// Settings for the Louvain method.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Louvain {
+    /// Higher values produce more, smaller communities. The standard definition of modularity
+    /// uses 1.
+    pub resolution: f32,
+    /// Whether to split communities which are not connected internally into their connected
+    /// parts.
+    pub refine: bool,
+}
+
+impl Default for Louvain {
+    fn default() -> Self {
+        Self {
+            resolution: 1.,
+            refine: true,
+        }
+    }
+}
+
+impl Louvain {
+    /// Assign every node in a graph to a community, numbered from 0 in order of decreasing size.
+    ///
+    /// Edges with a weight of zero or less are ignored, so a node with no other edges is in a
+    /// community of its own.
+    #[must_use]
+    pub fn detect<N: HasKey>(&self, graph: &Graph<N, f32>) -> HashMap<N::Key, usize> {
+        let Indexed {
+            keys, adjacency, ..
+        } = Indexed::new(graph);
+
+        let mut communities = self.partition(&adjacency);
+        if self.refine {
+            communities = split_disconnected(&adjacency, &communities);
+        }
+        let communities = by_size(&communities);
+        keys.into_iter().zip(communities).collect()
+    }
+
+    /// Find the community of each node in a graph given as adjacency lists, which must be
+    /// symmetric and have no self loops.
+    fn partition(&self, adjacency: &[Vec<(usize, f64)>]) -> Vec<usize> {
+        let mut level = Level {
+            adjacency: adjacency.to_vec(),
+            self_loops: vec![0.; adjacency.len()],
+        };
+        // The community of every original node, in terms of the nodes of the current level.
+        let mut communities = (0..adjacency.len()).collect::<Vec<_>>();
+        loop {
+            let (moved, level_communities) = level.move_nodes(f64::from(self.resolution));
+            if !moved {
+                return communities;
+            }
+            for community in &mut communities {
+                *community = level_communities[*community];
+            }
+            level = level.aggregate(&level_communities);
+        }
+    }
+}
+
+/// One level of the Louvain method, where each node stands for a community of the level below.
+struct Level {
+    // Weights between different nodes, with every edge listed from both ends.
+    adjacency: Vec<Vec<(usize, f64)>>,
+    // The total weight inside each node, counting each edge from both ends.
+    self_loops: Vec<f64>,
+}
+
+impl Level {
+    fn degree(&self, node: usize) -> f64 {
+        self.adjacency[node].iter().map(|(_, w)| w).sum::<f64>() + self.self_loops[node]
+    }
+
+    /// Move nodes between communities until no move improves modularity. Returns whether any
+    /// node moved, and the community of each node, numbered from 0 without gaps.
+    fn move_nodes(&self, resolution: f64) -> (bool, Vec<usize>) {
+        let count = self.adjacency.len();
+        let degrees = (0..count).map(|i| self.degree(i)).collect::<Vec<_>>();
+        let total = degrees.iter().sum::<f64>();
+        let mut communities = (0..count).collect::<Vec<_>>();
+        if total <= 0. {
+            return (false, communities);
+        }
+        // The total degree of the nodes in each community.
+        let mut totals = degrees.clone();
+        let mut moved = false;
+        loop {
+            let mut improved = false;
+            for node in 0..count {
+                let current = communities[node];
+                let degree = degrees[node];
+                totals[current] -= degree;
+
+                let mut links = HashMap::<usize, f64>::new();
+                links.insert(current, 0.);
+                for &(other, weight) in &self.adjacency[node] {
+                    *links.entry(communities[other]).or_default() += weight;
+                }
+                let gain = |community: usize, weight: f64| {
+                    weight - resolution * totals[community] * degree / total
+                };
+                let mut best = (current, gain(current, links[&current]));
+                let mut candidates = links.into_iter().collect::<Vec<_>>();
+                candidates.sort_unstable_by_key(|&(community, _)| community);
+                for (community, weight) in candidates {
+                    let gain = gain(community, weight);
+                    if gain > best.1 + MIN_GAIN {
+                        best = (community, gain);
+                    }
+                }
+
+                totals[best.0] += degree;
+                if best.0 != current {
+                    communities[node] = best.0;
+                    improved = true;
+                    moved = true;
+                }
+            }
+            if !improved {
+                break;
+            }
+        }
+        (moved, renumber(&communities))
+    }
+
+    /// Merge the nodes of each community into a single node.
+    fn aggregate(&self, communities: &[usize]) -> Self {
+        let count = communities.iter().max().map_or(0, |max| max + 1);
+        let mut weights = vec![HashMap::<usize, f64>::new(); count];
+        let mut self_loops = vec![0.; count];
+        for (node, edges) in self.adjacency.iter().enumerate() {
+            let community = communities[node];
+            self_loops[community] += self.self_loops[node];
+            for &(other, weight) in edges {
+                let other = communities[other];
+                if other == community {
+                    self_loops[community] += weight;
+                } else {
+                    *weights[community].entry(other).or_default() += weight;
+                }
+            }
+        }
+        let adjacency = weights
+            .into_iter()
+            .map(|edges| {
+                let mut edges = edges.into_iter().collect::<Vec<_>>();
+                edges.sort_unstable_by_key(|&(other, _)| other);
+                edges
+            })
+            .collect();
+        Self {
+            adjacency,
+            self_loops,
+        }
+    }
+}
+
+/// Number communities from 0 in order of first appearance.
+fn renumber(communities: &[usize]) -> Vec<usize> {
+    let mut numbers = HashMap::new();
+    communities
+        .iter()
+        .map(|community| {
+            let next = numbers.len();
+            *numbers.entry(*community).or_insert(next)
+        })
+        .collect()
+}
+
+/// Number communities from 0 in order of decreasing size, breaking ties by first appearance.
+pub(crate) fn by_size(communities: &[usize]) -> Vec<usize> {
+    let communities = renumber(communities);
+    let mut sizes = vec![0; communities.iter().max().map_or(0, |max| max + 1)];
+    for &community in &communities {
+        sizes[community] += 1;
+    }
+    let mut order = (0..sizes.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&community| std::cmp::Reverse(sizes[community]));
+    let mut numbers = vec![0; sizes.len()];
+    for (number, community) in order.into_iter().enumerate() {
+        numbers[community] = number;
+    }
+    communities
+        .into_iter()
+        .map(|community| numbers[community])
+        .collect()
+}
+
+/// Split each community into the parts which are connected without leaving the community.
+pub(crate) fn split_disconnected(
+    adjacency: &[Vec<(usize, f64)>],
+    communities: &[usize],
+) -> Vec<usize> {
+    let mut parts = vec![usize::MAX; communities.len()];
+    let mut next = 0;
+    for start in 0..communities.len() {
+        if parts[start] != usize::MAX {
+            continue;
+        }
+        parts[start] = next;
+        let mut stack = vec![start];
+        while let Some(node) = stack.pop() {
+            for &(other, _) in &adjacency[node] {
+                if parts[other] == usize::MAX && communities[other] == communities[node] {
+                    parts[other] = next;
+                    stack.push(other);
+                }
+            }
+        }
+        next += 1;
+    }
+    parts
+}
+
+/// Renumber a new set of communities so that each keeps the number of the previous community it
+/// has the most members in common with, where possible.
+///
+/// This keeps community numbers, and so colours, stable when communities are recomputed as the
+/// graph changes. Communities which can't keep an old number are given the lowest unused numbers,
+/// largest first.
+#[must_use]
+pub fn match_previous<K: Hash + Eq + Clone>(
+    previous: &HashMap<K, usize>,
+    next: &HashMap<K, usize>,
+) -> HashMap<K, usize> {
+    let mut overlaps = HashMap::<(usize, usize), usize>::new();
+    let mut sizes = HashMap::<usize, usize>::new();
+    for (key, &community) in next {
+        *sizes.entry(community).or_default() += 1;
+        if let Some(&old) = previous.get(key) {
+            *overlaps.entry((community, old)).or_default() += 1;
+        }
+    }
+    let mut overlaps = overlaps.into_iter().collect::<Vec<_>>();
+    overlaps.sort_unstable_by_key(|&((community, old), overlap)| {
+        (std::cmp::Reverse(overlap), old, community)
+    });
+
+    let mut numbers = HashMap::new();
+    let mut used = hashbrown::HashSet::new();
+    for ((community, old), _) in overlaps {
+        if !numbers.contains_key(&community) && !used.contains(&old) {
+            numbers.insert(community, old);
+            used.insert(old);
+        }
+    }
+    let mut unmatched = sizes
+        .into_iter()
+        .filter(|(community, _)| !numbers.contains_key(community))
+        .collect::<Vec<_>>();
+    unmatched.sort_unstable_by_key(|&(community, size)| (std::cmp::Reverse(size), community));
+    let mut free = (0..).filter(|number| !used.contains(number));
+    for (community, _) in unmatched {
+        if let Some(number) = free.next() {
+            numbers.insert(community, number);
+        }
+    }
+
+    next.iter()
+        .map(|(key, community)| (key.clone(), numbers[community]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{match_previous, split_disconnected, CommunityDetection, Louvain};
+    use crate::graph::{Graph, HasKey};
+    use crate::{FrameSink, Result, System};
+    use hashbrown::HashMap;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    struct Node(u32);
+
+    impl HasKey for Node {
+        type Key = u32;
+
+        fn key(&self) -> u32 {
+            self.0
+        }
+    }
+
+    /// Two groups of four, tightly connected inside, with a weak link between them.
+    fn two_groups() -> Graph<Node, f32> {
+        let mut graph = Graph::new();
+        for i in 0..8 {
+            graph.add_node(Node(i));
+        }
+        for group in [0, 4] {
+            for a in group..group + 4 {
+                for b in a + 1..group + 4 {
+                    graph.set_weight(a, b, 100.);
+                }
+            }
+        }
+        graph.set_weight(3, 4, 10.);
+        graph
+    }
+
+    #[test]
+    fn finds_groups() {
+        let communities = Louvain::default().detect(&two_groups());
+        assert_eq!(communities.len(), 8);
+        for i in 1..4 {
+            assert_eq!(communities[&i], communities[&0]);
+            assert_eq!(communities[&(i + 4)], communities[&4]);
+        }
+        assert_ne!(communities[&0], communities[&4]);
+
+        // Without any edges, every node is alone.
+        let mut graph = Graph::<Node, f32>::new();
+        graph.add_node(Node(0));
+        graph.add_node(Node(1));
+        let communities = Louvain::default().detect(&graph);
+        assert_ne!(communities[&0], communities[&1]);
+    }
+
+    #[test]
+    fn ignores_self_loops() {
+        // Counting the loops would keep each node in a community of its own.
+        let mut graph = Graph::<Node, f32>::new();
+        graph.add_node(Node(0));
+        graph.add_node(Node(1));
+        graph.set_weight(0, 1, 1.);
+        graph.set_weight(0, 0, 10.);
+        graph.set_weight(1, 1, 10.);
+        let communities = Louvain::default().detect(&graph);
+        assert_eq!(communities[&0], communities[&1]);
+    }
+
+    #[test]
+    fn splits_disconnected_communities() {
+        // Nodes 0 and 1 are linked, and so are 2 and 3, but the two pairs are not, even though
+        // they were put in the same community.
+        let adjacency = vec![vec![(1, 1.)], vec![(0, 1.)], vec![(3, 1.)], vec![(2, 1.)]];
+        assert_eq!(
+            split_disconnected(&adjacency, &[0, 0, 0, 0]),
+            vec![0, 0, 1, 1]
+        );
+        assert_eq!(
+            split_disconnected(&adjacency, &[0, 1, 0, 0]),
+            vec![0, 1, 2, 2]
+        );
+    }
+
+    #[test]
+    fn keeps_previous_numbers() {
+        let previous = HashMap::from([(0, 0), (1, 0), (2, 1), (3, 1)]);
+        // The same groups, numbered the other way round, with a newcomer joining the second and
+        // a new group of its own.
+        let next = HashMap::from([(0, 1), (1, 1), (2, 0), (3, 0), (4, 0), (5, 2)]);
+        let matched = match_previous(&previous, &next);
+        assert_eq!(
+            matched,
+            HashMap::from([(0, 0), (1, 0), (2, 1), (3, 1), (4, 1), (5, 2)])
+        );
+    }
+
+    /// Records the colour every node is currently shown in.
+    struct Colours(Rc<RefCell<HashMap<u64, [u8; 3]>>>);
+
+    impl FrameSink for Colours {
+        fn node_added(&mut self, id: &u64, colour: [u8; 3]) -> Result<()> {
+            self.0.borrow_mut().insert(*id, colour);
+            Ok(())
+        }
+
+        fn node_recoloured(&mut self, id: &u64, colour: [u8; 3]) -> Result<()> {
+            self.0.borrow_mut().insert(*id, colour);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn system_colours_by_community() {
+        let colours = Rc::new(RefCell::new(HashMap::new()));
+        let mut system: System = System::with_seed(1);
+        system.add_sink(Colours(colours.clone())).unwrap();
+        for id in 0..4 {
+            system.add_node(id, [0, 0, 0]);
+        }
+        system.set_weight(0, 1, 100.);
+        system.set_weight(2, 3, 100.);
+
+        let palette = vec![[255, 0, 0], [0, 0, 255]];
+        system
+            .set_community_detection(CommunityDetection {
+                interval: Some(10),
+                palette: Some(palette.clone()),
+                ..CommunityDetection::default()
+            })
+            .unwrap();
+        let first = system.community(&0).unwrap();
+        assert_eq!(system.community(&1), Some(first));
+        assert_ne!(system.community(&2), Some(first));
+        assert_eq!(colours.borrow()[&0], palette[first]);
+        assert_eq!(colours.borrow()[&2], palette[1 - first]);
+
+        // Once node 1 is pulled towards the other pair, it is recoloured at the next interval.
+        system.set_weight(0, 1, 0.);
+        system.set_weight(1, 2, 100.);
+        system.set_weight(1, 3, 100.);
+        system.many_steps(10).unwrap();
+        assert_eq!(system.community(&1), system.community(&2));
+        assert_eq!(colours.borrow()[&1], colours.borrow()[&2]);
+        // The pair that didn't change keeps its number.
+        assert_eq!(system.community(&2), Some(1 - first));
+
+        // Turning colouring off restores every node's own colour.
+        system
+            .set_community_detection(CommunityDetection::default())
+            .unwrap();
+        assert!(colours.borrow().values().all(|colour| *colour == [0, 0, 0]));
+    }
+}
@@ -1,17 +1,50 @@
-use crate::{Vec2d, SIZE};
+//! Rendering the layout as raster images.
+//!
+//! Edges are drawn as lines beneath the nodes, thicker and more opaque the heavier they are, in
+//! an [`EdgeStyle`] which can be set with the `with_edge_style` method of each sink.
+//!
+//! With the `labels` feature, nodes can also be labelled with text, set with
+//! [`Drawing::set_label`] or the `with_labels` method of each sink. With the `avatars` feature,
+//! nodes added with an avatar are drawn with it in place of their colour.
+use crate::colour::BACKGROUND_COLOUR;
+#[cfg(feature = "png")]
+use crate::output::FrameOutputs;
+#[cfg(feature = "gif")]
+use crate::output::Output;
+#[cfg(any(feature = "png", feature = "gif"))]
+use crate::FrameSink;
+use crate::{Error, NodeKey, Result, Vec2d, SIZE};
 use forma_render::cpu::{
     buffer::{layout::LinearLayout, BufferBuilder, BufferLayerCache},
     Renderer, RGBA,
 };
 use forma_render::math::{AffineTransform, Point};
 use forma_render::styling::{Color, Fill, Func, Props, Style};
-use forma_render::{Composition, Path, PathBuilder};
+use forma_render::{Composition, Order, Path, PathBuilder};
+use hashbrown::HashMap;
+#[cfg(any(feature = "png", feature = "gif"))]
 use std::io::Write;
 
-pub use forma_render::Order;
+#[cfg(feature = "avatars")]
+mod avatars;
+mod edges;
+#[cfg(feature = "labels")]
+mod labels;
+
+pub use edges::EdgeStyle;
+#[cfg(feature = "labels")]
+pub use labels::{LabelStyle, Placement};
 
 const NODE_RADIUS: f32 = 10.;
-const BACKGROUND_COLOUR: [u8; 3] = [238, 232, 213];
+
+/// The order of the first node layer. The orders below it are used for edges, so that every edge
+/// is beneath every node.
+const NODE_ORDERS: u32 = 1 << 19;
+
+/// The order of the first label layer. Each node's label is drawn at this much above the node
+/// itself, so that every label is above every node.
+#[cfg(feature = "labels")]
+const LABEL_ORDERS: u32 = 1 << 20;
 
 fn node_path() -> Path {
     let weight = 2.0f32.sqrt() / 2.;
@@ -42,7 +75,11 @@ fn node_path() -> Path {
 
 fn colour_from_rgb(rgb: [u8; 3]) -> Color {
     let [r, g, b] = rgb;
-    let (r, g, b) = (r as f32 / 255., g as f32 / 255., b as f32 / 255.);
+    let (r, g, b) = (
+        f32::from(r) / 255.,
+        f32::from(g) / 255.,
+        f32::from(b) / 255.,
+    );
     // Convert sRGB to linear, as forma_render uses a linear colour space and it is cheaper to do
     // the conversion here than on each pixel value.
     let (r, g, b) = (r.powf(2.2), g.powf(2.2), b.powf(2.2));
@@ -59,25 +96,95 @@ fn solid_fill(rgb: [u8; 3]) -> Props {
     }
 }
 
+/// A raster canvas which draws each node as a circle, using `forma_render`.
+///
+/// Nodes are identified by the same IDs as in the [`System`](crate::System). After placing the
+/// nodes for a step, call [`render_frame`](Self::render_frame) to update the pixel buffer, which can
+/// then be encoded by one of the `frame_as_*` methods.
 #[derive(Debug)]
-pub struct Drawing {
+pub struct Drawing<K = u64> {
     composition: Composition,
     renderer: Renderer,
     cache: BufferLayerCache,
     buffer: Vec<u8>,
     bg_col: Color,
     next_order: u32,
+    nodes: HashMap<K, DrawnNode>,
+    edges: HashMap<(K, K), f32>,
+    edge_style: EdgeStyle,
+    // The number of edge layers drawn in the last frame, from order 1 upwards.
+    edge_layers: u32,
+    #[cfg(feature = "labels")]
+    label_style: LabelStyle,
 }
 
-impl Default for Drawing {
+#[derive(Clone, Debug)]
+struct DrawnNode {
+    order: Order,
+    size: f32,
+    #[cfg(feature = "avatars")]
+    avatar: Option<forma_render::styling::Image>,
+    // This is `None` until the node is first placed.
+    center: Option<Vec2d>,
+    #[cfg(feature = "labels")]
+    label: Option<DrawnLabel>,
+}
+
+#[cfg(feature = "labels")]
+#[derive(Clone, Debug)]
+struct DrawnLabel {
+    text: String,
+    metrics: labels::Metrics,
+}
+
+#[cfg(feature = "labels")]
+impl DrawnNode {
+    fn label_order(&self) -> Order {
+        // Node orders are below `LABEL_ORDERS`, and `Order::MAX` is far above twice that.
+        Order::new(LABEL_ORDERS + self.order.as_u32()).unwrap()
+    }
+
+    /// Where the node's label starts, and the box it covers, if it has a label and has been
+    /// placed.
+    fn label_bounds(&self, placement: Placement) -> Option<(Vec2d, (Vec2d, Vec2d))> {
+        let label = self.label.as_ref()?;
+        let start = self.center? + label.metrics.offset(placement, NODE_RADIUS * self.size);
+        Some((start, label.metrics.bounds(start)))
+    }
+
+    /// Move the node's label next to it.
+    fn place_label(&self, composition: &mut Composition, placement: Placement) {
+        if let (Some((start, _)), Some(layer)) = (
+            self.label_bounds(placement),
+            composition.get_mut(self.label_order()),
+        ) {
+            let transform = AffineTransform {
+                tx: start.x,
+                ty: start.y,
+                ..AffineTransform::default()
+            };
+            if let Ok(transform) = transform.try_into() {
+                layer.set_transform(transform);
+            }
+        }
+    }
+}
+
+impl<K: NodeKey> Default for Drawing<K> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl Drawing {
+impl<K: NodeKey> Drawing<K> {
+    /// Create an empty canvas.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the renderer cannot allocate a layer cache.
+    #[must_use]
     pub fn new() -> Self {
-        let mut composition = Composition::new();
+        let composition = Composition::new();
         /*
         let mut background = composition.create_layer();
         let mut bg_path = PathBuilder::new();
@@ -101,46 +208,287 @@ impl Drawing {
             cache,
             buffer,
             bg_col,
-            next_order: 1,
+            next_order: NODE_ORDERS,
+            nodes: HashMap::new(),
+            edges: HashMap::new(),
+            edge_style: EdgeStyle::default(),
+            edge_layers: 0,
+            #[cfg(feature = "labels")]
+            label_style: LabelStyle::default(),
         }
     }
 
-    pub fn add_node(&mut self, colour: [u8; 3]) -> Order {
+    /// Add a node, drawn in the given colour above every existing node.
+    ///
+    /// # Panics
+    ///
+    /// Panics if more than half a million nodes have been added to this drawing.
+    pub fn add_node(&mut self, id: K, colour: [u8; 3]) {
         let mut layer = self.composition.create_layer();
         layer.insert(&node_path());
         layer.set_props(solid_fill(colour));
         let order = Order::new(self.next_order).unwrap();
         self.next_order += 1;
         self.composition.insert(order, layer);
-        order
+        self.nodes.insert(
+            id,
+            DrawnNode {
+                order,
+                size: 1.,
+                #[cfg(feature = "avatars")]
+                avatar: None,
+                center: None,
+                #[cfg(feature = "labels")]
+                label: None,
+            },
+        );
+    }
+
+    /// Draw a node in a new colour. This has no visible effect on a node with an avatar.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NodeNotFound`] if the node does not exist.
+    pub fn recolour_node(&mut self, id: &K, colour: [u8; 3]) -> Result<()> {
+        let node = self
+            .nodes
+            .get(id)
+            .ok_or_else(|| Error::node_not_found(id))?;
+        #[cfg(feature = "avatars")]
+        if node.avatar.is_some() {
+            return Ok(());
+        }
+        self.composition
+            .get_mut(node.order)
+            .ok_or_else(|| Error::node_not_found(id))?
+            .set_props(solid_fill(colour));
+        Ok(())
+    }
+
+    /// Draw a node with an avatar, given as the contents of a PNG file, in place of its colour.
+    /// The largest centred square of the image fills the node's circle. This takes effect the
+    /// next time it is placed.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NodeNotFound`] if the node does not exist, or [`Error::Avatar`] if the
+    /// image cannot be decoded.
+    #[cfg(feature = "avatars")]
+    pub fn set_avatar(&mut self, id: &K, png: &[u8]) -> Result<()> {
+        let node = self
+            .nodes
+            .get_mut(id)
+            .ok_or_else(|| Error::node_not_found(id))?;
+        node.avatar = Some(avatars::decode(png)?);
+        Ok(())
+    }
+
+    /// Remove a node and its edges from the canvas. Nothing happens if the node does not exist.
+    pub fn remove_node(&mut self, id: &K) {
+        if let Some(node) = self.nodes.remove(id) {
+            self.composition.remove(node.order);
+            #[cfg(feature = "labels")]
+            self.composition.remove(node.label_order());
+            self.edges.retain(|(from, to), _| from != id && to != id);
+        }
     }
 
-    pub fn place_node(&mut self, order: Order, center: Vec2d) {
-        let layer = self.composition.get_mut(order).unwrap();
+    /// Set the weight of the edge between two nodes. Edges are drawn in the current
+    /// [`EdgeStyle`], from the positions the nodes have when the frame is rendered.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NodeNotFound`] if either node does not exist.
+    pub fn set_weight(&mut self, from: &K, to: &K, weight: f32) -> Result<()> {
+        for id in [from, to] {
+            if !self.nodes.contains_key(id) {
+                return Err(Error::node_not_found(id));
+            }
+        }
+        // Edges are undirected, so the weight may have been set either way round before.
+        self.edges.remove(&(to.clone(), from.clone()));
+        if weight == 0. {
+            self.edges.remove(&(from.clone(), to.clone()));
+        } else {
+            self.edges.insert((from.clone(), to.clone()), weight);
+        }
+        Ok(())
+    }
+
+    /// Change how edges are drawn, from the next frame on.
+    pub const fn set_edge_style(&mut self, style: EdgeStyle) {
+        self.edge_style = style;
+    }
+
+    /// Draw a node at a new size, as a multiple of the default radius. This takes effect the next
+    /// time it is placed.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NodeNotFound`] if the node does not exist.
+    pub fn resize_node(&mut self, id: &K, size: f32) -> Result<()> {
+        self.nodes
+            .get_mut(id)
+            .ok_or_else(|| Error::node_not_found(id))?
+            .size = size;
+        Ok(())
+    }
+
+    /// Move a node so that it is centered on the given point.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NodeNotFound`] if the node does not exist.
+    pub fn place_node(&mut self, id: &K, center: Vec2d) -> Result<()> {
+        let node = self
+            .nodes
+            .get_mut(id)
+            .ok_or_else(|| Error::node_not_found(id))?;
+        node.center = Some(center);
+        #[cfg(feature = "labels")]
+        node.place_label(&mut self.composition, self.label_style.placement);
+        let size = node.size;
+        let Some(layer) = self.composition.get_mut(node.order) else {
+            return Err(Error::node_not_found(id));
+        };
+        // The texture is in screen space, so it has to be moved along with the node.
+        #[cfg(feature = "avatars")]
+        if let Some(avatar) = &node.avatar {
+            layer.set_props(avatars::fill(avatar, center, NODE_RADIUS * size));
+        }
         let transform = AffineTransform {
-            // x' = x * 1 + y * 0 + 1 * translate_x
-            ux: 1.,
+            // x' = x * size + y * 0 + 1 * translate_x
+            ux: size,
             vx: 0.,
             tx: center.x,
-            // y' = x * 0 + y * 1 + 1 * translate_y
+            // y' = x * 0 + y * size + 1 * translate_y
             uy: 0.,
-            vy: 1.,
+            vy: size,
             ty: center.y,
         };
-        layer.set_transform(transform.try_into().unwrap());
+        // A uniform scale and translation is always geometry preserving, so this conversion can't
+        // fail.
+        if let Ok(transform) = transform.try_into() {
+            layer.set_transform(transform);
+        }
+        Ok(())
     }
 
-    pub fn hide_node(&mut self, order: Order) {
-        let layer = self.composition.get_mut(order).unwrap();
-        layer.disable();
+    /// Label a node with a line of text, drawn next to it in the current [`LabelStyle`], or remove
+    /// its label.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NodeNotFound`] if the node does not exist.
+    #[cfg(feature = "labels")]
+    pub fn set_label(&mut self, id: &K, label: Option<String>) -> Result<()> {
+        let node = self
+            .nodes
+            .get_mut(id)
+            .ok_or_else(|| Error::node_not_found(id))?;
+        let order = node.label_order();
+        self.composition.remove(order);
+        node.label = label.filter(|text| !text.is_empty()).map(|text| {
+            let (path, metrics) = labels::outline(&text, self.label_style.size);
+            let mut layer = self.composition.create_layer();
+            layer.insert(&path);
+            layer.set_props(solid_fill(self.label_style.colour));
+            self.composition.insert(order, layer);
+            DrawnLabel { text, metrics }
+        });
+        node.place_label(&mut self.composition, self.label_style.placement);
+        Ok(())
     }
 
-    pub fn show_node(&mut self, order: Order) {
-        let layer = self.composition.get_mut(order).unwrap();
-        layer.enable();
+    /// Change how labels are drawn, redrawing any existing labels.
+    #[cfg(feature = "labels")]
+    pub fn set_label_style(&mut self, style: LabelStyle) {
+        self.label_style = style;
+        let labelled: Vec<_> = self
+            .nodes
+            .iter_mut()
+            .filter_map(|(id, node)| Some((id.clone(), node.label.take()?.text)))
+            .collect();
+        for (id, text) in labelled {
+            // The node was found above.
+            let _ = self.set_label(&id, Some(text));
+        }
     }
 
+    /// Show every label that fits, hiding any which would overlap a label already shown. Larger
+    /// nodes are labelled first, followed by those added earlier.
+    #[cfg(feature = "labels")]
+    fn hide_overlapping_labels(&mut self) {
+        let placement = self.label_style.placement;
+        let mut labelled: Vec<_> = self
+            .nodes
+            .values()
+            .filter(|node| node.label.is_some())
+            .collect();
+        labelled.sort_by(|a, b| {
+            b.size
+                .total_cmp(&a.size)
+                .then(a.order.as_u32().cmp(&b.order.as_u32()))
+        });
+        let mut shown = Vec::new();
+        for node in labelled {
+            let bounds = node.label_bounds(placement).map(|(_, bounds)| bounds);
+            let visible = bounds.is_some_and(|bounds| {
+                !self.label_style.hide_overlapping
+                    || !shown.iter().any(|&other| labels::overlap(bounds, other))
+            });
+            if visible {
+                shown.extend(bounds);
+            }
+            if let Some(layer) = self.composition.get_mut(node.label_order()) {
+                layer.set_is_enabled(visible);
+            }
+        }
+    }
+
+    /// Redraw every edge which is shown between the current positions of its nodes. If there are
+    /// too many edges to fit beneath the nodes, the lightest are left out.
+    fn draw_edges(&mut self) {
+        let style = self.edge_style;
+        let mut shown: Vec<_> = self
+            .edges
+            .iter()
+            .filter(|&(_, &weight)| style.shows(weight))
+            .filter_map(|((from, to), &weight)| {
+                Some((
+                    self.nodes.get(from)?.center?,
+                    self.nodes.get(to)?.center?,
+                    weight,
+                ))
+            })
+            .collect();
+        shown.sort_by(|(_, _, a), (_, _, b)| b.total_cmp(a));
+        shown.truncate(NODE_ORDERS as usize - 1);
+        let max_weight = shown.first().map_or(0., |&(_, _, weight)| weight);
+        // Draw the lightest edges first, so that heavier edges are drawn over them.
+        let mut order = 1;
+        for &(from, to, weight) in shown.iter().rev() {
+            let strength = weight / max_weight;
+            let layer = self
+                .composition
+                .get_mut_or_insert_default(Order::new(order).unwrap());
+            layer
+                .clear()
+                .insert(&edges::line(from, to, style.max_width * strength))
+                .set_props(style.fill(strength));
+            order += 1;
+        }
+        for unused in order..=self.edge_layers {
+            self.composition.remove(Order::new(unused).unwrap());
+        }
+        self.edge_layers = order - 1;
+    }
+
+    /// Render the current position of every node and edge to the pixel buffer.
     pub fn render_frame(&mut self) {
+        self.draw_edges();
+        #[cfg(feature = "labels")]
+        self.hide_overlapping_labels();
         let size = SIZE as usize;
         self.renderer.render(
             &mut self.composition,
@@ -156,19 +504,214 @@ impl Drawing {
         );
     }
 
+    /// Encode the last rendered frame as a PNG.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the PNG cannot be encoded or written.
     #[cfg(feature = "png")]
-    pub fn frame_as_png(&self, w: impl Write) {
+    pub fn frame_as_png(&self, w: impl Write) -> Result<()> {
         let size = SIZE as u32;
         let mut encoder = png::Encoder::new(w, size, size);
         encoder.set_color(png::ColorType::Rgba);
         encoder.set_depth(png::BitDepth::Eight);
-        let mut writer = encoder.write_header().unwrap();
-        writer.write_image_data(&self.buffer).unwrap();
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.buffer)?;
+        Ok(())
     }
 
+    /// Convert the last rendered frame to a GIF frame.
     #[cfg(feature = "gif")]
-    pub fn frame_as_gif(&mut self) -> gif::Frame {
+    #[must_use]
+    pub fn frame_as_gif(&mut self) -> gif::Frame<'_> {
         let size = SIZE as u16;
         gif::Frame::from_rgba_speed(size, size, &mut self.buffer, 20)
     }
 }
+
+#[cfg(all(feature = "labels", any(feature = "png", feature = "gif")))]
+type Labeller<K> = Box<dyn Fn(&K) -> Option<String>>;
+
+/// Implements the `with_labels` and `with_label_style` builder methods for a type which wraps a
+/// `Drawing` in a field called `drawing`, and has a `labels` field.
+#[cfg(all(feature = "labels", any(feature = "png", feature = "gif")))]
+macro_rules! label_builders {
+    () => {
+        /// Label each node with the text returned for it by the given function, when it is
+        /// added. Nodes for which it returns `None` are not labelled.
+        #[must_use]
+        pub fn with_labels(mut self, labels: impl Fn(&K) -> Option<String> + 'static) -> Self {
+            self.labels = Some(Box::new(labels));
+            self
+        }
+
+        /// Draw labels in the given style, rather than the default.
+        #[must_use]
+        pub fn with_label_style(mut self, style: LabelStyle) -> Self {
+            self.drawing.set_label_style(style);
+            self
+        }
+    };
+}
+
+/// Implements the `with_edge_style` builder method for a type which wraps a `Drawing` in a field
+/// called `drawing`.
+#[cfg(any(feature = "png", feature = "gif"))]
+macro_rules! edge_builders {
+    () => {
+        /// Draw edges in the given style, rather than the default.
+        #[must_use]
+        pub const fn with_edge_style(mut self, style: EdgeStyle) -> Self {
+            self.drawing.set_edge_style(style);
+            self
+        }
+    };
+}
+
+/// Implements the node- and edge-related `FrameSink` events for a type which wraps a `Drawing` in
+/// a field called `drawing`, leaving `step_finished` and `finish` to the type itself.
+#[cfg(any(feature = "png", feature = "gif"))]
+macro_rules! delegate_nodes_to_drawing {
+    () => {
+        fn node_added(&mut self, id: &K, colour: [u8; 3]) -> Result<()> {
+            self.drawing.add_node(id.clone(), colour);
+            #[cfg(feature = "labels")]
+            if let Some(labels) = &self.labels {
+                self.drawing.set_label(id, labels(id))?;
+            }
+            Ok(())
+        }
+
+        #[cfg(feature = "avatars")]
+        fn avatar_set(&mut self, id: &K, png: &[u8]) -> Result<()> {
+            self.drawing.set_avatar(id, png)
+        }
+
+        fn node_recoloured(&mut self, id: &K, colour: [u8; 3]) -> Result<()> {
+            self.drawing.recolour_node(id, colour)
+        }
+
+        fn node_resized(&mut self, id: &K, size: f32) -> Result<()> {
+            self.drawing.resize_node(id, size)
+        }
+
+        fn node_removed(&mut self, id: &K) -> Result<()> {
+            self.drawing.remove_node(id);
+            Ok(())
+        }
+
+        fn weight_changed(&mut self, from: &K, to: &K, weight: f32) -> Result<()> {
+            self.drawing.set_weight(from, to, weight)
+        }
+
+        fn node_moved(&mut self, id: &K, pos: Vec2d) -> Result<()> {
+            self.drawing.place_node(id, pos)
+        }
+    };
+}
+
+/// A sink that renders every step to a separate PNG file.
+#[cfg(feature = "png")]
+pub struct PngFrames<K = u64> {
+    drawing: Drawing<K>,
+    outputs: FrameOutputs,
+    #[cfg(feature = "labels")]
+    labels: Option<Labeller<K>>,
+}
+
+#[cfg(feature = "png")]
+impl<K: NodeKey> std::fmt::Debug for PngFrames<K> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PngFrames")
+            .field("drawing", &self.drawing)
+            .field("outputs", &self.outputs)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "png")]
+impl<K: NodeKey> PngFrames<K> {
+    /// Create a sink writing each frame to the given destination, for example
+    /// `PngFrames::new("frames/frame%04d.png")`.
+    pub fn new(outputs: impl Into<FrameOutputs>) -> Self {
+        Self {
+            drawing: Drawing::new(),
+            outputs: outputs.into(),
+            #[cfg(feature = "labels")]
+            labels: None,
+        }
+    }
+
+    edge_builders!();
+
+    #[cfg(feature = "labels")]
+    label_builders!();
+}
+
+#[cfg(feature = "png")]
+impl<K: NodeKey> FrameSink<K> for PngFrames<K> {
+    delegate_nodes_to_drawing!();
+
+    fn step_finished(&mut self, step: u64) -> Result<()> {
+        self.drawing.render_frame();
+        let mut writer = self.outputs.open(step)?;
+        self.drawing.frame_as_png(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// A sink that renders every step as a frame of an animated GIF.
+#[cfg(feature = "gif")]
+pub struct GifAnimation<K = u64> {
+    drawing: Drawing<K>,
+    // This is only `None` once the animation has been finished.
+    encoder: Option<gif::Encoder<Box<dyn Write>>>,
+    #[cfg(feature = "labels")]
+    labels: Option<Labeller<K>>,
+}
+
+#[cfg(feature = "gif")]
+impl<K: NodeKey> GifAnimation<K> {
+    /// Create a sink writing to the given destination.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the destination cannot be opened, or the GIF header cannot be written.
+    pub fn new(output: impl Into<Output>) -> Result<Self> {
+        let encoder = gif::Encoder::new(output.into().open()?, SIZE as u16, SIZE as u16, &[])?;
+        Ok(Self {
+            drawing: Drawing::new(),
+            encoder: Some(encoder),
+            #[cfg(feature = "labels")]
+            labels: None,
+        })
+    }
+
+    edge_builders!();
+
+    #[cfg(feature = "labels")]
+    label_builders!();
+}
+
+#[cfg(feature = "gif")]
+impl<K: NodeKey> FrameSink<K> for GifAnimation<K> {
+    delegate_nodes_to_drawing!();
+
+    fn step_finished(&mut self, _step: u64) -> Result<()> {
+        self.drawing.render_frame();
+        let mut frame = self.drawing.frame_as_gif();
+        frame.delay = 2;
+        if let Some(encoder) = &mut self.encoder {
+            encoder.write_frame(&frame)?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if let Some(encoder) = self.encoder.take() {
+            encoder.into_inner()?.flush()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,125 @@
+//! Avatar images drawn inside nodes, as a texture filling the node's circle.
+use crate::{Error, Result, Vec2d};
+use forma_render::math::AffineTransform;
+use forma_render::styling::{Fill, Func, Image, Props, Style, Texture};
+use png::{ColorType, Decoder, Transformations};
+
+/// Decode a PNG file into an image which can be used as a texture.
+pub fn decode(png: &[u8]) -> Result<Image> {
+    let mut decoder = Decoder::new(png);
+    // Expand palettes and low bit depths, and strip 16 bit channels to 8 bits, so that only the
+    // number of channels varies.
+    decoder.set_transformations(Transformations::normalize_to_color8());
+    let mut reader = decoder
+        .read_info()
+        .map_err(|_| Error::Avatar("invalid PNG header"))?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader
+        .next_frame(&mut buffer)
+        .map_err(|_| Error::Avatar("invalid PNG image data"))?;
+    let buffer = &buffer[..info.buffer_size()];
+    let pixels: Vec<[u8; 4]> = match info.color_type {
+        ColorType::Rgba => buffer
+            .chunks_exact(4)
+            .map(|p| [p[0], p[1], p[2], p[3]])
+            .collect(),
+        ColorType::Rgb => buffer
+            .chunks_exact(3)
+            .map(|p| [p[0], p[1], p[2], u8::MAX])
+            .collect(),
+        ColorType::GrayscaleAlpha => buffer
+            .chunks_exact(2)
+            .map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        ColorType::Grayscale => buffer.iter().map(|&v| [v, v, v, u8::MAX]).collect(),
+        ColorType::Indexed => return Err(Error::Avatar("unsupported PNG colour type")),
+    };
+    Image::from_srgba(&pixels, info.width as usize, info.height as usize)
+        .map_err(|_| Error::Avatar("image too large"))
+}
+
+/// Fill a node centred at `center` with the given radius with an image. The largest centred
+/// square of the image is scaled to fit the node.
+pub fn fill(image: &Image, center: Vec2d, radius: f32) -> Props {
+    let (width, height) = (image.width() as f32, image.height() as f32);
+    let side = width.min(height);
+    // The number of image pixels per screen pixel.
+    let scale = side / (2. * radius);
+    let transform = AffineTransform {
+        ux: scale,
+        vx: 0.,
+        tx: (radius - center.x).mul_add(scale, (width - side) / 2.),
+        uy: 0.,
+        vy: scale,
+        ty: (radius - center.y).mul_add(scale, (height - side) / 2.),
+    };
+    Props {
+        func: Func::Draw(Style {
+            fill: Fill::Texture(Texture {
+                transform,
+                image: image.clone(),
+            }),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::decode;
+    use crate::draw::Drawing;
+    use crate::{Error, Vec2d, SIZE};
+
+    /// Encode a PNG which is red on the left and blue on the right.
+    fn two_tone(width: u32, height: u32) -> Vec<u8> {
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, width, height);
+        encoder.set_color(png::ColorType::Rgb);
+        let data: Vec<u8> = (0..height)
+            .flat_map(|_| 0..width)
+            .flat_map(|x| {
+                if x < width / 2 {
+                    [255, 0, 0]
+                } else {
+                    [0, 0, 255]
+                }
+            })
+            .collect();
+        encoder
+            .write_header()
+            .unwrap()
+            .write_image_data(&data)
+            .unwrap();
+        png
+    }
+
+    fn pixel(drawing: &Drawing<u32>, x: usize, y: usize) -> [u8; 3] {
+        let index = (y * SIZE as usize + x) * 4;
+        let pixel = &drawing.buffer[index..index + 3];
+        [pixel[0], pixel[1], pixel[2]]
+    }
+
+    #[test]
+    fn decodes_png() {
+        let image = decode(&two_tone(4, 2)).unwrap();
+        assert_eq!((image.width(), image.height()), (4, 2));
+        assert!(matches!(decode(b"not a png"), Err(Error::Avatar(_))));
+    }
+
+    #[test]
+    fn draws_avatar_in_node() {
+        let mut drawing = Drawing::new();
+        drawing.add_node(1, [0, 255, 0]);
+        drawing.set_avatar(&1, &two_tone(64, 32)).unwrap();
+        drawing.place_node(&1, Vec2d::new(100., 100.)).unwrap();
+        // Recolouring a node with an avatar has no visible effect.
+        drawing.recolour_node(&1, [0, 0, 0]).unwrap();
+        drawing.render_frame();
+        assert_eq!(pixel(&drawing, 93, 100), [255, 0, 0]);
+        assert_eq!(pixel(&drawing, 107, 100), [0, 0, 255]);
+        // The corners of the image are clipped to the circle.
+        assert!(pixel(&drawing, 92, 92).iter().all(|&channel| channel > 200));
+        assert!(drawing.set_avatar(&2, &two_tone(1, 1)).is_err());
+    }
+}
@@ -0,0 +1,133 @@
+//! Edges drawn as straight lines between nodes, beneath every node.
+use super::colour_from_rgb;
+use crate::colour::EDGE_COLOUR;
+use crate::Vec2d;
+use forma_render::math::Point;
+use forma_render::styling::{Color, Fill, Func, Props, Style};
+use forma_render::{Path, PathBuilder};
+
+/// How edges are drawn.
+///
+/// The heaviest edge drawn in each frame is drawn at `max_width` and `max_opacity`, and every
+/// other edge at a width and opacity in proportion to its weight.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EdgeStyle {
+    pub colour: [u8; 3],
+    /// Edges with a lower weight than this are not drawn. Edges with a weight of 0 or less are
+    /// never drawn.
+    pub min_weight: f32,
+    /// The width of the heaviest edge, in pixels.
+    pub max_width: f32,
+    /// The opacity of the heaviest edge, from 0 to 1.
+    pub max_opacity: f32,
+}
+
+impl Default for EdgeStyle {
+    fn default() -> Self {
+        Self {
+            colour: EDGE_COLOUR,
+            min_weight: 0.,
+            max_width: 4.,
+            max_opacity: 0.8,
+        }
+    }
+}
+
+impl EdgeStyle {
+    /// Whether an edge of the given weight is drawn.
+    #[must_use]
+    pub fn shows(&self, weight: f32) -> bool {
+        weight > 0. && weight >= self.min_weight
+    }
+
+    /// The fill for an edge whose weight is `strength` times that of the heaviest edge.
+    #[must_use]
+    pub fn fill(&self, strength: f32) -> Props {
+        Props {
+            func: Func::Draw(Style {
+                fill: Fill::Solid(Color {
+                    a: self.max_opacity * strength,
+                    ..colour_from_rgb(self.colour)
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+/// Build a straight line of the given width between two points, as a thin rectangle.
+pub fn line(from: Vec2d, to: Vec2d, width: f32) -> Path {
+    let along = to - from;
+    let length = along.length();
+    // Offset each end by half the width, at right angles to the line.
+    let across = if length > 0. {
+        Vec2d::new(-along.y, along.x) * (width / 2. / length)
+    } else {
+        Vec2d::new(0., 0.)
+    };
+    let point = |pos: Vec2d| Point::new(pos.x, pos.y);
+    let mut builder = PathBuilder::new();
+    builder.move_to(point(from + across));
+    builder.line_to(point(to + across));
+    builder.line_to(point(to - across));
+    builder.line_to(point(from - across));
+    builder.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EdgeStyle;
+    use crate::draw::Drawing;
+    use crate::{Vec2d, SIZE};
+
+    fn pixel(drawing: &Drawing<u32>, x: usize, y: usize) -> [u8; 3] {
+        let index = (y * SIZE as usize + x) * 4;
+        let pixel = &drawing.buffer[index..index + 3];
+        [pixel[0], pixel[1], pixel[2]]
+    }
+
+    /// Whether a pixel is close to the background colour.
+    fn blank(drawing: &Drawing<u32>, x: usize, y: usize) -> bool {
+        pixel(drawing, x, y).iter().all(|&channel| channel > 200)
+    }
+
+    #[test]
+    fn draws_edges_beneath_nodes() {
+        let mut drawing = Drawing::new();
+        drawing.set_edge_style(EdgeStyle {
+            colour: [0, 0, 0],
+            min_weight: 2.,
+            max_width: 6.,
+            max_opacity: 1.,
+        });
+        drawing.add_node(1, [255, 0, 0]);
+        drawing.add_node(2, [0, 0, 255]);
+        drawing.add_node(3, [0, 255, 0]);
+        drawing.set_weight(&1, &2, 10.).unwrap();
+        drawing.set_weight(&3, &1, 1.).unwrap();
+        drawing.place_node(&1, Vec2d::new(100., 100.)).unwrap();
+        drawing.place_node(&2, Vec2d::new(300., 100.)).unwrap();
+        drawing.place_node(&3, Vec2d::new(100., 300.)).unwrap();
+        drawing.render_frame();
+        assert_eq!(pixel(&drawing, 200, 100), [0, 0, 0]);
+        assert_eq!(pixel(&drawing, 200, 102), [0, 0, 0]);
+        assert!(blank(&drawing, 200, 105));
+        // Nodes are drawn over edges.
+        assert_eq!(pixel(&drawing, 105, 100), [255, 0, 0]);
+        // The edge to node 3 is below the threshold.
+        assert!(blank(&drawing, 100, 200));
+
+        // Edges follow their nodes, and go with them when they are removed.
+        drawing.set_weight(&3, &1, 5.).unwrap();
+        drawing.place_node(&2, Vec2d::new(300., 300.)).unwrap();
+        drawing.render_frame();
+        assert_eq!(pixel(&drawing, 200, 200), [0, 0, 0]);
+        assert!(!blank(&drawing, 100, 200));
+        assert!(blank(&drawing, 200, 100));
+        drawing.remove_node(&1);
+        drawing.render_frame();
+        assert!(blank(&drawing, 200, 200));
+        assert!(drawing.set_weight(&1, &2, 1.).is_err());
+    }
+}
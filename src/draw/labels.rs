@@ -0,0 +1,212 @@
+//! Text labels drawn next to nodes, using the glyph outlines of an embedded copy of
+//! [DejaVu Sans](https://dejavu-fonts.github.io/).
+use crate::colour::LABEL_COLOUR;
+use crate::Vec2d;
+use forma_render::math::Point;
+use forma_render::{Path, PathBuilder};
+use ttf_parser::{Face, GlyphId, OutlineBuilder};
+
+/// The font every label is drawn in. Its licence is in `fonts/LICENSE`.
+static FONT: &[u8] = include_bytes!("../../fonts/DejaVuSans.ttf");
+
+/// The space left between the edge of a node and its label.
+const GAP: f32 = 4.;
+
+/// Which side of its node a label is drawn on.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Placement {
+    /// To the right of the node, centred vertically.
+    #[default]
+    Right,
+    /// To the left of the node, centred vertically.
+    Left,
+    /// Above the node, centred horizontally.
+    Above,
+    /// Below the node, centred horizontally.
+    Below,
+}
+
+/// How node labels are drawn.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LabelStyle {
+    /// The font size, in pixels.
+    pub size: f32,
+    pub colour: [u8; 3],
+    pub placement: Placement,
+    /// Whether to hide a label if it would overlap the label of a larger node, or of one added
+    /// earlier.
+    pub hide_overlapping: bool,
+}
+
+impl Default for LabelStyle {
+    fn default() -> Self {
+        Self {
+            size: 12.,
+            colour: LABEL_COLOUR,
+            placement: Placement::Right,
+            hide_overlapping: true,
+        }
+    }
+}
+
+/// Collects glyph outlines into a path, scaling them from font units to pixels and flipping them
+/// so that y points down.
+struct Outline {
+    builder: PathBuilder,
+    scale: f32,
+    // Where the current glyph starts along the baseline, in pixels.
+    x: f32,
+}
+
+impl Outline {
+    fn point(&self, x: f32, y: f32) -> Point {
+        Point::new(x.mul_add(self.scale, self.x), -y * self.scale)
+    }
+}
+
+impl OutlineBuilder for Outline {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.builder.move_to(self.point(x, y));
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.builder.line_to(self.point(x, y));
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        self.builder.quad_to(self.point(x1, y1), self.point(x, y));
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        self.builder
+            .cubic_to(self.point(x1, y1), self.point(x2, y2), self.point(x, y));
+    }
+
+    // Each contour is closed when the next one is started, or the path is built.
+    fn close(&mut self) {}
+}
+
+/// The extent of a line of text, in pixels.
+#[derive(Clone, Copy, Debug)]
+pub struct Metrics {
+    width: f32,
+    ascent: f32,
+    descent: f32,
+}
+
+impl Metrics {
+    /// Where the baseline of the text starts, relative to the centre of a node with the given
+    /// radius.
+    pub fn offset(self, placement: Placement, radius: f32) -> Vec2d {
+        let height = self.ascent + self.descent;
+        let (left, top) = match placement {
+            Placement::Right => (radius + GAP, -height / 2.),
+            Placement::Left => (-radius - GAP - self.width, -height / 2.),
+            Placement::Above => (-self.width / 2., -radius - GAP - height),
+            Placement::Below => (-self.width / 2., radius + GAP),
+        };
+        Vec2d::new(left, top + self.ascent)
+    }
+
+    /// The box covered by the text, as its top left and bottom right corners, when its baseline
+    /// starts at the given point.
+    pub fn bounds(self, start: Vec2d) -> (Vec2d, Vec2d) {
+        (
+            Vec2d::new(start.x, start.y - self.ascent),
+            Vec2d::new(start.x + self.width, start.y + self.descent),
+        )
+    }
+}
+
+/// Whether two boxes given by [`Metrics::bounds`] overlap.
+pub fn overlap((min_a, max_a): (Vec2d, Vec2d), (min_b, max_b): (Vec2d, Vec2d)) -> bool {
+    min_a.x < max_b.x && min_b.x < max_a.x && min_a.y < max_b.y && min_b.y < max_a.y
+}
+
+/// Lay out a line of text with its baseline starting at the origin, returning its outline.
+///
+/// Characters the font lacks are drawn as its replacement glyph. There is no kerning.
+pub fn outline(text: &str, size: f32) -> (Path, Metrics) {
+    let Ok(face) = Face::parse(FONT, 0) else {
+        unreachable!("the embedded font is valid")
+    };
+    let scale = size / f32::from(face.units_per_em());
+    let mut outline = Outline {
+        builder: PathBuilder::new(),
+        scale,
+        x: 0.,
+    };
+    for c in text.chars() {
+        let glyph = face.glyph_index(c).unwrap_or(GlyphId(0));
+        face.outline_glyph(glyph, &mut outline);
+        outline.x += f32::from(face.glyph_hor_advance(glyph).unwrap_or(0)) * scale;
+    }
+    let metrics = Metrics {
+        width: outline.x,
+        ascent: f32::from(face.ascender()) * scale,
+        descent: -f32::from(face.descender()) * scale,
+    };
+    (outline.builder.build(), metrics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{outline, LabelStyle, Placement};
+    use crate::draw::Drawing;
+    use crate::{Vec2d, SIZE};
+
+    /// Whether any pixel in the given rectangle differs from the background.
+    fn drawn(drawing: &Drawing<u32>, (x0, y0): (usize, usize), (x1, y1): (usize, usize)) -> bool {
+        let size = SIZE as usize;
+        (y0..y1).any(|y| {
+            (x0..x1).any(|x| drawing.buffer[(y * size + x) * 4..][..3] != drawing.buffer[..3])
+        })
+    }
+
+    #[test]
+    fn lays_out_text() {
+        let (_, short) = outline("ab", 12.);
+        let (_, long) = outline("abab", 12.);
+        assert!(short.width > 0.);
+        assert!((long.width / short.width - 2.).abs() < 1e-3);
+        let (_, large) = outline("ab", 24.);
+        assert!((large.ascent / short.ascent - 2.).abs() < 1e-3);
+
+        let right = short.offset(Placement::Right, 10.);
+        let left = short.offset(Placement::Left, 10.);
+        assert!(right.x > 10.);
+        assert!((left.x + short.width + 10. + super::GAP).abs() < 1e-3);
+        assert!((right.y - left.y).abs() < 1e-6);
+        let (min, max) = short.bounds(short.offset(Placement::Above, 10.));
+        assert!(max.y < -10. && min.x < 0. && max.x > 0.);
+    }
+
+    #[test]
+    fn draws_and_hides_labels() {
+        let mut drawing = Drawing::new();
+        drawing.add_node(1, [255, 0, 0]);
+        drawing.add_node(2, [0, 0, 255]);
+        drawing.add_node(3, [0, 255, 0]);
+        drawing.place_node(&1, Vec2d::new(100., 100.)).unwrap();
+        drawing.place_node(&2, Vec2d::new(100., 112.)).unwrap();
+        drawing.place_node(&3, Vec2d::new(100., 300.)).unwrap();
+        drawing.set_label(&1, Some("one".to_string())).unwrap();
+        drawing.set_label(&2, Some("two".to_string())).unwrap();
+        drawing.set_label(&3, Some("three".to_string())).unwrap();
+        drawing.render_frame();
+        assert!(drawn(&drawing, (115, 90), (160, 110)));
+        assert!(drawn(&drawing, (115, 290), (160, 310)));
+        // The second label overlaps the first, so is hidden.
+        assert!(!drawn(&drawing, (115, 110), (160, 120)));
+
+        drawing.set_label(&1, None).unwrap();
+        drawing.set_label_style(LabelStyle {
+            placement: Placement::Left,
+            ..LabelStyle::default()
+        });
+        drawing.render_frame();
+        assert!(!drawn(&drawing, (115, 90), (160, 120)));
+        assert!(drawn(&drawing, (40, 105), (85, 120)));
+        assert!(drawn(&drawing, (40, 290), (85, 310)));
+    }
+}
@@ -0,0 +1,136 @@
+use std::fmt::{self, Debug, Display};
+use std::io;
+
+/// Any error that can occur while running a simulation or writing its output.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// An operation referred to a node which does not exist. This holds the `Debug`
+    /// representation of the node's key.
+    NodeNotFound(String),
+    /// A node was added with the same key as an existing node. This holds the `Debug`
+    /// representation of the key.
+    NodeExists(String),
+    /// A checkpoint could not be loaded, because it is invalid or incompatible with the system
+    /// it is being loaded into.
+    Checkpoint(&'static str),
+    /// A chat export could not be imported, because it does not have the expected structure.
+    Import(&'static str),
+    /// A graph file could not be read, because it is invalid or uses a feature of its format which
+    /// is not supported.
+    Format(&'static str),
+    /// A node's avatar could not be drawn, because it is not a valid PNG image.
+    Avatar(&'static str),
+    /// A chat export is not valid JSON.
+    #[cfg(any(feature = "discord", feature = "slack", feature = "matrix"))]
+    Json(serde_json::Error),
+    /// A graph file is not valid XML.
+    #[cfg(any(feature = "graphml", feature = "gexf"))]
+    Xml(quick_xml::Error),
+    /// An output has run out of space for new colours.
+    ///
+    /// GIF images support at most 256 colours, including the background.
+    PaletteFull,
+    /// An output could not be opened or written to.
+    Io(io::Error),
+    /// A PNG frame could not be encoded.
+    #[cfg(feature = "png")]
+    Png(png::EncodingError),
+    /// A GIF could not be encoded.
+    #[cfg(any(feature = "gif", feature = "masquerade"))]
+    Gif(gif::EncodingError),
+}
+
+impl Error {
+    pub(crate) fn node_not_found(key: &impl Debug) -> Self {
+        Self::NodeNotFound(format!("{key:?}"))
+    }
+
+    pub(crate) fn node_exists(key: &impl Debug) -> Self {
+        Self::NodeExists(format!("{key:?}"))
+    }
+}
+
+/// A `Result` alias where the error type defaults to [`Error`].
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NodeNotFound(id) => write!(f, "node {id} does not exist"),
+            Self::NodeExists(id) => write!(f, "node {id} already exists"),
+            Self::Checkpoint(reason) => write!(f, "could not load checkpoint: {reason}"),
+            Self::Import(reason) => write!(f, "could not import chat export: {reason}"),
+            Self::Format(reason) => write!(f, "could not read graph file: {reason}"),
+            Self::Avatar(reason) => write!(f, "could not use avatar: {reason}"),
+            #[cfg(any(feature = "discord", feature = "slack", feature = "matrix"))]
+            Self::Json(e) => write!(f, "invalid JSON: {e}"),
+            #[cfg(any(feature = "graphml", feature = "gexf"))]
+            Self::Xml(e) => write!(f, "invalid XML: {e}"),
+            Self::PaletteFull => f.write_str("too many distinct colours for the palette"),
+            Self::Io(e) => write!(f, "I/O error: {e}"),
+            #[cfg(feature = "png")]
+            Self::Png(e) => write!(f, "PNG encoding error: {e}"),
+            #[cfg(any(feature = "gif", feature = "masquerade"))]
+            Self::Gif(e) => write!(f, "GIF encoding error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            #[cfg(any(feature = "discord", feature = "slack", feature = "matrix"))]
+            Self::Json(e) => Some(e),
+            #[cfg(any(feature = "graphml", feature = "gexf"))]
+            Self::Xml(e) => Some(e),
+            #[cfg(feature = "png")]
+            Self::Png(e) => Some(e),
+            #[cfg(any(feature = "gif", feature = "masquerade"))]
+            Self::Gif(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+#[cfg(any(feature = "discord", feature = "slack", feature = "matrix"))]
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json(e)
+    }
+}
+
+#[cfg(any(feature = "graphml", feature = "gexf"))]
+impl From<quick_xml::Error> for Error {
+    fn from(e: quick_xml::Error) -> Self {
+        Self::Xml(e)
+    }
+}
+
+#[cfg(any(feature = "graphml", feature = "gexf"))]
+impl From<quick_xml::events::attributes::AttrError> for Error {
+    fn from(e: quick_xml::events::attributes::AttrError) -> Self {
+        Self::Xml(e.into())
+    }
+}
+
+#[cfg(feature = "png")]
+impl From<png::EncodingError> for Error {
+    fn from(e: png::EncodingError) -> Self {
+        Self::Png(e)
+    }
+}
+
+#[cfg(any(feature = "gif", feature = "masquerade"))]
+impl From<gif::EncodingError> for Error {
+    fn from(e: gif::EncodingError) -> Self {
+        Self::Gif(e)
+    }
+}
@@ -0,0 +1,304 @@
+//! Moving graphs between snowball and other tools.
+//!
+//! Each supported format can be read into, and written from, a [`FileGraph`]: a [`Graph`] whose
+//! nodes are identified by strings and carry an optional colour and position. Those are the only
+//! attributes kept; anything else in a file is ignored when reading. A whole [`System`] can be
+//! copied into a file graph with [`System::to_file_graph`], optionally with the current position of
+//! every node, and a file graph can be loaded into a system with [`System::add_file_graph`].
+//!
+//! The formats are each enabled by a feature of the same name:
+//!
+//! - [`graphml`], for [GraphML](http://graphml.graphdrawing.org/), as used by Gephi, yEd and
+//!   `NetworkX`,
+//! - [`gexf`], for [GEXF](https://gexf.net/), Gephi's own format,
+//! - [`dot`], for the [DOT language](https://graphviz.org/doc/info/lang.html) of Graphviz,
+//! - [`csv`], for a pair of CSV files listing the nodes and the edges, as accepted by Gephi's
+//!   spreadsheet import.
+//!
+//! With the `gexf` feature, a whole simulation can also be recorded as a dynamic GEXF file using
+//! the sink in [`timeline`], so that its evolution can be explored in Gephi.
+//!
+//! Graphs are always treated as undirected. When a file has several edges between the same pair
+//! of nodes, their weights are added together, and edges without a weight count as 1.
+use crate::graph::{Graph, HasKey};
+use crate::{Error, NodeKey, Result, System, Vec2d};
+use std::fmt::Display;
+use std::str::FromStr;
+
+#[cfg(feature = "csv")]
+pub mod csv;
+#[cfg(feature = "dot")]
+pub mod dot;
+#[cfg(feature = "gexf")]
+pub mod gexf;
+#[cfg(feature = "graphml")]
+pub mod graphml;
+#[cfg(feature = "gexf")]
+pub mod timeline;
+#[cfg(any(feature = "graphml", feature = "gexf"))]
+mod xml;
+
+/// A node as stored in a graph file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileNode {
+    pub id: String,
+    pub colour: Option<[u8; 3]>,
+    pub pos: Option<Vec2d>,
+}
+
+impl FileNode {
+    /// Create a node with no colour or position.
+    #[must_use]
+    pub fn new(id: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            colour: None,
+            pos: None,
+        }
+    }
+}
+
+impl HasKey for FileNode {
+    type Key = String;
+
+    fn key(&self) -> String {
+        self.id.clone()
+    }
+}
+
+/// A graph as read from or written to a file.
+pub type FileGraph = Graph<FileNode, f32>;
+
+/// Add to the weight of an edge, adding either node first if it doesn't exist yet. This is how
+/// every reader records edges, so that parallel edges are summed.
+#[cfg(any(
+    feature = "graphml",
+    feature = "gexf",
+    feature = "dot",
+    feature = "csv"
+))]
+fn add_edge(graph: &mut FileGraph, from: &str, to: &str, weight: f32) {
+    for id in [from, to] {
+        if !graph.contains_node(&id.to_string()) {
+            graph.add_node(FileNode::new(id));
+        }
+    }
+    let total = graph.get_weight(&from.to_string(), &to.to_string()) + weight;
+    graph.set_weight(from.to_string(), to.to_string(), total);
+}
+
+/// Get the edges of a graph in a repeatable order, with the nodes of each edge sorted by ID, so
+/// that writing the same graph twice gives the same file.
+fn sorted_edges(graph: &FileGraph) -> Vec<(&str, &str, f32)> {
+    let mut edges = graph
+        .edges_unique()
+        .map(|(from, to, &weight)| {
+            let (from, to) = (from.id.as_str(), to.id.as_str());
+            (from.min(to), from.max(to), weight)
+        })
+        .collect::<Vec<_>>();
+    edges.sort_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)));
+    edges
+}
+
+/// Get the nodes of a graph sorted by ID.
+fn sorted_nodes(graph: &FileGraph) -> Vec<&FileNode> {
+    let mut nodes = graph.nodes().collect::<Vec<_>>();
+    nodes.sort_by(|a, b| a.id.cmp(&b.id));
+    nodes
+}
+
+/// Format a colour as `#rrggbb`.
+#[must_use]
+pub fn hex_colour(colour: [u8; 3]) -> String {
+    let [r, g, b] = colour;
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+/// Parse a colour written as `#rrggbb` or `#rgb`, with or without the `#`.
+#[must_use]
+pub fn parse_hex_colour(text: &str) -> Option<[u8; 3]> {
+    let hex = text.trim().trim_start_matches('#');
+    if !hex.is_ascii() {
+        return None;
+    }
+    let channel = |range: std::ops::Range<usize>| u8::from_str_radix(&hex[range], 16).ok();
+    match hex.len() {
+        6 => Some([channel(0..2)?, channel(2..4)?, channel(4..6)?]),
+        3 => Some([
+            channel(0..1)? * 17,
+            channel(1..2)? * 17,
+            channel(2..3)? * 17,
+        ]),
+        _ => None,
+    }
+}
+
+/// Parse a number from a file, reporting `what` if it is invalid.
+#[cfg(any(
+    feature = "graphml",
+    feature = "gexf",
+    feature = "dot",
+    feature = "csv"
+))]
+fn parse_number(text: &str, what: &'static str) -> Result<f32> {
+    text.trim().parse().map_err(|_| Error::Format(what))
+}
+
+impl<K: NodeKey + Display> System<K> {
+    /// Copy the graph into a [`FileGraph`], with each node's ID formatted with `Display`, and its
+    /// own colour (rather than any community colour it is shown in). If `positions` is true, each
+    /// node's current position is included too.
+    ///
+    /// Edges are only included if their weight has been set to something other than 0.
+    #[must_use]
+    pub fn to_file_graph(&self, positions: bool) -> FileGraph {
+        let mut graph = Graph::new();
+        for node in self.graph.nodes() {
+            graph.add_node(FileNode {
+                id: node.id.to_string(),
+                colour: Some(node.colour),
+                pos: positions.then_some(node.pos),
+            });
+        }
+        for (from, to, &weight) in self.graph.edges_unique() {
+            if weight != 0. {
+                graph.set_weight(from.id.to_string(), to.id.to_string(), weight);
+            }
+        }
+        graph
+    }
+}
+
+impl<K: NodeKey + FromStr> System<K> {
+    /// Add every node and edge in a [`FileGraph`] to the system, parsing each node's ID with
+    /// `FromStr`. Nodes without a colour get one as in [`add_node`](Self::add_node), and nodes
+    /// with a position start there rather than in the middle.
+    ///
+    /// Nodes are added in order of their IDs, so loading the same graph into systems with the
+    /// same seed gives the same layout.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Format`] if an ID can't be parsed as a key, or [`Error::NodeExists`] if a
+    /// node is already in the system, in which case nothing is changed. Otherwise returns the
+    /// first error from any sink, after adding everything.
+    pub fn add_file_graph(&mut self, graph: &FileGraph) -> Result<()> {
+        let nodes = sorted_nodes(graph)
+            .into_iter()
+            .map(|node| {
+                let id = node
+                    .id
+                    .parse::<K>()
+                    .map_err(|_| Error::Format("node ID is not a valid key"))?;
+                if self.graph.contains_node(&id) {
+                    return Err(Error::node_exists(&id));
+                }
+                Ok((id, node))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let mut result = Ok(());
+        for (id, node) in &nodes {
+            let add_result = self.try_add_node(id.clone(), node.colour).map(drop);
+            if let (Some(pos), Some(added)) = (node.pos, self.graph.get_node_mut(id)) {
+                added.pos = pos;
+            }
+            if result.is_ok() {
+                result = add_result;
+            }
+        }
+        let keys = nodes
+            .into_iter()
+            .map(|(id, node)| (node.id.as_str(), id))
+            .collect::<hashbrown::HashMap<_, _>>();
+        for (from, to, weight) in sorted_edges(graph) {
+            let (from, to) = (&keys[from], &keys[to]);
+            self.graph.set_weight(from.clone(), to.clone(), weight);
+            let edge_result = self.broadcast(|sink| sink.weight_changed(from, to, weight));
+            if result.is_ok() {
+                result = edge_result;
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hex_colour, parse_hex_colour, FileGraph, FileNode};
+    use crate::{System, Vec2d};
+
+    /// A small graph using every attribute, for the round trip tests of each format.
+    pub(super) fn example() -> FileGraph {
+        let mut graph = FileGraph::new();
+        graph.add_node(FileNode {
+            id: "alice".to_string(),
+            colour: Some([255, 0, 0]),
+            pos: Some(Vec2d::new(1.5, -2.)),
+        });
+        graph.add_node(FileNode {
+            id: "bob \"the builder\" <&>".to_string(),
+            colour: Some([0, 128, 255]),
+            pos: None,
+        });
+        graph.add_node(FileNode::new("carol, esq."));
+        graph.set_weight(
+            "alice".to_string(),
+            "bob \"the builder\" <&>".to_string(),
+            2.5,
+        );
+        graph.set_weight("alice".to_string(), "carol, esq.".to_string(), 1.);
+        graph
+    }
+
+    /// Check that two graphs have the same nodes, attributes and edges.
+    #[cfg(any(
+        feature = "graphml",
+        feature = "gexf",
+        feature = "dot",
+        feature = "csv"
+    ))]
+    pub(super) fn assert_same(a: &FileGraph, b: &FileGraph) {
+        assert_eq!(a.node_count(), b.node_count());
+        for node in a.nodes() {
+            assert_eq!(Some(node), b.get_node(&node.id));
+        }
+        assert_eq!(super::sorted_edges(a), super::sorted_edges(b));
+    }
+
+    #[test]
+    fn colours() {
+        assert_eq!(hex_colour([255, 0, 128]), "#ff0080");
+        assert_eq!(parse_hex_colour("#ff0080"), Some([255, 0, 128]));
+        assert_eq!(parse_hex_colour("F08"), Some([255, 0, 136]));
+        assert_eq!(parse_hex_colour("red"), None);
+        assert_eq!(parse_hex_colour("#ffé"), None);
+    }
+
+    #[test]
+    fn system_round_trip() {
+        let mut system: System = System::with_seed(2);
+        system.add_node(1, [10, 20, 30]);
+        system.add_node(2, None);
+        system.set_weight(1, 2, 3.);
+        system.many_steps(5).unwrap();
+
+        let graph = system.to_file_graph(true);
+        assert_eq!(
+            graph.get_node(&"1".to_string()).unwrap().colour,
+            Some([10, 20, 30])
+        );
+        let mut copy: System = System::with_seed(2);
+        copy.add_file_graph(&graph).unwrap();
+        for id in [1, 2] {
+            let (a, b) = (system.graph.get_node(&id), copy.graph.get_node(&id));
+            assert_eq!(a.unwrap().pos, b.unwrap().pos);
+            assert_eq!(a.unwrap().colour, b.unwrap().colour);
+        }
+        assert!((copy.graph.get_weight(&1, &2) - 3.).abs() < f32::EPSILON);
+        assert!(copy.add_file_graph(&graph).is_err());
+        assert!(System::<u64>::new()
+            .add_file_graph(&super::tests::example())
+            .is_err());
+    }
+}
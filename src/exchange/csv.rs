@@ -0,0 +1,200 @@
+//! Reading and writing graphs as a pair of CSV files, one listing the nodes and one the edges, as
+//! accepted by the spreadsheet import of Gephi.
+//!
+//! The node file has the columns `id`, `colour`, `x` and `y`, and the edge file has `source`,
+//! `target` and `weight`. Missing values are left empty. When reading, columns are matched by their
+//! header, ignoring case, `color` is accepted for the colour, and other columns are ignored. Only
+//! `id`, `source` and `target` are required, and the node file may be empty, in which case every
+//! node is taken from the edges.
+use super::{add_edge, hex_colour, parse_hex_colour, parse_number, sorted_edges, sorted_nodes};
+use super::{FileGraph, FileNode};
+use crate::{Error, Result, Vec2d};
+use std::io::{Read, Write};
+
+/// Write a field, quoting it if it contains anything that would otherwise need escaping.
+fn field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) || text.trim() != text {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+/// Write a graph as a node file and an edge file.
+///
+/// # Errors
+///
+/// Returns an error if writing fails.
+pub fn write(graph: &FileGraph, mut nodes: impl Write, mut edges: impl Write) -> Result<()> {
+    writeln!(nodes, "id,colour,x,y")?;
+    for node in sorted_nodes(graph) {
+        let colour = node.colour.map(hex_colour).unwrap_or_default();
+        let (x, y) = node
+            .pos
+            .map(|pos| (pos.x.to_string(), pos.y.to_string()))
+            .unwrap_or_default();
+        writeln!(nodes, "{},{colour},{x},{y}", field(&node.id))?;
+    }
+    writeln!(edges, "source,target,weight")?;
+    for (from, to, weight) in sorted_edges(graph) {
+        writeln!(edges, "{},{},{weight}", field(from), field(to))?;
+    }
+    Ok(())
+}
+
+/// Split a CSV file into records, each a list of fields.
+fn records(mut r: impl Read) -> Result<Vec<Vec<String>>> {
+    let mut text = String::new();
+    r.read_to_string(&mut text)?;
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut chars = text.trim_start_matches('\u{feff}').chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if field.is_empty() => loop {
+                match chars.next() {
+                    Some('"') if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    Some('"') => break,
+                    Some(c) => field.push(c),
+                    None => return Err(Error::Format("unterminated quoted field")),
+                }
+            },
+            ',' => record.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    // Skip blank lines.
+    records.retain(|record| record.iter().any(|field| !field.is_empty()));
+    Ok(records)
+}
+
+/// The records of a CSV file, with its columns looked up by header.
+struct Table {
+    header: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    fn read(r: impl Read) -> Result<Self> {
+        let mut rows = records(r)?;
+        let header = if rows.is_empty() {
+            Vec::new()
+        } else {
+            rows.remove(0)
+                .into_iter()
+                .map(|name| name.trim().to_lowercase())
+                .collect()
+        };
+        Ok(Self { header, rows })
+    }
+
+    fn column(&self, names: &[&str]) -> Option<usize> {
+        self.header
+            .iter()
+            .position(|name| names.contains(&name.as_str()))
+    }
+
+    /// Get a field, treating a missing or empty field as absent.
+    fn get(row: &[String], column: Option<usize>) -> Option<&str> {
+        column
+            .and_then(|column| row.get(column))
+            .map(|field| field.trim())
+            .filter(|field| !field.is_empty())
+    }
+}
+
+/// Read a graph from a node file and an edge file.
+///
+/// # Errors
+///
+/// Returns [`Error::Format`] if a file is not valid CSV, lacks a required column, or has an
+/// invalid value, or [`Error::Io`] if reading fails.
+pub fn read(nodes: impl Read, edges: impl Read) -> Result<FileGraph> {
+    let mut graph = FileGraph::new();
+
+    let nodes = Table::read(nodes)?;
+    if !nodes.rows.is_empty() {
+        let id = nodes.column(&["id"]).ok_or(Error::Format("no id column"))?;
+        let colour = nodes.column(&["colour", "color"]);
+        let (x, y) = (nodes.column(&["x"]), nodes.column(&["y"]));
+        for row in &nodes.rows {
+            let mut node =
+                FileNode::new(Table::get(row, Some(id)).ok_or(Error::Format("missing node id"))?);
+            node.colour = Table::get(row, colour).and_then(parse_hex_colour);
+            if let (Some(x), Some(y)) = (Table::get(row, x), Table::get(row, y)) {
+                node.pos = Some(Vec2d::new(
+                    parse_number(x, "invalid x coordinate")?,
+                    parse_number(y, "invalid y coordinate")?,
+                ));
+            }
+            graph.add_node(node);
+        }
+    }
+
+    let edges = Table::read(edges)?;
+    if !edges.rows.is_empty() {
+        let source = edges
+            .column(&["source"])
+            .ok_or(Error::Format("no source column"))?;
+        let target = edges
+            .column(&["target"])
+            .ok_or(Error::Format("no target column"))?;
+        let weight = edges.column(&["weight"]);
+        for row in &edges.rows {
+            let from = Table::get(row, Some(source)).ok_or(Error::Format("missing edge source"))?;
+            let to = Table::get(row, Some(target)).ok_or(Error::Format("missing edge target"))?;
+            let weight = Table::get(row, weight)
+                .map_or(Ok(1.), |weight| parse_number(weight, "invalid edge weight"))?;
+            add_edge(&mut graph, from, to, weight);
+        }
+    }
+    Ok(graph)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read, write};
+    use crate::exchange::tests::{assert_same, example};
+
+    #[test]
+    fn round_trip() {
+        let graph = example();
+        let (mut nodes, mut edges) = (Vec::new(), Vec::new());
+        write(&graph, &mut nodes, &mut edges).unwrap();
+        assert_same(&graph, &read(nodes.as_slice(), edges.as_slice()).unwrap());
+    }
+
+    #[test]
+    fn reads_gephi_export() {
+        let nodes = "\u{feff}Id,Label,Color\r\n\"a\",Alice,#336699\r\nb,Bob,\r\n\r\n";
+        let edges = "Source,Target,Type,Weight\na,b,Undirected,2\nb,a,Undirected,0.5\n\"c\nd\",a,Undirected,\n";
+        let graph = read(nodes.as_bytes(), edges.as_bytes()).unwrap();
+        assert_eq!(graph.node_count(), 3);
+        assert_eq!(
+            graph.get_node(&"a".to_string()).unwrap().colour,
+            Some([0x33, 0x66, 0x99])
+        );
+        assert_eq!(graph.get_node(&"b".to_string()).unwrap().colour, None);
+        let weight = |from: &str, to: &str| graph.get_weight(&from.to_string(), &to.to_string());
+        assert!((weight("a", "b") - 2.5).abs() < 1e-6);
+        assert!((weight("c\nd", "a") - 1.).abs() < 1e-6);
+
+        let from_edges = read(&b""[..], edges.as_bytes()).unwrap();
+        assert_eq!(from_edges.node_count(), 3);
+        assert!(read(&b""[..], &b"from,to\na,b\n"[..]).is_err());
+        assert!(read(&b""[..], &b"source,target\n\"a,b\n"[..]).is_err());
+    }
+}
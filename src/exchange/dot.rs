@@ -0,0 +1,447 @@
+//! Reading and writing the [DOT language](https://graphviz.org/doc/info/lang.html) of Graphviz.
+//!
+//! Colours are written as the `color` attribute of nodes, positions as `pos` (pinned with `!`, so
+//! that `neato` keeps them), and weights as the `weight` attribute of edges. When reading,
+//! `fillcolor` takes precedence over `color`, and colours can only be read in `#rrggbb` form: named
+//! colours are ignored.
+//!
+//! Subgraphs are flattened into the graph, but edges to or from a whole subgraph are not
+//! supported, and nor are multiple graphs in one file. Directed graphs are read as if they were
+//! undirected.
+use super::{add_edge, hex_colour, parse_hex_colour, parse_number, sorted_edges, sorted_nodes};
+use super::{FileGraph, FileNode};
+use crate::{Error, Result, Vec2d};
+use std::io::{Read, Write};
+
+/// Write a string as a quoted DOT ID.
+fn quote(id: &str) -> String {
+    // Backslashes are escaped first, so that the ones escaping quotes are left alone.
+    let escaped = id.replace('\\', "\\\\").replace('"', "\\\"");
+    format!("\"{escaped}\"")
+}
+
+/// Write a graph in the DOT language.
+///
+/// # Errors
+///
+/// Returns an error if writing fails.
+pub fn write(graph: &FileGraph, mut w: impl Write) -> Result<()> {
+    writeln!(w, "graph {{")?;
+    for node in sorted_nodes(graph) {
+        let mut attributes = Vec::new();
+        if let Some(colour) = node.colour {
+            attributes.push(format!("color=\"{}\"", hex_colour(colour)));
+        }
+        if let Some(pos) = node.pos {
+            attributes.push(format!("pos=\"{},{}!\"", pos.x, pos.y));
+        }
+        if attributes.is_empty() {
+            writeln!(w, "  {};", quote(&node.id))?;
+        } else {
+            writeln!(w, "  {} [{}];", quote(&node.id), attributes.join(", "))?;
+        }
+    }
+    for (from, to, weight) in sorted_edges(graph) {
+        writeln!(w, "  {} -- {} [weight={weight}];", quote(from), quote(to))?;
+    }
+    writeln!(w, "}}")?;
+    Ok(())
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    /// An ID, and whether it was quoted. Keywords are only recognised when unquoted.
+    Id(String, bool),
+    Edge,
+    Open,
+    Close,
+    OpenList,
+    CloseList,
+    Equals,
+    Separator,
+    Colon,
+}
+
+/// Split a DOT file into tokens, skipping whitespace and comments.
+fn tokenize(text: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line_start = true;
+    while let Some(c) = chars.next() {
+        let at_line_start = std::mem::replace(&mut line_start, c == '\n');
+        match c {
+            // Lines starting with `#` are C preprocessor output, which Graphviz skips.
+            '#' if at_line_start => {
+                chars.by_ref().find(|&c| c == '\n');
+                line_start = true;
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                chars.by_ref().find(|&c| c == '\n');
+                line_start = true;
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                loop {
+                    match chars.next() {
+                        Some('/') if previous == '*' => break,
+                        Some(c) => previous = c,
+                        None => return Err(Error::Format("unterminated comment")),
+                    }
+                }
+            }
+            c if c.is_whitespace() => line_start = line_start || c == '\n',
+            '{' => tokens.push(Token::Open),
+            '}' => tokens.push(Token::Close),
+            '[' => tokens.push(Token::OpenList),
+            ']' => tokens.push(Token::CloseList),
+            '=' => tokens.push(Token::Equals),
+            ';' | ',' => tokens.push(Token::Separator),
+            ':' => tokens.push(Token::Colon),
+            '-' if matches!(chars.peek(), Some('-' | '>')) => {
+                chars.next();
+                tokens.push(Token::Edge);
+            }
+            '"' => {
+                let mut id = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\')) => id.push(c),
+                            // A backslash before a newline continues the line.
+                            Some('\n') => {}
+                            Some(c) => {
+                                id.push('\\');
+                                id.push(c);
+                            }
+                            None => return Err(Error::Format("unterminated string")),
+                        },
+                        Some(c) => id.push(c),
+                        None => return Err(Error::Format("unterminated string")),
+                    }
+                }
+                // Quoted strings can be joined with `+`.
+                if let Some(Token::Id(previous, true)) = concatenation(&mut tokens) {
+                    tokens.push(Token::Id(previous + &id, true));
+                } else {
+                    tokens.push(Token::Id(id, true));
+                }
+            }
+            '+' => tokens.push(Token::Id("+".to_string(), false)),
+            '<' => {
+                // An HTML string, which ends at the matching `>`.
+                let mut id = String::new();
+                let mut depth = 1;
+                while depth > 0 {
+                    let c = chars
+                        .next()
+                        .ok_or(Error::Format("unterminated HTML string"))?;
+                    depth += i32::from(c == '<') - i32::from(c == '>');
+                    if depth > 0 {
+                        id.push(c);
+                    }
+                }
+                tokens.push(Token::Id(id, true));
+            }
+            c if c.is_alphanumeric() || c == '_' || c == '.' || c == '-' || !c.is_ascii() => {
+                let mut id = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_alphanumeric() || c == '_' || c == '.' || !c.is_ascii() {
+                        id.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                tokens.push(Token::Id(id, false));
+            }
+            _ => return Err(Error::Format("unexpected character")),
+        }
+    }
+    Ok(tokens)
+}
+
+/// If the last two tokens are a quoted string followed by `+`, remove and return the string.
+fn concatenation(tokens: &mut Vec<Token>) -> Option<Token> {
+    match tokens.as_slice() {
+        [.., Token::Id(_, true), Token::Id(plus, false)] if plus == "+" => {
+            tokens.pop();
+            tokens.pop()
+        }
+        _ => None,
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    next: usize,
+    graph: FileGraph,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next)
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.next).cloned();
+        self.next += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: &Token, error: &'static str) -> Result<()> {
+        if self.advance().as_ref() == Some(expected) {
+            Ok(())
+        } else {
+            Err(Error::Format(error))
+        }
+    }
+
+    fn keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Id(id, false)) if id.eq_ignore_ascii_case(keyword))
+    }
+
+    fn id(&mut self) -> Result<String> {
+        match self.advance() {
+            Some(Token::Id(id, _)) => Ok(id),
+            _ => Err(Error::Format("expected an ID")),
+        }
+    }
+
+    fn graph(&mut self) -> Result<()> {
+        if self.keyword("strict") {
+            self.next += 1;
+        }
+        if !(self.keyword("graph") || self.keyword("digraph")) {
+            return Err(Error::Format("expected graph or digraph"));
+        }
+        self.next += 1;
+        if matches!(self.peek(), Some(Token::Id(..))) {
+            self.next += 1;
+        }
+        self.expect(&Token::Open, "expected {")?;
+        self.statements()?;
+        if self.peek().is_some() {
+            return Err(Error::Format("only one graph per file is supported"));
+        }
+        Ok(())
+    }
+
+    /// Read statements up to and including the closing brace of the current block.
+    fn statements(&mut self) -> Result<()> {
+        loop {
+            match self.peek() {
+                None => return Err(Error::Format("expected }")),
+                Some(Token::Close) => {
+                    self.next += 1;
+                    return Ok(());
+                }
+                Some(Token::Separator) => self.next += 1,
+                _ => self.statement()?,
+            }
+        }
+    }
+
+    fn statement(&mut self) -> Result<()> {
+        if self.keyword("graph") || self.keyword("node") || self.keyword("edge") {
+            // Default attributes don't affect anything we read.
+            self.next += 1;
+            self.attributes()?;
+            return Ok(());
+        }
+        if self.keyword("subgraph") || self.peek() == Some(&Token::Open) {
+            if self.keyword("subgraph") {
+                self.next += 1;
+                if matches!(self.peek(), Some(Token::Id(..))) {
+                    self.next += 1;
+                }
+            }
+            self.expect(&Token::Open, "expected {")?;
+            self.statements()?;
+            if self.peek() == Some(&Token::Edge) {
+                return Err(Error::Format("edges to subgraphs are not supported"));
+            }
+            return Ok(());
+        }
+        let first = self.node_id()?;
+        if self.peek() == Some(&Token::Equals) {
+            // A graph attribute.
+            self.next += 1;
+            self.id()?;
+            return Ok(());
+        }
+        let mut nodes = vec![first];
+        while self.peek() == Some(&Token::Edge) {
+            self.next += 1;
+            if self.keyword("subgraph") || self.peek() == Some(&Token::Open) {
+                return Err(Error::Format("edges to subgraphs are not supported"));
+            }
+            nodes.push(self.node_id()?);
+        }
+        let attributes = self.attributes()?;
+        let get = |name: &str| {
+            attributes
+                .iter()
+                .rev()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.as_str())
+        };
+        if let [id] = nodes.as_slice() {
+            let mut node = self
+                .graph
+                .get_node(id)
+                .cloned()
+                .unwrap_or_else(|| FileNode::new(id.as_str()));
+            if let Some(colour) = get("fillcolor")
+                .and_then(parse_colour)
+                .or_else(|| get("color").and_then(parse_colour))
+            {
+                node.colour = Some(colour);
+            }
+            if let Some(pos) = get("pos") {
+                node.pos = Some(parse_pos(pos)?);
+            }
+            self.graph.add_node(node);
+        } else {
+            let weight = get("weight")
+                .map_or(Ok(1.), |weight| parse_number(weight, "invalid edge weight"))?;
+            for pair in nodes.windows(2) {
+                add_edge(&mut self.graph, &pair[0], &pair[1], weight);
+            }
+        }
+        Ok(())
+    }
+
+    /// Read a node ID, ignoring any port.
+    fn node_id(&mut self) -> Result<String> {
+        let id = self.id()?;
+        while self.peek() == Some(&Token::Colon) {
+            self.next += 1;
+            self.id()?;
+        }
+        Ok(id)
+    }
+
+    /// Read any number of attribute lists.
+    fn attributes(&mut self) -> Result<Vec<(String, String)>> {
+        let mut attributes = Vec::new();
+        while self.peek() == Some(&Token::OpenList) {
+            self.next += 1;
+            loop {
+                match self.peek() {
+                    Some(Token::CloseList) => {
+                        self.next += 1;
+                        break;
+                    }
+                    Some(Token::Separator) => self.next += 1,
+                    _ => {
+                        let key = self.id()?;
+                        self.expect(&Token::Equals, "expected =")?;
+                        attributes.push((key, self.id()?));
+                    }
+                }
+            }
+        }
+        Ok(attributes)
+    }
+}
+
+/// Parse a colour, which may have an alpha channel after the RGB channels.
+fn parse_colour(text: &str) -> Option<[u8; 3]> {
+    let hex = text.trim().strip_prefix('#')?;
+    parse_hex_colour(hex.get(..6).filter(|_| hex.len() == 8).unwrap_or(hex))
+}
+
+/// Parse a position, which may be followed by `!`.
+fn parse_pos(text: &str) -> Result<Vec2d> {
+    let text = text.trim().trim_end_matches('!');
+    let (x, y) = text.split_once(',').ok_or(Error::Format("invalid pos"))?;
+    Ok(Vec2d::new(
+        parse_number(x, "invalid pos")?,
+        parse_number(y, "invalid pos")?,
+    ))
+}
+
+/// Read a graph in the DOT language.
+///
+/// # Errors
+///
+/// Returns [`Error::Format`] if the file is not valid DOT, or uses a feature described above as
+/// unsupported, or [`Error::Io`] if reading fails.
+pub fn read(mut r: impl Read) -> Result<FileGraph> {
+    let mut text = String::new();
+    r.read_to_string(&mut text)?;
+    let mut parser = Parser {
+        tokens: tokenize(&text)?,
+        next: 0,
+        graph: FileGraph::new(),
+    };
+    parser.graph()?;
+    Ok(parser.graph)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read, write};
+    use crate::exchange::tests::{assert_same, example};
+    use crate::exchange::{FileGraph, FileNode};
+    use crate::Vec2d;
+
+    #[test]
+    fn round_trip() {
+        let graph = example();
+        let mut file = Vec::new();
+        write(&graph, &mut file).unwrap();
+        assert_same(&graph, &read(file.as_slice()).unwrap());
+    }
+
+    #[test]
+    fn round_trips_backslashes() {
+        let mut graph = FileGraph::new();
+        graph.add_node(FileNode::new("a\\\"b"));
+        graph.add_node(FileNode::new("c\\"));
+        graph.set_weight("a\\\"b".to_string(), "c\\".to_string(), 1.);
+        let mut file = Vec::new();
+        write(&graph, &mut file).unwrap();
+        assert_same(&graph, &read(file.as_slice()).unwrap());
+    }
+
+    #[test]
+    fn reads_graphviz() {
+        let file = r##"
+# 1 "preprocessed.gv"
+strict digraph "G" {
+    graph [overlap=false]; node [shape=circle]
+    // A comment.
+    a [label=<<b>A</b>>, fillcolor="#11223380", color=red, pos="1,2!"]
+    "b" + "c":n:e -> d -> a [weight=2.5] /* another */
+    subgraph cluster_0 { label = "Cluster"; e; f -- g }
+    a -> bc
+}"##;
+        let graph = read(file.as_bytes()).unwrap();
+        assert_eq!(
+            graph.get_node(&"a".to_string()),
+            Some(&FileNode {
+                id: "a".to_string(),
+                colour: Some([0x11, 0x22, 0x33]),
+                pos: Some(Vec2d::new(1., 2.)),
+            })
+        );
+        let weight = |from: &str, to: &str| graph.get_weight(&from.to_string(), &to.to_string());
+        assert!((weight("bc", "d") - 2.5).abs() < 1e-6);
+        assert!((weight("a", "bc") - 1.).abs() < 1e-6);
+        assert!((weight("d", "a") - 2.5).abs() < 1e-6);
+        assert!((weight("f", "g") - 1.).abs() < 1e-6);
+        assert!(graph.contains_node(&"e".to_string()));
+        assert_eq!(graph.node_count(), 6);
+
+        for invalid in [
+            "graph { a -- }",
+            "graph { a -- { b } }",
+            "graph {} graph {}",
+        ] {
+            assert!(read(invalid.as_bytes()).is_err(), "{invalid}");
+        }
+    }
+}
@@ -0,0 +1,181 @@
+//! Reading and writing [GEXF](https://gexf.net/), the native format of Gephi.
+//!
+//! Colours and positions are stored with the standard `viz:color` and `viz:position` elements, and
+//! weights with the `weight` attribute of edges. Files are written as GEXF 1.3, and versions 1.1
+//! to 1.3 can be read.
+use super::xml::{self, escape};
+use super::{add_edge, parse_hex_colour, parse_number, sorted_edges, sorted_nodes};
+use super::{FileGraph, FileNode};
+use crate::{Error, Result, Vec2d};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::io::{BufRead, Write};
+
+/// Write a graph as GEXF.
+///
+/// # Errors
+///
+/// Returns an error if writing fails.
+pub fn write(graph: &FileGraph, mut w: impl Write) -> Result<()> {
+    writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        w,
+        r#"<gexf xmlns="http://gexf.net/1.3" xmlns:viz="http://gexf.net/1.3/viz" version="1.3">"#
+    )?;
+    writeln!(w, r#"  <graph defaultedgetype="undirected">"#)?;
+    writeln!(w, "    <nodes>")?;
+    for node in sorted_nodes(graph) {
+        let id = escape(node.id.as_str());
+        writeln!(w, r#"      <node id="{id}" label="{id}">"#)?;
+        if let Some([r, g, b]) = node.colour {
+            writeln!(w, r#"        <viz:color r="{r}" g="{g}" b="{b}"/>"#)?;
+        }
+        if let Some(pos) = node.pos {
+            writeln!(
+                w,
+                r#"        <viz:position x="{}" y="{}" z="0"/>"#,
+                pos.x, pos.y
+            )?;
+        }
+        writeln!(w, "      </node>")?;
+    }
+    writeln!(w, "    </nodes>")?;
+    writeln!(w, "    <edges>")?;
+    for (index, (from, to, weight)) in sorted_edges(graph).into_iter().enumerate() {
+        writeln!(
+            w,
+            r#"      <edge id="{index}" source="{}" target="{}" weight="{weight}"/>"#,
+            escape(from),
+            escape(to)
+        )?;
+    }
+    writeln!(w, "    </edges>")?;
+    writeln!(w, "  </graph>")?;
+    writeln!(w, "</gexf>")?;
+    Ok(())
+}
+
+/// Read a GEXF file.
+///
+/// Attributes other than colours, positions and weights are ignored, as are the spells of dynamic
+/// graphs: every node and edge which ever exists is read.
+///
+/// # Errors
+///
+/// Returns [`Error::Xml`] if the file is not valid XML, or [`Error::Format`] if it is not valid
+/// GEXF.
+pub fn read(r: impl BufRead) -> Result<FileGraph> {
+    let mut reader = Reader::from_reader(r);
+    reader.config_mut().trim_text(true);
+    let mut graph = FileGraph::new();
+    let mut node = None;
+    let mut buf = Vec::new();
+    loop {
+        let event = reader.read_event_into(&mut buf)?;
+        match &event {
+            Event::Start(start) | Event::Empty(start) => {
+                let attributes = xml::attributes(start)?;
+                let get = |name: &'static str| {
+                    attributes
+                        .get(name)
+                        .map(String::as_str)
+                        .ok_or(Error::Format("missing attribute"))
+                };
+                match (xml::name(start).as_str(), &mut node) {
+                    ("node", _) => node = Some(FileNode::new(get("id")?)),
+                    ("edge", _) => {
+                        let weight = attributes
+                            .get("weight")
+                            .map_or(Ok(1.), |weight| parse_number(weight, "invalid edge weight"))?;
+                        add_edge(&mut graph, get("source")?, get("target")?, weight);
+                    }
+                    ("color", Some(node)) => {
+                        let channel = |name| get(name).ok().and_then(|value| value.parse().ok());
+                        node.colour = attributes
+                            .get("hex")
+                            .and_then(|hex| parse_hex_colour(hex))
+                            .or_else(|| Some([channel("r")?, channel("g")?, channel("b")?]));
+                    }
+                    ("position", Some(node)) => {
+                        let x = parse_number(get("x")?, "invalid x coordinate")?;
+                        let y = parse_number(get("y")?, "invalid y coordinate")?;
+                        node.pos = Some(Vec2d::new(x, y));
+                    }
+                    _ => {}
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        let ends_node = match &event {
+            Event::End(end) => end.local_name().as_ref() == b"node",
+            Event::Empty(start) => start.local_name().as_ref() == b"node",
+            _ => false,
+        };
+        if ends_node {
+            if let Some(node) = node.take() {
+                graph.add_node(node);
+            }
+        }
+        buf.clear();
+    }
+    Ok(graph)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read, write};
+    use crate::exchange::tests::{assert_same, example};
+    use crate::exchange::FileNode;
+    use crate::Vec2d;
+
+    #[test]
+    fn round_trip() {
+        let graph = example();
+        let mut file = Vec::new();
+        write(&graph, &mut file).unwrap();
+        assert_same(&graph, &read(file.as_slice()).unwrap());
+    }
+
+    #[test]
+    fn reads_gephi_export() {
+        let file = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gexf xmlns="http://www.gexf.net/1.2draft" xmlns:viz="http://www.gexf.net/1.2draft/viz"
+      version="1.2">
+  <meta lastmodifieddate="2024-01-01"><creator>Gephi 0.10</creator></meta>
+  <graph defaultedgetype="undirected" mode="static">
+    <attributes class="node"><attribute id="0" title="role" type="string"/></attributes>
+    <nodes>
+      <node id="n0" label="Zero">
+        <attvalues><attvalue for="0" value="admin"/></attvalues>
+        <viz:size value="10.0"/>
+        <viz:position x="12.5" y="-3.25" z="0.0"/>
+        <viz:color r="10" g="200" b="30"/>
+      </node>
+      <node id="n1" label="One"/>
+    </nodes>
+    <edges>
+      <edge id="0" source="n0" target="n1" weight="4.0"/>
+      <edge id="1" source="n1" target="n0"/>
+    </edges>
+  </graph>
+</gexf>"#;
+        let graph = read(file.as_bytes()).unwrap();
+        assert_eq!(
+            graph.get_node(&"n0".to_string()),
+            Some(&FileNode {
+                id: "n0".to_string(),
+                colour: Some([10, 200, 30]),
+                pos: Some(Vec2d::new(12.5, -3.25)),
+            })
+        );
+        assert_eq!(
+            graph.get_node(&"n1".to_string()),
+            Some(&FileNode::new("n1"))
+        );
+        assert!((graph.get_weight(&"n0".to_string(), &"n1".to_string()) - 5.).abs() < 1e-6);
+        assert!(
+            read(&br#"<gexf><graph><edges><edge source="a"/></edges></graph></gexf>"#[..]).is_err()
+        );
+    }
+}
@@ -0,0 +1,260 @@
+//! Reading and writing [GraphML](http://graphml.graphdrawing.org/).
+//!
+//! Node colours are written as a `colour` attribute holding a `#rrggbb` string, positions as `x`
+//! and `y` attributes, and weights as a `weight` attribute of edges. When reading, `color` is
+//! accepted for the colour, as are separate `r`, `g` and `b` attributes as written by Gephi.
+//! Attributes are matched by their `attr.name`, ignoring case.
+use super::xml::{self, escape};
+use super::{add_edge, hex_colour, parse_hex_colour, parse_number, sorted_edges, sorted_nodes};
+use super::{FileGraph, FileNode};
+use crate::{Error, Result, Vec2d};
+use hashbrown::HashMap;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::io::{BufRead, Write};
+
+/// Write a graph as [GraphML](http://graphml.graphdrawing.org/).
+///
+/// # Errors
+///
+/// Returns an error if writing fails.
+pub fn write(graph: &FileGraph, mut w: impl Write) -> Result<()> {
+    writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        w,
+        r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+    )?;
+    writeln!(
+        w,
+        r#"  <key id="colour" for="node" attr.name="colour" attr.type="string"/>"#
+    )?;
+    writeln!(
+        w,
+        r#"  <key id="x" for="node" attr.name="x" attr.type="float"/>"#
+    )?;
+    writeln!(
+        w,
+        r#"  <key id="y" for="node" attr.name="y" attr.type="float"/>"#
+    )?;
+    writeln!(
+        w,
+        r#"  <key id="weight" for="edge" attr.name="weight" attr.type="float"/>"#
+    )?;
+    writeln!(w, r#"  <graph edgedefault="undirected">"#)?;
+    for node in sorted_nodes(graph) {
+        writeln!(w, r#"    <node id="{}">"#, escape(node.id.as_str()))?;
+        if let Some(colour) = node.colour {
+            writeln!(
+                w,
+                r#"      <data key="colour">{}</data>"#,
+                hex_colour(colour)
+            )?;
+        }
+        if let Some(pos) = node.pos {
+            writeln!(w, r#"      <data key="x">{}</data>"#, pos.x)?;
+            writeln!(w, r#"      <data key="y">{}</data>"#, pos.y)?;
+        }
+        writeln!(w, "    </node>")?;
+    }
+    for (from, to, weight) in sorted_edges(graph) {
+        writeln!(
+            w,
+            r#"    <edge source="{}" target="{}">"#,
+            escape(from),
+            escape(to)
+        )?;
+        writeln!(w, r#"      <data key="weight">{weight}</data>"#)?;
+        writeln!(w, "    </edge>")?;
+    }
+    writeln!(w, "  </graph>")?;
+    writeln!(w, "</graphml>")?;
+    Ok(())
+}
+
+/// The element whose attributes are currently being read.
+enum Element {
+    Node(FileNode),
+    Edge {
+        source: String,
+        target: String,
+        weight: f32,
+    },
+}
+
+/// The attributes of a node being read which need to be combined.
+#[derive(Default)]
+struct Parts {
+    x: Option<f32>,
+    y: Option<f32>,
+    rgb: [Option<u8>; 3],
+}
+
+/// Read a [GraphML](http://graphml.graphdrawing.org/) file.
+///
+/// Only the first graph in the file is read, and nested graphs are flattened into it. Hyperedges
+/// and ports are not supported.
+///
+/// # Errors
+///
+/// Returns [`Error::Xml`] if the file is not valid XML, or [`Error::Format`] if it is not a
+/// valid graph file.
+pub fn read(r: impl BufRead) -> Result<FileGraph> {
+    let mut reader = Reader::from_reader(r);
+    reader.config_mut().trim_text(true);
+    let mut graph = FileGraph::new();
+    // The attribute name for each key ID.
+    let mut keys = HashMap::new();
+    let mut element = None;
+    let mut parts = Parts::default();
+    // The name of the attribute whose `<data>` is being read.
+    let mut data = None;
+    let mut buf = Vec::new();
+    loop {
+        let event = reader.read_event_into(&mut buf)?;
+        match &event {
+            Event::Start(start) | Event::Empty(start) => {
+                let attributes = xml::attributes(start)?;
+                let get = |name: &'static str| {
+                    attributes
+                        .get(name)
+                        .cloned()
+                        .ok_or(Error::Format("missing attribute"))
+                };
+                match xml::name(start).as_str() {
+                    "key" => {
+                        if let Some(name) = attributes.get("attr.name") {
+                            keys.insert(get("id")?, name.to_ascii_lowercase());
+                        }
+                    }
+                    "node" => {
+                        element = Some(Element::Node(FileNode::new(get("id")?)));
+                        parts = Parts::default();
+                    }
+                    "edge" => {
+                        element = Some(Element::Edge {
+                            source: get("source")?,
+                            target: get("target")?,
+                            weight: 1.,
+                        });
+                    }
+                    "data" => data = keys.get(&get("key")?).cloned(),
+                    "hyperedge" | "port" => {
+                        return Err(Error::Format("hyperedges and ports are not supported"))
+                    }
+                    _ => {}
+                }
+            }
+            Event::Text(text) => {
+                if let (Some(name), Some(element)) = (&data, &mut element) {
+                    set_data(element, &mut parts, name, &text.unescape()?)?;
+                }
+            }
+            _ => {}
+        }
+        let ends = match &event {
+            Event::End(end) => Some(String::from_utf8_lossy(end.local_name().as_ref()).into()),
+            Event::Empty(start) => Some(xml::name(start)),
+            Event::Eof => break,
+            _ => None,
+        };
+        match ends.as_deref() {
+            Some("data") => data = None,
+            Some("node" | "edge") => match element.take() {
+                Some(Element::Node(mut node)) => {
+                    if let (Some(x), Some(y)) = (parts.x, parts.y) {
+                        node.pos = Some(Vec2d::new(x, y));
+                    }
+                    if let [Some(r), Some(g), Some(b)] = parts.rgb {
+                        node.colour = node.colour.or(Some([r, g, b]));
+                    }
+                    // This replaces any placeholder added for an edge read earlier, keeping the
+                    // edge.
+                    graph.add_node(node);
+                }
+                Some(Element::Edge {
+                    source,
+                    target,
+                    weight,
+                }) => add_edge(&mut graph, &source, &target, weight),
+                None => {}
+            },
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(graph)
+}
+
+/// Record the value of a `<data>` element.
+fn set_data(element: &mut Element, parts: &mut Parts, name: &str, value: &str) -> Result<()> {
+    match element {
+        Element::Node(node) => match name {
+            "colour" | "color" => node.colour = parse_hex_colour(value),
+            "x" => parts.x = Some(parse_number(value, "invalid x coordinate")?),
+            "y" => parts.y = Some(parse_number(value, "invalid y coordinate")?),
+            "r" | "g" | "b" => {
+                let channel = usize::from(name == "g") + 2 * usize::from(name == "b");
+                parts.rgb[channel] = value.trim().parse().ok();
+            }
+            _ => {}
+        },
+        Element::Edge { weight, .. } => {
+            if name == "weight" {
+                *weight = parse_number(value, "invalid edge weight")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read, write};
+    use crate::exchange::tests::{assert_same, example};
+    use crate::exchange::FileNode;
+    use crate::Vec2d;
+
+    #[test]
+    fn round_trip() {
+        let graph = example();
+        let mut file = Vec::new();
+        write(&graph, &mut file).unwrap();
+        assert_same(&graph, &read(file.as_slice()).unwrap());
+    }
+
+    #[test]
+    fn reads_gephi_export() {
+        let file = r#"<?xml version="1.0" encoding="UTF-8"?>
+<graphml xmlns="http://graphml.graphdrawing.org/xmlns">
+<key attr.name="label" attr.type="string" for="node" id="label"/>
+<key attr.name="Edge Weight" attr.type="double" for="edge" id="weight"/>
+<key attr.name="r" attr.type="int" for="node" id="r"/>
+<key attr.name="g" attr.type="int" for="node" id="g"/>
+<key attr.name="b" attr.type="int" for="node" id="b"/>
+<key attr.name="x" attr.type="float" for="node" id="x"/>
+<key attr.name="y" attr.type="float" for="node" id="y"/>
+<key attr.name="weight" attr.type="double" for="edge" id="w"/>
+<graph edgedefault="undirected">
+<node id="0"><data key="label">Zero</data><data key="r">255</data><data key="g">0</data>
+<data key="b">51</data><data key="x">-10.5</data><data key="y">3.0</data></node>
+<node id="1"/>
+<edge source="0" target="1"><data key="w">2.0</data></edge>
+<edge source="1" target="0"><data key="w">0.5</data></edge>
+<edge source="1" target="2"/>
+</graph>
+</graphml>"#;
+        let graph = read(file.as_bytes()).unwrap();
+        assert_eq!(
+            graph.get_node(&"0".to_string()),
+            Some(&FileNode {
+                id: "0".to_string(),
+                colour: Some([255, 0, 51]),
+                pos: Some(Vec2d::new(-10.5, 3.)),
+            })
+        );
+        assert_eq!(graph.node_count(), 3);
+        assert!((graph.get_weight(&"0".to_string(), &"1".to_string()) - 2.5).abs() < 1e-6);
+        assert!((graph.get_weight(&"2".to_string(), &"1".to_string()) - 1.).abs() < 1e-6);
+        assert!(read(&b"<graphml><graph><node/></graph></graphml>"[..]).is_err());
+    }
+}
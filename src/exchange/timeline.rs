@@ -0,0 +1,630 @@
+//! Recording a whole simulation as a dynamic [GEXF](https://gexf.net/) file, which Gephi can play
+//! back with its timeline.
+//!
+//! Time is measured in steps. Each node has a spell for every period it existed, and its colour,
+//! size and position are written as `viz` elements which each hold for a period of steps. Each
+//! edge has a spell for every period its weight was not 0, and its weight over time is written as
+//! timestamped values of the `weight` attribute.
+//!
+//! Node IDs are formatted with `Display`, so a node which is removed and later added again with
+//! the same key is written as one node with several spells.
+use super::xml::escape;
+use crate::output::Output;
+use crate::sink::set_at;
+use crate::state::{NodeTable, ReadState, WriteState};
+use crate::{Error, FrameSink, NodeKey, Result, Vec2d};
+use hashbrown::HashMap;
+use std::fmt::Display;
+use std::io::{Read, Write};
+
+/// The size of a node in Gephi which is drawn at the default size in snowball.
+const NODE_SIZE: f32 = 10.;
+
+/// Marks a node which has not been removed in a saved timeline.
+const STILL_OPEN: u32 = u32::MAX;
+
+/// A period of steps, from `start` up to but not including `end`, or with no end.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Interval {
+    start: u32,
+    end: Option<u32>,
+}
+
+impl Interval {
+    fn is_empty(self) -> bool {
+        self.end.is_some_and(|end| end <= self.start)
+    }
+
+    /// Format the interval as GEXF attributes. GEXF intervals include both ends.
+    fn attributes(self) -> String {
+        self.end.map_or_else(
+            || format!(r#"start="{}""#, self.start),
+            |end| format!(r#"start="{}" end="{}""#, self.start, end - 1),
+        )
+    }
+}
+
+/// Split a list of changes into the periods each value held for, up to `end`, skipping any which
+/// are empty.
+fn spans<T: Copy>(changes: &[(u32, T)], end: Option<u32>) -> Vec<(Interval, T)> {
+    changes
+        .iter()
+        .enumerate()
+        .map(|(i, &(start, value))| {
+            let next = changes.get(i + 1).map(|&(next, _)| next);
+            let interval = Interval {
+                start,
+                end: next.or(end),
+            };
+            (interval, value)
+        })
+        .filter(|(interval, _)| !interval.is_empty())
+        .collect()
+}
+
+/// One lifetime of a node, from when it was added until it was removed.
+#[derive(Clone, Debug)]
+struct Node {
+    id: String,
+    lifetime: Interval,
+    colours: Vec<(u32, [u8; 3])>,
+    sizes: Vec<(u32, f32)>,
+    // Positions are only recorded when they change, so nodes which have settled down cost very
+    // little.
+    positions: Vec<(u32, Vec2d)>,
+}
+
+impl Node {
+    fn save_state(&self, w: &mut dyn Write) -> Result<()> {
+        w.write_bytes(self.id.as_bytes())?;
+        w.write_u32(self.lifetime.start)?;
+        w.write_u32(self.lifetime.end.unwrap_or(STILL_OPEN))?;
+        w.write_len(self.colours.len())?;
+        for &(step, colour) in &self.colours {
+            w.write_u32(step)?;
+            w.write_colour(colour)?;
+        }
+        w.write_len(self.sizes.len())?;
+        for &(step, size) in &self.sizes {
+            w.write_u32(step)?;
+            w.write_f32(size)?;
+        }
+        w.write_len(self.positions.len())?;
+        for &(step, pos) in &self.positions {
+            w.write_u32(step)?;
+            w.write_vec2d(pos)?;
+        }
+        Ok(())
+    }
+
+    fn load_state(r: &mut dyn Read) -> Result<Self> {
+        let id = String::from_utf8(r.read_bytes()?)
+            .map_err(|_| Error::Checkpoint("node ID is not valid UTF-8"))?;
+        let start = r.read_u32()?;
+        let end = Some(r.read_u32()?).filter(|&end| end != STILL_OPEN);
+        let mut colours = Vec::new();
+        for _ in 0..r.read_len()? {
+            colours.push((r.read_u32()?, r.read_colour()?));
+        }
+        let mut sizes = Vec::new();
+        for _ in 0..r.read_len()? {
+            sizes.push((r.read_u32()?, r.read_f32()?));
+        }
+        let mut positions = Vec::new();
+        for _ in 0..r.read_len()? {
+            positions.push((r.read_u32()?, r.read_vec2d()?));
+        }
+        Ok(Self {
+            id,
+            lifetime: Interval { start, end },
+            colours,
+            sizes,
+            positions,
+        })
+    }
+
+    fn write(&self, w: &mut impl Write) -> Result<()> {
+        let end = self.lifetime.end;
+        for (interval, [r, g, b]) in spans(&self.colours, end) {
+            let interval = interval.attributes();
+            writeln!(
+                w,
+                r#"        <viz:color r="{r}" g="{g}" b="{b}" {interval}/>"#
+            )?;
+        }
+        for (interval, size) in spans(&self.sizes, end) {
+            writeln!(
+                w,
+                r#"        <viz:size value="{}" {}/>"#,
+                size * NODE_SIZE,
+                interval.attributes()
+            )?;
+        }
+        for (interval, pos) in spans(&self.positions, end) {
+            writeln!(
+                w,
+                r#"        <viz:position x="{}" y="{}" z="0" {}/>"#,
+                pos.x,
+                pos.y,
+                interval.attributes()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// A record of every change to a graph over the course of a simulation, to be written as a
+/// dynamic GEXF file.
+#[derive(Clone, Debug)]
+pub struct Timeline<K = u64> {
+    // Every lifetime of every node, in the order they started.
+    nodes: Vec<Node>,
+    // The index in `nodes` of the current lifetime of each node which exists.
+    open: HashMap<K, usize>,
+    // The weight of each edge over time, keyed by the indices of its nodes in `nodes`, smallest
+    // first. Edges are tied to lifetimes, so an edge ends when either node is removed.
+    edges: HashMap<(usize, usize), Vec<(u32, f32)>>,
+    step: u32,
+}
+
+impl<K: NodeKey> Default for Timeline<K> {
+    fn default() -> Self {
+        Self {
+            nodes: Vec::new(),
+            open: HashMap::new(),
+            edges: HashMap::new(),
+            step: 0,
+        }
+    }
+}
+
+impl<K: NodeKey + Display> Timeline<K> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn node_mut(&mut self, id: &K) -> Result<&mut Node> {
+        let index = *self.open.get(id).ok_or_else(|| Error::node_not_found(id))?;
+        Ok(&mut self.nodes[index])
+    }
+
+    /// Start a new lifetime of a node at the current step. If the node already exists, its
+    /// current lifetime ends first.
+    pub fn add_node(&mut self, id: K, colour: [u8; 3]) {
+        self.remove_node(&id);
+        self.nodes.push(Node {
+            id: id.to_string(),
+            lifetime: Interval {
+                start: self.step,
+                end: None,
+            },
+            colours: vec![(self.step, colour)],
+            sizes: vec![(self.step, 1.)],
+            positions: Vec::new(),
+        });
+        self.open.insert(id, self.nodes.len() - 1);
+    }
+
+    /// Show a node in a new colour from the current step onwards.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NodeNotFound`] if the node has not been added, or has been removed.
+    pub fn set_colour(&mut self, id: &K, colour: [u8; 3]) -> Result<()> {
+        let step = self.step;
+        set_at(&mut self.node_mut(id)?.colours, step, colour);
+        Ok(())
+    }
+
+    /// Show a node at a new size from the current step onwards, where 1 is the default size.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NodeNotFound`] if the node has not been added, or has been removed.
+    pub fn set_size(&mut self, id: &K, size: f32) -> Result<()> {
+        let step = self.step;
+        set_at(&mut self.node_mut(id)?.sizes, step, size);
+        Ok(())
+    }
+
+    /// Record the weight of an edge from the current step onwards.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NodeNotFound`] if either node has not been added, or has been removed.
+    pub fn set_weight(&mut self, from: &K, to: &K, weight: f32) -> Result<()> {
+        let index = |id| {
+            self.open
+                .get(id)
+                .copied()
+                .ok_or_else(|| Error::node_not_found(id))
+        };
+        let (from, to) = (index(from)?, index(to)?);
+        let changes = self.edges.entry((from.min(to), from.max(to))).or_default();
+        set_at(changes, self.step, weight);
+        Ok(())
+    }
+
+    /// End the current lifetime of a node, along with all of its edges. Nothing happens if the
+    /// node does not exist.
+    pub fn remove_node(&mut self, id: &K) {
+        let Some(index) = self.open.remove(id) else {
+            return;
+        };
+        self.nodes[index].lifetime.end = Some(self.step);
+        for (&(from, to), changes) in &mut self.edges {
+            if from == index || to == index {
+                set_at(changes, self.step, 0.);
+            }
+        }
+    }
+
+    /// Record the position of a node for the current step.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NodeNotFound`] if the node has not been added, or has been removed.
+    pub fn set_position(&mut self, id: &K, pos: Vec2d) -> Result<()> {
+        let step = self.step;
+        let positions = &mut self.node_mut(id)?.positions;
+        if positions.last().map(|&(_, last)| last) != Some(pos) {
+            positions.push((step, pos));
+        }
+        Ok(())
+    }
+
+    pub const fn next_step(&mut self) {
+        self.step += 1;
+    }
+
+    /// Write the recorded timeline in the checkpoint format (see [`crate::state`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing fails, or if a node which has not been removed is missing from
+    /// `nodes`.
+    pub fn save_state(&self, w: &mut dyn Write, nodes: &NodeTable<K>) -> Result<()> {
+        w.write_u32(self.step)?;
+        w.write_len(self.nodes.len())?;
+        for node in &self.nodes {
+            node.save_state(w)?;
+        }
+        w.write_len(self.open.len())?;
+        for (id, &index) in &self.open {
+            w.write_u64(nodes.index(id)?)?;
+            w.write_len(index)?;
+        }
+        w.write_len(self.edges.len())?;
+        for (&(from, to), changes) in &self.edges {
+            w.write_len(from)?;
+            w.write_len(to)?;
+            w.write_len(changes.len())?;
+            for &(step, weight) in changes {
+                w.write_u32(step)?;
+                w.write_f32(weight)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read a timeline written by [`save_state`](Self::save_state).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading fails, the data is truncated or inconsistent, or it refers to a
+    /// node which is not in `nodes`.
+    pub fn load_state(r: &mut dyn Read, nodes: &NodeTable<K>) -> Result<Self> {
+        let step = r.read_u32()?;
+        let mut lifetimes = Vec::new();
+        for _ in 0..r.read_len()? {
+            lifetimes.push(Node::load_state(r)?);
+        }
+        let index = |r: &mut dyn Read| {
+            let index = r.read_len()?;
+            if index < lifetimes.len() {
+                Ok(index)
+            } else {
+                Err(Error::Checkpoint("node index out of range"))
+            }
+        };
+        let mut open = HashMap::new();
+        for _ in 0..r.read_len()? {
+            let id = nodes.key(r.read_u64()?)?.clone();
+            open.insert(id, index(r)?);
+        }
+        let mut edges = HashMap::new();
+        for _ in 0..r.read_len()? {
+            let key = (index(r)?, index(r)?);
+            let mut changes = Vec::new();
+            for _ in 0..r.read_len()? {
+                changes.push((r.read_u32()?, r.read_f32()?));
+            }
+            edges.insert(key, changes);
+        }
+        Ok(Self {
+            nodes: lifetimes,
+            open,
+            edges,
+            step,
+        })
+    }
+
+    /// Write the timeline as a dynamic GEXF file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing fails.
+    pub fn write(&self, mut w: impl Write) -> Result<()> {
+        writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            w,
+            r#"<gexf xmlns="http://gexf.net/1.3" xmlns:viz="http://gexf.net/1.3/viz" version="1.3">"#
+        )?;
+        writeln!(
+            w,
+            r#"  <graph mode="dynamic" defaultedgetype="undirected" timeformat="integer" timerepresentation="interval">"#
+        )?;
+
+        // Group the lifetimes of each node, in the order the nodes first appeared.
+        let mut ids = Vec::new();
+        let mut lifetimes = HashMap::<&str, Vec<&Node>>::new();
+        for node in self.nodes.iter().filter(|node| !node.lifetime.is_empty()) {
+            lifetimes
+                .entry(node.id.as_str())
+                .or_insert_with(|| {
+                    ids.push(node.id.as_str());
+                    Vec::new()
+                })
+                .push(node);
+        }
+        writeln!(w, "    <nodes>")?;
+        for id in &ids {
+            let id_attribute = escape(*id);
+            writeln!(
+                w,
+                r#"      <node id="{id_attribute}" label="{id_attribute}">"#
+            )?;
+            writeln!(w, "        <spells>")?;
+            for node in &lifetimes[id] {
+                writeln!(w, "          <spell {}/>", node.lifetime.attributes())?;
+            }
+            writeln!(w, "        </spells>")?;
+            for node in &lifetimes[id] {
+                node.write(&mut w)?;
+            }
+            writeln!(w, "      </node>")?;
+        }
+        writeln!(w, "    </nodes>")?;
+
+        // Group the edges between each pair of IDs, as with nodes.
+        let mut pairs = HashMap::<(&str, &str), Vec<(Interval, f32)>>::new();
+        for (&(from, to), changes) in &self.edges {
+            let (from, to) = (self.nodes[from].id.as_str(), self.nodes[to].id.as_str());
+            pairs
+                .entry((from.min(to), from.max(to)))
+                .or_default()
+                .extend(spans(changes, None).into_iter().filter(|&(_, w)| w != 0.));
+        }
+        let mut pairs = pairs
+            .into_iter()
+            .filter(|(_, spans)| !spans.is_empty())
+            .collect::<Vec<_>>();
+        pairs.sort_by(|a, b| a.0.cmp(&b.0));
+        writeln!(w, "    <edges>")?;
+        for (index, ((from, to), mut spans)) in pairs.into_iter().enumerate() {
+            spans.sort_by_key(|(interval, _)| interval.start);
+            let last_weight = spans.last().map_or(0., |&(_, weight)| weight);
+            writeln!(
+                w,
+                r#"      <edge id="{index}" source="{}" target="{}" weight="{last_weight}">"#,
+                escape(from),
+                escape(to)
+            )?;
+            writeln!(w, "        <attvalues>")?;
+            for (interval, weight) in &spans {
+                writeln!(
+                    w,
+                    r#"          <attvalue for="weight" value="{weight}" {}/>"#,
+                    interval.attributes()
+                )?;
+            }
+            writeln!(w, "        </attvalues>")?;
+            // Spells only need to change when the edge disappears, not whenever its weight does.
+            let mut spells: Vec<Interval> = Vec::new();
+            for (interval, _) in spans {
+                match spells.last_mut() {
+                    Some(last) if last.end == Some(interval.start) => last.end = interval.end,
+                    _ => spells.push(interval),
+                }
+            }
+            writeln!(w, "        <spells>")?;
+            for spell in spells {
+                writeln!(w, "          <spell {}/>", spell.attributes())?;
+            }
+            writeln!(w, "        </spells>")?;
+            writeln!(w, "      </edge>")?;
+        }
+        writeln!(w, "    </edges>")?;
+        writeln!(w, "  </graph>")?;
+        writeln!(w, "</gexf>")?;
+        Ok(())
+    }
+}
+
+/// A sink which records a [`Timeline`], and writes it as a dynamic GEXF file when the simulation
+/// finishes.
+pub struct DynamicGexf<K = u64> {
+    timeline: Timeline<K>,
+    // This is only `None` once the file has been written.
+    output: Option<Box<dyn Write>>,
+}
+
+impl<K: NodeKey + Display> DynamicGexf<K> {
+    /// Create a sink which will write to the given destination when finished.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the destination cannot be opened.
+    pub fn new(output: impl Into<Output>) -> Result<Self> {
+        Ok(Self {
+            timeline: Timeline::new(),
+            output: Some(output.into().open()?),
+        })
+    }
+
+    /// Get the timeline recorded so far.
+    #[must_use]
+    pub const fn timeline(&self) -> &Timeline<K> {
+        &self.timeline
+    }
+}
+
+impl<K: NodeKey + Display> FrameSink<K> for DynamicGexf<K> {
+    fn node_added(&mut self, id: &K, colour: [u8; 3]) -> Result<()> {
+        self.timeline.add_node(id.clone(), colour);
+        Ok(())
+    }
+
+    fn node_recoloured(&mut self, id: &K, colour: [u8; 3]) -> Result<()> {
+        self.timeline.set_colour(id, colour)
+    }
+
+    fn node_resized(&mut self, id: &K, size: f32) -> Result<()> {
+        self.timeline.set_size(id, size)
+    }
+
+    fn node_removed(&mut self, id: &K) -> Result<()> {
+        self.timeline.remove_node(id);
+        Ok(())
+    }
+
+    fn weight_changed(&mut self, from: &K, to: &K, weight: f32) -> Result<()> {
+        self.timeline.set_weight(from, to, weight)
+    }
+
+    fn node_moved(&mut self, id: &K, pos: Vec2d) -> Result<()> {
+        self.timeline.set_position(id, pos)
+    }
+
+    fn step_finished(&mut self, _step: u64) -> Result<()> {
+        self.timeline.next_step();
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if let Some(mut output) = self.output.take() {
+            self.timeline.write(&mut output)?;
+            output.flush()?;
+        }
+        Ok(())
+    }
+
+    fn save_state(&self, w: &mut dyn Write, nodes: &NodeTable<K>) -> Result<()> {
+        self.timeline.save_state(w, nodes)
+    }
+
+    fn load_state(&mut self, r: &mut dyn Read, nodes: &NodeTable<K>) -> Result<()> {
+        self.timeline = Timeline::load_state(r, nodes)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DynamicGexf, Timeline};
+    use crate::exchange::gexf;
+    use crate::state::NodeTable;
+    use crate::{SharedBuffer, System, Vec2d};
+
+    fn example() -> Timeline<&'static str> {
+        let mut timeline = Timeline::new();
+        timeline.add_node("alice", [255, 0, 0]);
+        timeline.add_node("bob", [0, 0, 255]);
+        timeline.set_weight(&"alice", &"bob", 2.).unwrap();
+        for step in 0..4 {
+            timeline
+                .set_position(&"alice", Vec2d::new(step as f32, 0.))
+                .unwrap();
+            timeline.set_position(&"bob", Vec2d::new(5., 5.)).unwrap();
+            if step == 1 {
+                timeline.set_weight(&"alice", &"bob", 3.).unwrap();
+                timeline.set_colour(&"bob", [0, 255, 0]).unwrap();
+                timeline.set_size(&"bob", 2.).unwrap();
+            }
+            timeline.next_step();
+        }
+        timeline.remove_node(&"alice");
+        timeline.next_step();
+        timeline.add_node("alice", [255, 0, 0]);
+        timeline.set_weight(&"alice", &"bob", 1.).unwrap();
+        timeline.next_step();
+        timeline
+    }
+
+    #[test]
+    fn writes_spells_and_timestamps() {
+        let mut file = Vec::new();
+        example().write(&mut file).unwrap();
+        let file = String::from_utf8(file).unwrap();
+        for expected in [
+            r#"<spell start="0" end="3"/>"#,
+            r#"<spell start="5"/>"#,
+            r#"<viz:color r="0" g="0" b="255" start="0" end="0"/>"#,
+            r#"<viz:color r="0" g="255" b="0" start="1"/>"#,
+            r#"<viz:size value="20" start="1"/>"#,
+            r#"<viz:position x="2" y="0" z="0" start="2" end="2"/>"#,
+            r#"<viz:position x="5" y="5" z="0" start="0"/>"#,
+            r#"<attvalue for="weight" value="2" start="0" end="0"/>"#,
+            r#"<attvalue for="weight" value="3" start="1" end="3"/>"#,
+            r#"<attvalue for="weight" value="1" start="5"/>"#,
+        ] {
+            assert!(file.contains(expected), "{expected} not in {file}");
+        }
+        // One node each for alice and bob, and one edge between them, with a gap in its spells.
+        assert_eq!(file.matches("<node ").count(), 2);
+        assert_eq!(file.matches("<edge ").count(), 1);
+        assert_eq!(file.matches("<spell ").count(), 5);
+
+        // The static reader sees every node and edge that ever existed.
+        let graph = gexf::read(file.as_bytes()).unwrap();
+        assert_eq!(graph.node_count(), 2);
+        assert_eq!(graph.edge_count(), 1);
+    }
+
+    #[test]
+    fn state_round_trip() {
+        let timeline = example();
+        let nodes = NodeTable::new(vec!["alice", "bob"]);
+        let mut state = Vec::new();
+        timeline.save_state(&mut state, &nodes).unwrap();
+        let loaded = Timeline::load_state(&mut state.as_slice(), &nodes).unwrap();
+        let (mut a, mut b) = (Vec::new(), Vec::new());
+        timeline.write(&mut a).unwrap();
+        loaded.write(&mut b).unwrap();
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn records_system() {
+        let buffer = SharedBuffer::new();
+        let mut system: System = System::new();
+        system.add_node(1, [10, 20, 30]);
+        system.add_node(2, None);
+        system.set_weight(1, 2, 5.);
+        system
+            .add_sink(DynamicGexf::new(buffer.clone()).unwrap())
+            .unwrap();
+        system.add_node(3, None);
+        system.set_weight(2, 3, 1.);
+        system.many_steps(3).unwrap();
+        system.remove_node(&3);
+        system.finish().unwrap();
+
+        let file = String::from_utf8(buffer.take()).unwrap();
+        assert!(file.contains(r#"<node id="3" label="3">"#));
+        assert!(file.contains(r#"<spell start="0" end="2"/>"#));
+        assert!(file.contains(r#"<attvalue for="weight" value="5" start="0"/>"#));
+        assert!(file.contains(r#"<attvalue for="weight" value="1" start="0" end="2"/>"#));
+    }
+}
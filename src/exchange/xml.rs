@@ -0,0 +1,24 @@
+//! Helpers shared by the XML-based formats.
+use crate::Result;
+use hashbrown::HashMap;
+use quick_xml::events::BytesStart;
+use std::borrow::Cow;
+
+pub use quick_xml::escape::escape;
+
+/// Get the local name of an element, without any namespace prefix.
+pub fn name(element: &BytesStart) -> String {
+    String::from_utf8_lossy(element.local_name().as_ref()).into_owned()
+}
+
+/// Get every attribute of an element, keyed by local name, without any namespace prefix.
+pub fn attributes(element: &BytesStart) -> Result<HashMap<String, String>> {
+    let mut attributes = HashMap::new();
+    for attribute in element.attributes() {
+        let attribute = attribute?;
+        let key = String::from_utf8_lossy(attribute.key.local_name().as_ref()).into_owned();
+        let value = attribute.unescape_value()?;
+        attributes.insert(key, Cow::into_owned(value));
+    }
+    Ok(attributes)
+}
@@ -1,5 +1,6 @@
 use hashbrown::HashMap;
 use std::hash::Hash;
+use std::rc::Rc;
 
 /// A trait for nodes in the graph, which allows obtaining a key to identify them by.
 pub trait HasKey {
@@ -20,6 +21,12 @@ pub trait HasKey {
 ///   initially with a weight of `W::default()`. This type must also implement `Clone` because edges
 ///   are stored twice, once for each endpoint. Note that `Option<T>` implements `Default`, so you
 ///   can use it to represent the concept of edges that may or may not exist.
+///
+/// Only edges whose weight has been set take up memory, and
+/// [`sparse_edges`](Self::sparse_edges), [`neighbours`](Self::neighbours) and
+/// [`edges_unique`](Self::edges_unique) iterate over just those, for graphs where most pairs of
+/// nodes are unrelated. [`remove_edge`](Self::remove_edge) returns an edge to the default weight and
+/// frees its memory.
 #[derive(Clone, Debug)]
 pub struct Graph<N: HasKey, W: Clone + Default> {
     // Nodes indexed by their key.
@@ -56,6 +63,11 @@ impl<N: HasKey, W: Clone + Default> Graph<N, W> {
         self.nodes.insert(node.key(), node);
     }
 
+    /// Check whether a node with the given key exists.
+    pub fn contains_node(&self, key: &N::Key) -> bool {
+        self.nodes.contains_key(key)
+    }
+
     /// Get a reference to a node in the graph.
     pub fn get_node(&self, key: &N::Key) -> Option<&N> {
         self.nodes.get(key)
@@ -77,20 +89,39 @@ impl<N: HasKey, W: Clone + Default> Graph<N, W> {
     }
 
     /// Get the total number of nodes in the graph.
+    #[must_use]
     pub fn node_count(&self) -> usize {
         self.nodes.len()
     }
 
-    /// Set the weight of an edge.
+    /// Set the weight of an edge, returning the previous weight.
     ///
     /// Edges are undirected, so the weight of the edge from `to` to `from` will also be set.
     ///
     /// # Panics
     ///
-    /// Panics if either of the nodes does not exist in the graph.
+    /// Panics if either of the nodes does not exist in the graph. See
+    /// [`try_set_weight`](Self::try_set_weight) for a non-panicking version.
     pub fn set_weight(&mut self, from: N::Key, to: N::Key, weight: W) -> W {
         assert!(self.nodes.contains_key(&from));
         assert!(self.nodes.contains_key(&to));
+        self.insert_weight(from, to, weight)
+    }
+
+    /// Set the weight of an edge, returning the previous weight.
+    ///
+    /// If either of the nodes does not exist in the graph, nothing is changed and `None` is
+    /// returned.
+    pub fn try_set_weight(&mut self, from: N::Key, to: N::Key, weight: W) -> Option<W> {
+        if self.nodes.contains_key(&from) && self.nodes.contains_key(&to) {
+            Some(self.insert_weight(from, to, weight))
+        } else {
+            None
+        }
+    }
+
+    // Store both copies of an edge, without checking that the nodes exist.
+    fn insert_weight(&mut self, from: N::Key, to: N::Key, weight: W) -> W {
         self.edges
             .entry(from.clone())
             .or_default()
@@ -128,6 +159,135 @@ impl<N: HasKey, W: Clone + Default> Graph<N, W> {
             })
     }
 
+    /// Iterate over only the edges of a given node whose weight has been set, in no particular
+    /// order.
+    ///
+    /// Unlike [`edges`](Self::edges), this treats the graph as sparse, so it takes time in
+    /// proportion to the number of edges the node actually has rather than the size of the graph.
+    /// Edges which have been explicitly set back to the default weight are still included, unless
+    /// they were removed with [`remove_edge`](Self::remove_edge).
+    pub fn sparse_edges(&self, key: &N::Key) -> impl Iterator<Item = (&N, &W)> {
+        self.edges
+            .get(key)
+            .into_iter()
+            .flatten()
+            .filter_map(|(sibling, weight)| Some((self.nodes.get(sibling)?, weight)))
+    }
+
+    /// Iterate over the nodes joined to a given node by an edge whose weight has been set, in no
+    /// particular order.
+    pub fn neighbours(&self, key: &N::Key) -> impl Iterator<Item = &N> {
+        self.sparse_edges(key).map(|(sibling, _)| sibling)
+    }
+
+    /// Iterate over every edge whose weight has been set, once each, in no particular order.
+    ///
+    /// Each edge is given as its two nodes and its weight. Which of the two nodes comes first is
+    /// arbitrary.
+    pub fn edges_unique(&self) -> impl Iterator<Item = (&N, &N, &W)> {
+        // Each edge is yielded from whichever of its nodes comes first in the map.
+        let order = Rc::new(
+            self.edges
+                .keys()
+                .enumerate()
+                .map(|(i, key)| (key, i))
+                .collect::<HashMap<_, _>>(),
+        );
+        self.edges.iter().flat_map(move |(from, siblings)| {
+            let order = Rc::clone(&order);
+            let position = order[from];
+            let node = self.nodes.get(from);
+            siblings.iter().filter_map(move |(to, weight)| {
+                if order.get(to).is_some_and(|&other| other < position) {
+                    None
+                } else {
+                    Some((node?, self.nodes.get(to)?, weight))
+                }
+            })
+        })
+    }
+
+    /// Get the number of edges whose weight has been set.
+    #[must_use]
+    pub fn edge_count(&self) -> usize {
+        // Every edge is stored twice, except an edge from a node to itself, which is stored once.
+        let (copies, loops) = self
+            .edges
+            .iter()
+            .fold((0, 0), |(copies, loops), (key, siblings)| {
+                (
+                    copies + siblings.len(),
+                    loops + usize::from(siblings.contains_key(key)),
+                )
+            });
+        (copies - loops) / 2 + loops
+    }
+
+    /// Remove an edge, returning its weight if it had been set.
+    ///
+    /// The edge goes back to having the default weight, and no longer takes up any memory.
+    pub fn remove_edge(&mut self, from: &N::Key, to: &N::Key) -> Option<W> {
+        let weight = self.remove_copy(from, to);
+        self.remove_copy(to, from);
+        weight
+    }
+
+    // Remove one of the two copies of an edge, dropping the map of edges for `from` if it is empty.
+    fn remove_copy(&mut self, from: &N::Key, to: &N::Key) -> Option<W> {
+        let siblings = self.edges.get_mut(from)?;
+        let weight = siblings.remove(to);
+        if siblings.is_empty() {
+            self.edges.remove(from);
+        }
+        weight
+    }
+
+    /// Remove every edge whose weight has been set for which `keep` returns `false`.
+    ///
+    /// `keep` is called once for each edge, with the same arguments as given by
+    /// [`edges_unique`](Self::edges_unique).
+    pub fn retain_edges(&mut self, mut keep: impl FnMut(&N, &N, &W) -> bool) {
+        let removed = self
+            .edges_unique()
+            .filter(|(from, to, weight)| !keep(from, to, weight))
+            .map(|(from, to, _)| (from.key(), to.key()))
+            .collect::<Vec<_>>();
+        for (from, to) in removed {
+            self.remove_edge(&from, &to);
+        }
+    }
+
+    /// Copy the given nodes into a new graph, along with every edge between them whose weight has
+    /// been set. Keys which aren't in this graph are ignored.
+    #[must_use]
+    pub fn subgraph<'a>(&self, keys: impl IntoIterator<Item = &'a N::Key>) -> Self
+    where
+        N: Clone,
+        N::Key: 'a,
+    {
+        let mut subgraph = Self::new();
+        for key in keys {
+            if let Some(node) = self.nodes.get(key) {
+                subgraph.add_node(node.clone());
+            }
+        }
+        for (from, siblings) in &self.edges {
+            if !subgraph.contains_node(from) {
+                continue;
+            }
+            for (to, weight) in siblings {
+                if subgraph.contains_node(to) {
+                    subgraph
+                        .edges
+                        .entry(from.clone())
+                        .or_default()
+                        .insert(to.clone(), weight.clone());
+                }
+            }
+        }
+        subgraph
+    }
+
     /// Remove a node from the graph, and return it if it existed.
     ///
     /// This will also remove all edges connected to the node.
@@ -136,10 +296,108 @@ impl<N: HasKey, W: Clone + Default> Graph<N, W> {
         if node.is_some() {
             if let Some(siblings) = self.edges.remove(key) {
                 for (sibling, _) in siblings {
-                    self.edges.get_mut(&sibling).unwrap().remove(key);
+                    self.remove_copy(&sibling, key);
                 }
             }
         }
         node
     }
 }
+
+#[cfg(test)]
+// Weights are only stored and copied here, never calculated, so they can be compared exactly.
+#[allow(clippy::float_cmp)]
+mod tests {
+    use super::{Graph, HasKey};
+
+    #[derive(Clone, Debug)]
+    struct Node(u32);
+
+    impl HasKey for Node {
+        type Key = u32;
+
+        fn key(&self) -> u32 {
+            self.0
+        }
+    }
+
+    /// Check that both copies of every edge exist and have the same weight, and that no edge
+    /// refers to a node which isn't in the graph.
+    fn assert_consistent(graph: &Graph<Node, f32>) {
+        for (from, siblings) in &graph.edges {
+            assert!(graph.contains_node(from), "edge from missing node {from}");
+            assert!(!siblings.is_empty(), "empty edge map for {from}");
+            for (to, weight) in siblings {
+                let reverse = graph.edges.get(to).and_then(|edges| edges.get(from));
+                assert_eq!(reverse, Some(weight), "edge {from}-{to} has diverged");
+            }
+        }
+        assert_eq!(graph.edges_unique().count(), graph.edge_count());
+    }
+
+    fn graph() -> Graph<Node, f32> {
+        let mut graph = Graph::new();
+        for i in 0..5 {
+            graph.add_node(Node(i));
+        }
+        graph.set_weight(0, 1, 1.);
+        graph.set_weight(1, 2, 2.);
+        graph.set_weight(2, 0, 3.);
+        graph.set_weight(3, 3, 4.);
+        graph.set_weight(3, 4, 5.);
+        graph
+    }
+
+    #[test]
+    fn unique_edges() {
+        let graph = graph();
+        assert_consistent(&graph);
+        assert_eq!(graph.edge_count(), 5);
+        let mut edges = graph
+            .edges_unique()
+            .map(|(from, to, &weight)| (from.0.min(to.0), from.0.max(to.0), weight))
+            .collect::<Vec<_>>();
+        edges.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(
+            edges,
+            [(0, 1, 1.), (0, 2, 3.), (1, 2, 2.), (3, 3, 4.), (3, 4, 5.)]
+        );
+        let mut neighbours = graph.neighbours(&2).map(|node| node.0).collect::<Vec<_>>();
+        neighbours.sort_unstable();
+        assert_eq!(neighbours, [0, 1]);
+    }
+
+    #[test]
+    fn removing_edges_keeps_copies_in_sync() {
+        let mut graph = graph();
+        assert_eq!(graph.remove_edge(&1, &0), Some(1.));
+        assert_eq!(graph.remove_edge(&0, &1), None);
+        assert_eq!(graph.remove_edge(&3, &3), Some(4.));
+        assert_consistent(&graph);
+        assert_eq!(graph.edge_count(), 3);
+        assert_eq!(graph.get_weight(&0, &1), 0.);
+
+        graph.retain_edges(|_, _, &weight| weight > 2.5);
+        assert_consistent(&graph);
+        assert_eq!(graph.edge_count(), 2);
+        assert_eq!(graph.get_weight(&2, &1), 0.);
+        assert_eq!(graph.get_weight(&0, &2), 3.);
+
+        graph.set_weight(4, 0, 6.);
+        graph.remove_node(&4);
+        assert_consistent(&graph);
+        assert_eq!(graph.edge_count(), 1);
+    }
+
+    #[test]
+    fn subgraphs() {
+        let graph = graph();
+        let subgraph = graph.subgraph(&[0, 2, 3, 9]);
+        assert_consistent(&subgraph);
+        assert_eq!(subgraph.node_count(), 3);
+        assert_eq!(subgraph.edge_count(), 2);
+        assert_eq!(subgraph.get_weight(&2, &0), 3.);
+        assert_eq!(subgraph.get_weight(&3, &3), 4.);
+        assert!(!subgraph.contains_node(&4));
+    }
+}
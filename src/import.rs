@@ -0,0 +1,429 @@
+//! Building a [`System`] from chat history.
+//!
+//! Importers for each chat platform read an export and record two things in a [`Timeline`]: when
+//! each user first appeared, and every interaction between two users. The timeline can then be
+//! turned into a time-ordered list of [`Event`]s, each holding an [`Op`] to apply to a system, so
+//! that the graph grows in the same order as the conversation did. A [`Scheduler`] decides how
+//! many steps to simulate between events.
+//!
+//! Importers for Discord, Slack and Matrix are enabled by the `discord`, `slack` and `matrix`
+//! features respectively.
+use crate::{NodeKey, Result, System};
+use hashbrown::HashMap;
+
+#[cfg(feature = "discord")]
+pub mod discord;
+#[cfg(feature = "matrix")]
+pub mod matrix;
+mod rules;
+mod schedule;
+#[cfg(feature = "slack")]
+pub mod slack;
+
+pub use rules::{Rule, Rules};
+pub use schedule::Scheduler;
+
+/// A change to make to a [`System`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Op<K> {
+    /// Add a node for a user who has just appeared. If the user's colour is not known, such as
+    /// when they have no coloured role, the system derives one from their ID.
+    AddNode { id: K, colour: Option<[u8; 3]> },
+    /// Set the weight of the edge between two users to a new total.
+    SetWeight { from: K, to: K, weight: f32 },
+}
+
+impl<K: NodeKey> Op<K> {
+    /// Apply the operation to a system.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the node being added already exists, if an edge refers to a node which
+    /// does not exist, or if any sink fails.
+    pub fn apply(&self, system: &mut System<K>) -> Result<()> {
+        match self {
+            Self::AddNode { id, colour } => system.try_add_node(id.clone(), *colour).map(drop),
+            Self::SetWeight { from, to, weight } => {
+                system.try_set_weight(from.clone(), to.clone(), *weight)
+            }
+        }
+    }
+}
+
+/// An operation, along with the time it happened at in milliseconds since the Unix epoch.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Event<K> {
+    pub time: i64,
+    pub op: Op<K>,
+}
+
+/// The ways in which one user can interact with another.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum InteractionKind {
+    /// One user replied to a message by the other.
+    Reply,
+    /// One user mentioned the other in a message.
+    Mention,
+    /// One user reacted to a message by the other.
+    Reaction,
+    /// One user posted in a thread the other had already posted in. This is counted once per
+    /// thread for each pair of users.
+    Thread,
+    /// One user posted in a channel shortly after the other. See [`Rules::proximity_window`].
+    Proximity,
+}
+
+/// A single interaction from one user to another.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Interaction<K> {
+    /// When the interaction happened, in milliseconds since the Unix epoch.
+    pub time: i64,
+    pub from: K,
+    pub to: K,
+    pub kind: InteractionKind,
+}
+
+#[derive(Clone, Debug)]
+struct User {
+    first_seen: i64,
+    colour: Option<[u8; 3]>,
+}
+
+#[derive(Clone, Debug)]
+struct Message<K> {
+    time: i64,
+    author: K,
+    // An index into `Timeline::channels`.
+    channel: usize,
+}
+
+#[derive(Clone, Debug)]
+struct Channel {
+    name: String,
+    thread: bool,
+}
+
+/// Everything an importer has learned about a conversation, which may come from several exports.
+///
+/// Nothing needs to be recorded in order, since [`events`](Self::events) sorts everything by time.
+#[derive(Clone, Debug)]
+pub struct Timeline<K> {
+    // Users are kept in the order they were first recorded, so that users who appear at the same
+    // time are always added to the system in the same order.
+    order: Vec<K>,
+    users: HashMap<K, User>,
+    interactions: Vec<Interaction<K>>,
+    messages: Vec<Message<K>>,
+    channels: Vec<Channel>,
+}
+
+impl<K: NodeKey> Default for Timeline<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: NodeKey> Timeline<K> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            order: Vec::new(),
+            users: HashMap::new(),
+            interactions: Vec::new(),
+            messages: Vec::new(),
+            channels: Vec::new(),
+        }
+    }
+
+    /// Record that a user was seen at a given time, with their colour if it is known.
+    ///
+    /// The earliest time a user is seen is used as the time they join the graph, and the first
+    /// colour recorded for them is kept.
+    pub fn see_user(&mut self, time: i64, id: K, colour: Option<[u8; 3]>) {
+        if let Some(user) = self.users.get_mut(&id) {
+            user.first_seen = user.first_seen.min(time);
+            user.colour = user.colour.or(colour);
+        } else {
+            self.order.push(id.clone());
+            self.users.insert(
+                id,
+                User {
+                    first_seen: time,
+                    colour,
+                },
+            );
+        }
+    }
+
+    /// Set the colour of a user who has already been seen, replacing any colour recorded so far.
+    /// Nothing happens if the user has not been seen.
+    pub fn set_colour(&mut self, id: &K, colour: [u8; 3]) {
+        if let Some(user) = self.users.get_mut(id) {
+            user.colour = Some(colour);
+        }
+    }
+
+    /// Record an interaction between two users. Both users are also recorded as seen at the time
+    /// of the interaction, and users interacting with themselves are ignored.
+    pub fn interact(&mut self, time: i64, from: K, to: K, kind: InteractionKind) {
+        if from == to {
+            return;
+        }
+        self.see_user(time, from.clone(), None);
+        self.see_user(time, to.clone(), None);
+        self.interactions.push(Interaction {
+            time,
+            from,
+            to,
+            kind,
+        });
+    }
+
+    /// Record that a user posted a message in a channel, which may be a thread (see
+    /// [`mark_thread`](Self::mark_thread)). The user is also recorded as seen at that time.
+    ///
+    /// Messages are used to find [`InteractionKind::Thread`] and [`InteractionKind::Proximity`]
+    /// interactions. Channels can be named anything, as long as the names are unique.
+    pub fn message(&mut self, time: i64, author: K, channel: &str) {
+        self.see_user(time, author.clone(), None);
+        let channel = self.channel_index(channel);
+        self.messages.push(Message {
+            time,
+            author,
+            channel,
+        });
+    }
+
+    /// Record that a channel is a thread, so that everyone who posts in it interacts with each
+    /// other.
+    pub fn mark_thread(&mut self, channel: &str) {
+        let channel = self.channel_index(channel);
+        self.channels[channel].thread = true;
+    }
+
+    fn channel_index(&mut self, name: &str) -> usize {
+        if let Some(index) = self
+            .channels
+            .iter()
+            .position(|channel| channel.name == name)
+        {
+            index
+        } else {
+            self.channels.push(Channel {
+                name: name.to_owned(),
+                thread: false,
+            });
+            self.channels.len() - 1
+        }
+    }
+
+    /// Get every interaction recorded so far with [`interact`](Self::interact), in the order they
+    /// were recorded. This does not include interactions found from messages.
+    #[must_use]
+    pub fn interactions(&self) -> &[Interaction<K>] {
+        &self.interactions
+    }
+
+    /// Get the number of users recorded so far.
+    #[must_use]
+    pub const fn user_count(&self) -> usize {
+        self.order.len()
+    }
+
+    /// Turn the timeline into operations ordered by time.
+    ///
+    /// Each user is added when they are first seen, and every interaction which changes the weight
+    /// of the edge between two users sets it to the new total, as scored by `rules`.
+    #[must_use]
+    pub fn events(&self, rules: &Rules) -> Vec<Event<K>> {
+        let mut events = Vec::with_capacity(self.order.len() + self.interactions.len());
+        for id in &self.order {
+            let user = &self.users[id];
+            events.push(Event {
+                time: user.first_seen,
+                op: Op::AddNode {
+                    id: id.clone(),
+                    colour: user.colour,
+                },
+            });
+        }
+        events.extend(rules.score(self));
+        // The sort is stable, so users are still added before any interactions at the same time.
+        events.sort_by_key(|event| event.time);
+        events
+    }
+}
+
+/// Parse an ISO 8601 timestamp, such as `2021-03-04T05:06:07.890+01:00`, into milliseconds since
+/// the Unix epoch.
+///
+/// Only the complete date and time format used by chat exports is supported. Digits beyond
+/// milliseconds are ignored, and a missing offset is treated as UTC.
+#[must_use]
+pub fn parse_timestamp(timestamp: &str) -> Option<i64> {
+    fn number(s: &str) -> Option<i64> {
+        if s.bytes().all(|b| b.is_ascii_digit()) {
+            s.parse().ok()
+        } else {
+            None
+        }
+    }
+
+    let (date, time) = timestamp.split_once(['T', ' '])?;
+    let mut date_parts = date.splitn(3, '-');
+    let year = number(date_parts.next()?)?;
+    let month = number(date_parts.next()?)?;
+    let day = number(date_parts.next()?)?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let (time, offset) = if let Some(time) = time.strip_suffix(['Z', 'z']) {
+        (time, 0)
+    } else if let Some(index) = time.rfind(['+', '-']) {
+        let (time, offset) = time.split_at(index);
+        let sign = if offset.starts_with('-') { -1 } else { 1 };
+        let (hours, minutes) = match offset[1..].split_once(':') {
+            Some(parts) => parts,
+            // The basic format leaves out the colon, as in `+0100`.
+            None if offset.len() == 5 => (offset.get(1..3)?, offset.get(3..)?),
+            None => (&offset[1..], "00"),
+        };
+        if hours.len() != 2 || minutes.len() != 2 {
+            return None;
+        }
+        let (hours, minutes) = (number(hours)?, number(minutes)?);
+        if hours > 23 || minutes > 59 {
+            return None;
+        }
+        (time, sign * (hours * 60 + minutes))
+    } else {
+        (time, 0)
+    };
+    let (time, fraction) = time.split_once('.').unwrap_or((time, ""));
+    let mut time_parts = time.splitn(3, ':');
+    let hour = number(time_parts.next()?)?;
+    let minute = number(time_parts.next()?)?;
+    let second = number(time_parts.next()?)?;
+    let millis = parse_millis(fraction)?;
+
+    let days = days_from_civil(year, month, day);
+    let seconds = ((days * 24 + hour) * 60 + minute - offset) * 60 + second;
+    Some(seconds * 1000 + millis)
+}
+
+/// Parse the digits after the decimal point in a number of seconds into milliseconds, ignoring
+/// any digits beyond the third.
+pub(crate) fn parse_millis(fraction: &str) -> Option<i64> {
+    // Every byte is checked first, so that slicing can't split a character.
+    if !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let digits = &fraction[..fraction.len().min(3)];
+    if digits.is_empty() {
+        Some(0)
+    } else {
+        Some(digits.parse::<i64>().ok()? * 10_i64.pow(3 - digits.len() as u32))
+    }
+}
+
+/// Parse a colour written in hexadecimal as `#rrggbb`, or `rrggbb` as Slack does.
+#[must_use]
+pub fn parse_colour(colour: &str) -> Option<[u8; 3]> {
+    let hex = colour.strip_prefix('#').unwrap_or(colour);
+    if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    let [_, r, g, b] = value.to_be_bytes();
+    Some([r, g, b])
+}
+
+/// Get the number of days between the Unix epoch and a date in the proleptic Gregorian calendar.
+const fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    // See http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_index = (month + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_timestamp, InteractionKind, Op, Rules, Timeline};
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(parse_timestamp("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(
+            parse_timestamp("2015-01-01T00:00:00.000+00:00"),
+            Some(1_420_070_400_000)
+        );
+        assert_eq!(
+            parse_timestamp("2021-03-04T05:06:07.89+01:00"),
+            Some(1_614_830_767_890)
+        );
+        assert_eq!(
+            parse_timestamp("2021-03-04T04:06:07.8901234Z"),
+            Some(1_614_830_767_890)
+        );
+        assert_eq!(
+            parse_timestamp("2021-03-04T05:06:07+0100"),
+            Some(1_614_830_767_000)
+        );
+        assert_eq!(
+            parse_timestamp("2021-03-04T05:06:07+01"),
+            Some(1_614_830_767_000)
+        );
+        assert_eq!(
+            parse_timestamp("2021-03-03T22:36:07-05:30"),
+            Some(1_614_830_767_000)
+        );
+        assert_eq!(parse_timestamp("2021-03-04T05:06:07+24:00"), None);
+        assert_eq!(parse_timestamp("2021-03-04T05:06:07+01:60"), None);
+        assert_eq!(parse_timestamp("2021-03-04T05:06:07+100"), None);
+        assert_eq!(parse_timestamp("1970-01-01T00:00:00.\u{e9}\u{e9}"), None);
+        assert_eq!(parse_timestamp("2021-13-04T04:06:07Z"), None);
+        assert_eq!(parse_timestamp("yesterday"), None);
+    }
+
+    #[test]
+    fn events_are_ordered() {
+        let mut timeline = Timeline::new();
+        timeline.interact(30, 2, 1, InteractionKind::Mention);
+        timeline.see_user(10, 1, Some([255, 0, 0]));
+        timeline.interact(20, 1, 2, InteractionKind::Reply);
+        timeline.interact(40, 3, 3, InteractionKind::Reaction);
+        let ops = timeline
+            .events(&Rules::default())
+            .into_iter()
+            .map(|event| event.op)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ops,
+            vec![
+                Op::AddNode {
+                    id: 1,
+                    colour: Some([255, 0, 0])
+                },
+                Op::AddNode {
+                    id: 2,
+                    colour: None
+                },
+                Op::SetWeight {
+                    from: 1,
+                    to: 2,
+                    weight: 30.
+                },
+                Op::SetWeight {
+                    from: 2,
+                    to: 1,
+                    weight: 50.
+                },
+            ]
+        );
+    }
+}
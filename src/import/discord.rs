@@ -0,0 +1,239 @@
+//! Importing [DiscordChatExporter](https://github.com/Tyrrrz/DiscordChatExporter) JSON exports.
+//!
+//! Users are identified by their Discord IDs, and coloured by their highest coloured role. Each
+//! reply, mention and reaction is recorded as an [`Interaction`](super::Interaction), and every
+//! message is recorded so that [`Rules`](super::Rules) can find who was talking together. Threads
+//! are exported as channels of their own, and are marked as threads. Reactions are only included
+//! if the export lists who reacted, which recent versions of the exporter do.
+use super::{parse_colour, parse_timestamp, InteractionKind, Timeline};
+use crate::snowflake::Snowflake;
+use crate::{Error, Result};
+use hashbrown::HashMap;
+use serde_json::Value;
+use std::io::Read;
+
+/// Reads any number of exports into a single [`Timeline`].
+///
+/// Replies are resolved once every export has been read, so a reply to a message in another
+/// export (such as a thread replying to its parent channel) is still counted.
+#[derive(Clone, Debug, Default)]
+pub struct Importer {
+    timeline: Timeline<u64>,
+    // The author of every message seen so far, by message ID.
+    authors: HashMap<u64, u64>,
+    // Replies as (time, author, ID of the message replied to).
+    replies: Vec<(i64, u64, u64)>,
+}
+
+impl Importer {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read one exported channel.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Json`] if the export is not valid JSON, or [`Error::Import`] if it does
+    /// not have the structure of a `DiscordChatExporter` export.
+    pub fn read(&mut self, r: impl Read) -> Result<()> {
+        let export: Value = serde_json::from_reader(r)?;
+        let channel = export["channel"]["id"]
+            .as_str()
+            .ok_or(Error::Import("export has no channel"))?;
+        if export["channel"]["type"]
+            .as_str()
+            .is_some_and(|kind| kind.contains("Thread"))
+        {
+            self.timeline.mark_thread(channel);
+        }
+        let messages = export["messages"]
+            .as_array()
+            .ok_or(Error::Import("export has no messages"))?;
+        for message in messages {
+            self.read_message(channel, message)?;
+        }
+        Ok(())
+    }
+
+    fn read_message(&mut self, channel: &str, message: &Value) -> Result<()> {
+        let id = snowflake(&message["id"]).ok_or(Error::Import("message has no ID"))?;
+        // Every message ID includes the time it was sent, which is used if the timestamp is
+        // missing. The timestamp is preferred, since it is exactly what the exporter saw.
+        let time = message["timestamp"]
+            .as_str()
+            .map_or_else(|| Some(Snowflake(id).timestamp()), parse_timestamp)
+            .ok_or(Error::Import("message has an invalid timestamp"))?;
+        let author = &message["author"];
+        let author_id = snowflake(&author["id"]).ok_or(Error::Import("message has no author"))?;
+        self.timeline.see_user(time, author_id, user_colour(author));
+        self.timeline.message(time, author_id, channel);
+        self.authors.insert(id, author_id);
+
+        if let Some(reply_to) = snowflake(&message["reference"]["messageId"]) {
+            self.replies.push((time, author_id, reply_to));
+        }
+        for mentioned in message["mentions"].as_array().into_iter().flatten() {
+            if let Some(mentioned_id) = snowflake(&mentioned["id"]) {
+                self.timeline
+                    .see_user(time, mentioned_id, user_colour(mentioned));
+                self.timeline
+                    .interact(time, author_id, mentioned_id, InteractionKind::Mention);
+            }
+        }
+        let reactions = message["reactions"].as_array().into_iter().flatten();
+        for user in
+            reactions.flat_map(|reaction| reaction["users"].as_array().into_iter().flatten())
+        {
+            if let Some(user_id) = snowflake(&user["id"]) {
+                self.timeline.see_user(time, user_id, user_colour(user));
+                self.timeline
+                    .interact(time, user_id, author_id, InteractionKind::Reaction);
+            }
+        }
+        Ok(())
+    }
+
+    /// Finish reading exports, and get the resulting timeline.
+    ///
+    /// Replies to messages which were not in any of the exports are ignored.
+    #[must_use]
+    pub fn finish(mut self) -> Timeline<u64> {
+        for (time, author, reply_to) in self.replies {
+            if let Some(&replied_author) = self.authors.get(&reply_to) {
+                self.timeline
+                    .interact(time, author, replied_author, InteractionKind::Reply);
+            }
+        }
+        self.timeline
+    }
+}
+
+/// Read a Discord ID, which the exporter writes as a string.
+fn snowflake(value: &Value) -> Option<u64> {
+    value.as_str()?.parse().ok()
+}
+
+/// Get the colour a user is shown in, which is the colour of their highest coloured role.
+///
+/// The exporter includes this directly for message authors. For other users, it is found from
+/// their roles if those are listed.
+fn user_colour(user: &Value) -> Option<[u8; 3]> {
+    if let Some(colour) = user["color"].as_str().and_then(parse_colour) {
+        return Some(colour);
+    }
+    user["roles"]
+        .as_array()?
+        .iter()
+        .filter_map(|role| {
+            Some((
+                role["position"].as_i64()?,
+                parse_colour(role["color"].as_str()?)?,
+            ))
+        })
+        .max_by_key(|(position, _)| *position)
+        .map(|(_, colour)| colour)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Importer;
+    use crate::import::{Op, Rules};
+
+    const EXPORT: &str = r##"{
+        "guild": { "id": "1", "name": "Snowball" },
+        "channel": { "id": "2", "type": "GuildTextChat", "name": "general" },
+        "messages": [
+            {
+                "id": "100",
+                "type": "Default",
+                "timestamp": "2022-01-01T12:00:00+00:00",
+                "content": "hello",
+                "author": { "id": "10", "name": "alice", "color": "#B58900" },
+                "reactions": [
+                    { "emoji": { "name": "👋" }, "count": 1, "users": [{ "id": "20", "name": "bob" }] }
+                ],
+                "mentions": []
+            },
+            {
+                "id": "101",
+                "type": "Reply",
+                "timestamp": "2022-01-01T12:01:00+00:00",
+                "content": "hi @carol",
+                "author": {
+                    "id": "20",
+                    "name": "bob",
+                    "color": null,
+                    "roles": [
+                        { "id": "5", "name": "low", "color": "#000001", "position": 1 },
+                        { "id": "6", "name": "high", "color": "#CB4B16", "position": 2 }
+                    ]
+                },
+                "reactions": [],
+                "mentions": [{ "id": "30", "name": "carol" }],
+                "reference": { "messageId": "100", "channelId": "2", "guildId": "1" }
+            }
+        ]
+    }"##;
+
+    #[test]
+    fn reads_export() {
+        let mut importer = Importer::new();
+        importer.read(EXPORT.as_bytes()).unwrap();
+        let ops = importer
+            .finish()
+            .events(&Rules::default())
+            .into_iter()
+            .map(|event| event.op)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ops,
+            vec![
+                Op::AddNode {
+                    id: 10,
+                    colour: Some([181, 137, 0])
+                },
+                // Bob is first seen reacting, before their own message gives their role colour.
+                Op::AddNode {
+                    id: 20,
+                    colour: Some([203, 75, 22])
+                },
+                Op::SetWeight {
+                    from: 20,
+                    to: 10,
+                    weight: 10.
+                },
+                Op::AddNode {
+                    id: 30,
+                    colour: None
+                },
+                Op::SetWeight {
+                    from: 20,
+                    to: 30,
+                    weight: 20.
+                },
+                Op::SetWeight {
+                    from: 20,
+                    to: 10,
+                    weight: 40.
+                },
+                // Bob also posted within a minute of Alice.
+                Op::SetWeight {
+                    from: 20,
+                    to: 10,
+                    weight: 45.
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_exports() {
+        assert!(Importer::new().read(&b"{"[..]).is_err());
+        assert!(Importer::new().read(&b"{}"[..]).is_err());
+        assert!(Importer::new()
+            .read(&br#"{ "channel": { "id": "1" }, "messages": [{ "id": "2" }] }"#[..])
+            .is_err());
+    }
+}
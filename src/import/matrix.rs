@@ -0,0 +1,281 @@
+//! Importing Matrix room event dumps.
+//!
+//! A dump is a JSON list of room events in the format used by the client-server API. This can be
+//! the list itself, a response from the `/messages` endpoint (with the events in `chunk`), or a
+//! JSON export from Element (with the events in `messages`). Users are identified by their Matrix
+//! IDs, such as `@alice:example.org`. Matrix has no user colours, so every user's colour is derived
+//! from their ID.
+//!
+//! Replies, mentions and reactions are recorded as interactions, and each thread is recorded as a
+//! channel of its own, named after the event ID of its first message.
+use super::{InteractionKind, Timeline};
+use crate::{Error, Result};
+use hashbrown::{HashMap, HashSet};
+use serde_json::Value;
+use std::io::Read;
+
+/// The prefix of links to users in the HTML body of a message, which is how older clients
+/// mention people.
+const USER_LINK: &str = "https://matrix.to/#/@";
+
+/// Reads any number of room dumps into a single [`Timeline`].
+///
+/// Replies and reactions are resolved once every dump has been read, so they can refer to events
+/// in other dumps.
+#[derive(Clone, Debug, Default)]
+pub struct Importer {
+    timeline: Timeline<String>,
+    // The sender and time of every message seen so far, by event ID.
+    messages: HashMap<String, (String, i64)>,
+    // Replies and reactions as (time, sender, kind, ID of the event they refer to).
+    relations: Vec<(i64, String, InteractionKind, String)>,
+    // The event IDs of the first message in every thread.
+    threads: HashSet<String>,
+}
+
+impl Importer {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read one dump of room events.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Json`] if the dump is not valid JSON, or [`Error::Import`] if it does not
+    /// contain a list of events, or an event is missing its sender or timestamp.
+    pub fn read(&mut self, r: impl Read) -> Result<()> {
+        let dump: Value = serde_json::from_reader(r)?;
+        let events = dump
+            .as_array()
+            .or_else(|| dump["chunk"].as_array())
+            .or_else(|| dump["messages"].as_array())
+            .ok_or(Error::Import("dump has no events"))?;
+        for event in events {
+            self.read_event(event)?;
+        }
+        Ok(())
+    }
+
+    fn read_event(&mut self, event: &Value) -> Result<()> {
+        let sender = event["sender"]
+            .as_str()
+            .ok_or(Error::Import("event has no sender"))?;
+        let time = event["origin_server_ts"]
+            .as_i64()
+            .ok_or(Error::Import("event has no timestamp"))?;
+        let content = &event["content"];
+        let relation = &content["m.relates_to"];
+        match event["type"].as_str().unwrap_or_default() {
+            "m.room.member" if content["membership"] == "join" => {
+                let member = event["state_key"].as_str().unwrap_or(sender);
+                self.timeline.see_user(time, member.to_owned(), None);
+            }
+            // Edits are sent as new messages, but aren't new activity.
+            "m.room.message" if relation["rel_type"] != "m.replace" => {
+                self.read_message(event, sender, time);
+            }
+            "m.reaction" if relation["rel_type"] == "m.annotation" => {
+                if let Some(target) = relation["event_id"].as_str() {
+                    self.relations.push((
+                        time,
+                        sender.to_owned(),
+                        InteractionKind::Reaction,
+                        target.to_owned(),
+                    ));
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn read_message(&mut self, event: &Value, sender: &str, time: i64) {
+        let content = &event["content"];
+        let relation = &content["m.relates_to"];
+        if let Some(id) = event["event_id"].as_str() {
+            self.messages
+                .insert(id.to_owned(), (sender.to_owned(), time));
+        }
+
+        if relation["rel_type"] == "m.thread" {
+            if let Some(root) = relation["event_id"].as_str() {
+                self.timeline.mark_thread(root);
+                self.timeline.message(time, sender.to_owned(), root);
+                self.threads.insert(root.to_owned());
+            }
+        } else {
+            let room = event["room_id"].as_str().unwrap_or_default();
+            self.timeline.message(time, sender.to_owned(), room);
+        }
+
+        // Clients include a reply to the previous message in a thread for the benefit of clients
+        // which don't support threads, but that isn't really a reply.
+        if relation["is_falling_back"] != true {
+            if let Some(reply_to) = relation["m.in_reply_to"]["event_id"].as_str() {
+                self.relations.push((
+                    time,
+                    sender.to_owned(),
+                    InteractionKind::Reply,
+                    reply_to.to_owned(),
+                ));
+            }
+        }
+
+        // Newer clients list mentions explicitly, while older ones only link to the users.
+        let mentioned = content["m.mentions"]["user_ids"].as_array().map_or_else(
+            || {
+                content["formatted_body"]
+                    .as_str()
+                    .map(linked_users)
+                    .unwrap_or_default()
+            },
+            |user_ids| {
+                user_ids
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .map(str::to_owned)
+                    .collect()
+            },
+        );
+        for user in mentioned {
+            self.timeline
+                .interact(time, sender.to_owned(), user, InteractionKind::Mention);
+        }
+    }
+
+    /// Finish reading dumps, and get the resulting timeline.
+    ///
+    /// Replies and reactions to messages which were not in any of the dumps are ignored.
+    #[must_use]
+    pub fn finish(mut self) -> Timeline<String> {
+        for (time, sender, kind, target) in self.relations {
+            if let Some((target_sender, _)) = self.messages.get(&target) {
+                self.timeline
+                    .interact(time, sender, target_sender.clone(), kind);
+            }
+        }
+        // The first message in a thread is sent to the room, but its sender takes part in the
+        // thread too.
+        for root in &self.threads {
+            if let Some((sender, time)) = self.messages.get(root) {
+                self.timeline.message(*time, sender.clone(), root);
+            }
+        }
+        self.timeline
+    }
+}
+
+/// Find the users linked to in the HTML body of a message.
+fn linked_users(html: &str) -> Vec<String> {
+    html.split(USER_LINK)
+        .skip(1)
+        .filter_map(|rest| {
+            let end = rest.find(['"', '\'', '?'])?;
+            let user = rest[..end].replace("%3A", ":").replace("%3a", ":");
+            Some(format!("@{user}"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{linked_users, Importer};
+    use crate::import::{Op, Rules};
+
+    const DUMP: &str = r#"{
+        "room_name": "Snowball",
+        "messages": [
+            {
+                "type": "m.room.member", "sender": "@alice:example.org",
+                "state_key": "@alice:example.org", "origin_server_ts": 1000,
+                "content": { "membership": "join" }
+            },
+            {
+                "type": "m.room.message", "event_id": "$1", "room_id": "!room",
+                "sender": "@alice:example.org", "origin_server_ts": 2000,
+                "content": { "msgtype": "m.text", "body": "hello" }
+            },
+            {
+                "type": "m.reaction", "event_id": "$2", "room_id": "!room",
+                "sender": "@bob:example.org", "origin_server_ts": 300000,
+                "content": {
+                    "m.relates_to": { "rel_type": "m.annotation", "event_id": "$1", "key": "👋" }
+                }
+            },
+            {
+                "type": "m.room.message", "event_id": "$3", "room_id": "!room",
+                "sender": "@bob:example.org", "origin_server_ts": 600000,
+                "content": {
+                    "msgtype": "m.text", "body": "hi",
+                    "m.relates_to": { "m.in_reply_to": { "event_id": "$1" } }
+                }
+            },
+            {
+                "type": "m.room.message", "event_id": "$4", "room_id": "!room",
+                "sender": "@bob:example.org", "origin_server_ts": 900000,
+                "content": {
+                    "msgtype": "m.text", "body": "* hi!",
+                    "m.relates_to": { "rel_type": "m.replace", "event_id": "$3" }
+                }
+            },
+            {
+                "type": "m.room.message", "event_id": "$5", "room_id": "!room",
+                "sender": "@carol:example.org", "origin_server_ts": 1200000,
+                "content": {
+                    "msgtype": "m.text", "body": "welcome",
+                    "m.relates_to": {
+                        "rel_type": "m.thread", "event_id": "$1",
+                        "is_falling_back": true, "m.in_reply_to": { "event_id": "$3" }
+                    },
+                    "m.mentions": { "user_ids": ["@bob:example.org"] }
+                }
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn reads_dump() {
+        let mut importer = Importer::new();
+        importer.read(DUMP.as_bytes()).unwrap();
+        let timeline = importer.finish();
+        assert_eq!(timeline.user_count(), 3);
+
+        let rules = Rules::default();
+        let ops = timeline
+            .events(&rules)
+            .into_iter()
+            .filter_map(|event| match event.op {
+                Op::SetWeight { from, to, weight } => Some((from, to, weight)),
+                Op::AddNode { .. } => None,
+            })
+            .collect::<Vec<_>>();
+        let alice = "@alice:example.org".to_owned();
+        let bob = "@bob:example.org".to_owned();
+        let carol = "@carol:example.org".to_owned();
+        assert_eq!(
+            ops,
+            vec![
+                (bob.clone(), alice.clone(), rules.reaction.weight),
+                (
+                    bob.clone(),
+                    alice.clone(),
+                    rules.reaction.weight + rules.reply.weight
+                ),
+                (carol.clone(), bob, rules.mention.weight),
+                // Carol replied in the thread started by Alice's message.
+                (carol, alice, rules.thread.weight),
+            ]
+        );
+    }
+
+    #[test]
+    fn html_mentions() {
+        let html = r#"<a href="https://matrix.to/#/@bob:example.org">Bob</a> and <a href='https://matrix.to/#/@carol%3Aexample.org?via=x'>Carol</a>"#;
+        assert_eq!(
+            linked_users(html),
+            vec!["@bob:example.org", "@carol:example.org"]
+        );
+    }
+}
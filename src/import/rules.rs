@@ -0,0 +1,308 @@
+use super::{Event, Interaction, InteractionKind, Op, Timeline};
+use crate::NodeKey;
+use hashbrown::HashMap;
+
+/// How one kind of interaction contributes to the weight of an edge.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rule {
+    /// How much each interaction adds to the weight of the edge between the two users.
+    pub weight: f32,
+    /// The most that interactions of this kind can add to the weight of any one edge, if limited.
+    pub cap: Option<f32>,
+}
+
+impl Rule {
+    /// A rule which adds `weight` for every interaction, up to an optional total of `cap`.
+    #[must_use]
+    pub const fn new(weight: f32, cap: Option<f32>) -> Self {
+        Self { weight, cap }
+    }
+
+    /// A rule which ignores interactions of its kind entirely.
+    #[must_use]
+    pub const fn ignore() -> Self {
+        Self::new(0., None)
+    }
+}
+
+/// Decides how much each interaction between two users counts for.
+///
+/// Every interaction adds the [`Rule::weight`] for its kind to the edge between the two users,
+/// regardless of direction, until the [`Rule::cap`] for that kind is reached. The weight of an edge
+/// is the sum of what each kind contributes, limited to [`max_weight`](Self::max_weight).
+///
+/// The defaults favour direct interactions, and cap the indirect ones so that people who are merely
+/// active in the same channels do not end up as close as people who talk to each other.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rules {
+    pub reply: Rule,
+    pub mention: Rule,
+    pub reaction: Rule,
+    pub thread: Rule,
+    pub proximity: Rule,
+    /// How close together two messages in the same channel must be, in milliseconds, to count as
+    /// an [`InteractionKind::Proximity`] interaction.
+    pub proximity_window: i64,
+    /// The most the weight of any edge can reach, if limited.
+    pub max_weight: Option<f32>,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            reply: Rule::new(30., None),
+            mention: Rule::new(20., None),
+            reaction: Rule::new(10., Some(200.)),
+            thread: Rule::new(20., None),
+            proximity: Rule::new(5., Some(200.)),
+            proximity_window: 60_000,
+            max_weight: None,
+        }
+    }
+}
+
+impl Rules {
+    /// Get the rule for a kind of interaction.
+    #[must_use]
+    pub const fn rule(&self, kind: InteractionKind) -> Rule {
+        match kind {
+            InteractionKind::Reply => self.reply,
+            InteractionKind::Mention => self.mention,
+            InteractionKind::Reaction => self.reaction,
+            InteractionKind::Thread => self.thread,
+            InteractionKind::Proximity => self.proximity,
+        }
+    }
+
+    /// Find the interactions implied by the messages in a timeline.
+    fn message_interactions<K: NodeKey>(&self, timeline: &Timeline<K>) -> Vec<Interaction<K>> {
+        let mut messages = timeline.messages.iter().collect::<Vec<_>>();
+        messages.sort_by_key(|message| message.time);
+
+        let mut interactions = Vec::new();
+        // The users who have posted in each thread so far.
+        let mut participants = HashMap::<usize, Vec<K>>::new();
+        // The last time each user posted in each channel, for those who have posted recently.
+        let mut recent = HashMap::<usize, Vec<(K, i64)>>::new();
+        for message in messages {
+            if timeline.channels[message.channel].thread {
+                let participants = participants.entry(message.channel).or_default();
+                if !participants.contains(&message.author) {
+                    for other in participants.iter() {
+                        interactions.push(Interaction {
+                            time: message.time,
+                            from: message.author.clone(),
+                            to: other.clone(),
+                            kind: InteractionKind::Thread,
+                        });
+                    }
+                    participants.push(message.author.clone());
+                }
+            }
+
+            let recent = recent.entry(message.channel).or_default();
+            recent.retain(|(_, time)| message.time - time <= self.proximity_window);
+            for (other, _) in recent.iter() {
+                if *other != message.author {
+                    interactions.push(Interaction {
+                        time: message.time,
+                        from: message.author.clone(),
+                        to: other.clone(),
+                        kind: InteractionKind::Proximity,
+                    });
+                }
+            }
+            if let Some(entry) = recent.iter_mut().find(|(id, _)| *id == message.author) {
+                entry.1 = message.time;
+            } else {
+                recent.push((message.author.clone(), message.time));
+            }
+        }
+        interactions
+    }
+
+    /// Score every interaction in a timeline, in time order, returning an event whenever the
+    /// weight of an edge changes.
+    pub(super) fn score<K: NodeKey>(&self, timeline: &Timeline<K>) -> Vec<Event<K>> {
+        let derived = self.message_interactions(timeline);
+        let mut interactions = timeline
+            .interactions
+            .iter()
+            .chain(&derived)
+            .collect::<Vec<_>>();
+        interactions.sort_by_key(|interaction| interaction.time);
+
+        // The total contributed by each kind of interaction, for each pair of users, indexed by
+        // `InteractionKind as usize`.
+        let mut totals = HashMap::<(K, K), [f32; 5]>::new();
+        let mut events = Vec::new();
+        for interaction in interactions {
+            let rule = self.rule(interaction.kind);
+            if rule.weight == 0. {
+                continue;
+            }
+            let reversed = (interaction.to.clone(), interaction.from.clone());
+            let pair = if totals.contains_key(&reversed) {
+                reversed
+            } else {
+                (interaction.from.clone(), interaction.to.clone())
+            };
+            let kinds = totals.entry(pair).or_default();
+            let before = self.edge_weight(kinds);
+            let total = &mut kinds[interaction.kind as usize];
+            *total += rule.weight;
+            if let Some(cap) = rule.cap {
+                *total = total.min(cap);
+            }
+            let after = self.edge_weight(kinds);
+            // Only an exact repeat of the previous weight is skipped, so there's no need for a
+            // margin of error.
+            #[allow(clippy::float_cmp)]
+            if after != before {
+                events.push(Event {
+                    time: interaction.time,
+                    op: Op::SetWeight {
+                        from: interaction.from.clone(),
+                        to: interaction.to.clone(),
+                        weight: after,
+                    },
+                });
+            }
+        }
+        events
+    }
+
+    fn edge_weight(&self, kinds: &[f32; 5]) -> f32 {
+        let weight = kinds.iter().sum::<f32>();
+        self.max_weight.map_or(weight, |max| weight.min(max))
+    }
+}
+
+#[cfg(test)]
+// Every weight in these tests is a sum of small whole numbers, which is exact.
+#[allow(clippy::float_cmp)]
+mod tests {
+    use super::{Rule, Rules};
+    use crate::import::{InteractionKind, Op, Timeline};
+    use hashbrown::HashMap;
+
+    const SECOND: i64 = 1000;
+
+    /// Get the final weight of every edge, keyed with the smaller ID first.
+    fn final_weights(timeline: &Timeline<u64>, rules: &Rules) -> HashMap<(u64, u64), f32> {
+        let mut weights = HashMap::new();
+        for event in timeline.events(rules) {
+            if let Op::SetWeight { from, to, weight } = event.op {
+                weights.insert((from.min(to), from.max(to)), weight);
+            }
+        }
+        weights
+    }
+
+    /// Only count one kind of interaction, with the given rule.
+    fn only(kind: InteractionKind, rule: Rule) -> Rules {
+        let mut rules = Rules {
+            reply: Rule::ignore(),
+            mention: Rule::ignore(),
+            reaction: Rule::ignore(),
+            thread: Rule::ignore(),
+            proximity: Rule::ignore(),
+            ..Rules::default()
+        };
+        match kind {
+            InteractionKind::Reply => rules.reply = rule,
+            InteractionKind::Mention => rules.mention = rule,
+            InteractionKind::Reaction => rules.reaction = rule,
+            InteractionKind::Thread => rules.thread = rule,
+            InteractionKind::Proximity => rules.proximity = rule,
+        }
+        rules
+    }
+
+    #[test]
+    fn direct_interactions_with_caps() {
+        let mut timeline = Timeline::new();
+        // Alice (1) and Bob (2) reply to each other a lot, while Carol (3) reacts to everything
+        // Alice says.
+        for i in 0..10 {
+            timeline.interact(i * 100 * SECOND, 1, 2, InteractionKind::Reply);
+            timeline.interact(i * 100 * SECOND + SECOND, 2, 1, InteractionKind::Reply);
+            timeline.interact(i * 100 * SECOND, 3, 1, InteractionKind::Reaction);
+        }
+        timeline.interact(0, 3, 2, InteractionKind::Mention);
+
+        let rules = Rules {
+            reaction: Rule::new(10., Some(50.)),
+            max_weight: Some(500.),
+            ..Rules::default()
+        };
+        let weights = final_weights(&timeline, &rules);
+        assert_eq!(weights[&(1, 2)], 500.);
+        assert_eq!(weights[&(1, 3)], 50.);
+        assert_eq!(weights[&(2, 3)], 20.);
+
+        // Once a cap is reached, further interactions don't produce any more events.
+        let updates = timeline
+            .events(&rules)
+            .into_iter()
+            .filter(|event| matches!(event.op, Op::SetWeight { from: 3, to: 1, .. }))
+            .count();
+        assert_eq!(updates, 5);
+    }
+
+    #[test]
+    fn thread_participation() {
+        let mut timeline = Timeline::new();
+        timeline.mark_thread("thread");
+        timeline.message(0, 1, "thread");
+        timeline.message(SECOND * 1000, 2, "thread");
+        timeline.message(SECOND * 2000, 1, "thread");
+        timeline.message(SECOND * 3000, 3, "thread");
+        // The same users talking in an ordinary channel don't count as a thread.
+        timeline.message(0, 4, "general");
+        timeline.message(SECOND * 1000, 5, "general");
+
+        let weights = final_weights(
+            &timeline,
+            &only(InteractionKind::Thread, Rule::new(20., None)),
+        );
+        assert_eq!(weights.len(), 3);
+        assert_eq!(weights[&(1, 2)], 20.);
+        assert_eq!(weights[&(1, 3)], 20.);
+        assert_eq!(weights[&(2, 3)], 20.);
+    }
+
+    #[test]
+    fn proximity() {
+        let mut timeline = Timeline::new();
+        // A quick back and forth between 1 and 2, with 3 chiming in much later.
+        timeline.message(0, 1, "general");
+        timeline.message(10 * SECOND, 2, "general");
+        timeline.message(20 * SECOND, 1, "general");
+        timeline.message(30 * SECOND, 2, "general");
+        timeline.message(500 * SECOND, 3, "general");
+        // Messages close together in different channels don't count.
+        timeline.message(505 * SECOND, 4, "random");
+
+        let rules = Rules {
+            proximity_window: 60 * SECOND,
+            ..only(InteractionKind::Proximity, Rule::new(5., Some(12.)))
+        };
+        let weights = final_weights(&timeline, &rules);
+        assert_eq!(weights.len(), 1);
+        assert_eq!(weights[&(1, 2)], 12.);
+    }
+
+    #[test]
+    fn ignored_kinds_add_nothing() {
+        let mut timeline = Timeline::new();
+        timeline.interact(0, 1, 2, InteractionKind::Mention);
+        timeline.interact(0, 1, 3, InteractionKind::Reply);
+        let weights = final_weights(
+            &timeline,
+            &only(InteractionKind::Reply, Rule::new(1., None)),
+        );
+        assert_eq!(weights.len(), 1);
+        assert_eq!(weights[&(1, 3)], 1.);
+    }
+}
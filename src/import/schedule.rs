@@ -0,0 +1,113 @@
+use super::Event;
+use crate::{NodeKey, Result, System};
+
+/// Decides how many steps to simulate between events, based on the real time between them.
+///
+/// While a conversation is active, each step stands for a fixed amount of real time. Quiet
+/// periods are compressed, since nothing interesting happens in them other than the layout
+/// settling: every time a gap doubles beyond [`idle_after`](Self::idle_after), it only gets
+/// [`steps_per_doubling`](Self::steps_per_doubling) more steps. This way a burst of messages plays
+/// out at a readable pace, while a week of silence doesn't take hours to watch.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Scheduler {
+    /// How much real time each step stands for while the conversation is active, in milliseconds.
+    pub step_length: i64,
+    /// How long a gap between events can be before it is compressed, in milliseconds.
+    pub idle_after: i64,
+    /// How many steps each doubling of a gap beyond [`idle_after`](Self::idle_after) adds.
+    pub steps_per_doubling: u64,
+    /// The most steps to run between any two events, and after the last one.
+    pub max_steps: u64,
+    // The time of the last event scheduled.
+    last: Option<i64>,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self {
+            step_length: 1000,
+            idle_after: 60_000,
+            steps_per_doubling: 20,
+            max_steps: 300,
+            last: None,
+        }
+    }
+}
+
+impl Scheduler {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the number of steps to run for a gap between two events, in milliseconds.
+    #[must_use]
+    pub fn steps_for_gap(&self, gap: i64) -> u64 {
+        let step_length = self.step_length.max(1);
+        let steps = if gap <= 0 {
+            0
+        } else if gap <= self.idle_after {
+            (gap / step_length) as u64
+        } else {
+            let doublings = (gap as f64 / self.idle_after.max(1) as f64).log2();
+            (self.idle_after / step_length) as u64
+                + (doublings * self.steps_per_doubling as f64).round() as u64
+        };
+        steps.min(self.max_steps)
+    }
+
+    /// Get the number of steps to run before an event at the given time, in milliseconds since
+    /// the Unix epoch. Nothing needs to be run before the first event.
+    pub fn steps_until(&mut self, time: i64) -> u64 {
+        let steps = self.last.map_or(0, |last| self.steps_for_gap(time - last));
+        self.last = Some(self.last.map_or(time, |last| last.max(time)));
+        steps
+    }
+
+    /// Apply every event to a system in order, running the scheduled number of steps before each
+    /// one, and [`max_steps`](Self::max_steps) after the last so that the layout can settle.
+    ///
+    /// # Errors
+    ///
+    /// Stops and returns the error if any step or operation fails.
+    pub fn replay<K: NodeKey>(
+        &mut self,
+        system: &mut System<K>,
+        events: &[Event<K>],
+    ) -> Result<()> {
+        for event in events {
+            system.many_steps(self.steps_until(event.time))?;
+            event.op.apply(system)?;
+        }
+        system.many_steps(self.max_steps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Scheduler;
+
+    #[test]
+    fn compresses_idle_gaps() {
+        let scheduler = Scheduler::new();
+        assert_eq!(scheduler.steps_for_gap(0), 0);
+        assert_eq!(scheduler.steps_for_gap(-5000), 0);
+        assert_eq!(scheduler.steps_for_gap(15_000), 15);
+        assert_eq!(scheduler.steps_for_gap(60_000), 60);
+        assert_eq!(scheduler.steps_for_gap(120_000), 80);
+        assert_eq!(scheduler.steps_for_gap(240_000), 100);
+        // A year of silence is still limited.
+        assert_eq!(scheduler.steps_for_gap(365 * 24 * 3_600_000), 300);
+    }
+
+    #[test]
+    fn steps_between_events() {
+        let mut scheduler = Scheduler::new();
+        assert_eq!(scheduler.steps_until(1_000_000), 0);
+        assert_eq!(scheduler.steps_until(1_010_000), 10);
+        assert_eq!(scheduler.steps_until(1_010_000), 0);
+        // Events out of order don't move time backwards.
+        assert_eq!(scheduler.steps_until(1_005_000), 0);
+        assert_eq!(scheduler.steps_until(1_015_000), 5);
+    }
+}
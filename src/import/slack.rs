@@ -0,0 +1,294 @@
+//! Importing Slack workspace exports.
+//!
+//! A workspace export is a directory containing `users.json`, which lists every user along with
+//! their colour, and a directory for each channel holding one JSON file of messages per day. Users
+//! are identified by their Slack IDs, such as `U012AB3CD`.
+//!
+//! Mentions, reactions and thread replies are recorded as interactions. Each thread is also
+//! recorded as a channel of its own, named `channel/thread_ts`, so that everyone taking part in a
+//! thread interacts with each other.
+use super::{parse_colour, parse_millis, InteractionKind, Timeline};
+use crate::{Error, Result};
+use hashbrown::HashMap;
+use serde_json::Value;
+use std::fs::{self, File};
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+/// Message subtypes which are not really messages, and so don't count as activity.
+const IGNORED_SUBTYPES: [&str; 4] = [
+    "channel_join",
+    "channel_leave",
+    "channel_topic",
+    "channel_purpose",
+];
+
+/// Reads a workspace export into a single [`Timeline`].
+///
+/// Thread replies are resolved once everything has been read, so files can be read in any order.
+#[derive(Clone, Debug, Default)]
+pub struct Importer {
+    timeline: Timeline<String>,
+    colours: HashMap<String, [u8; 3]>,
+    // The author of every message seen so far, by channel and timestamp. Slack uses timestamps to
+    // identify messages, and they are only unique within a channel.
+    authors: HashMap<(String, String), String>,
+    // Thread replies as (time, author, channel, timestamp of the thread's first message).
+    replies: Vec<(i64, String, String, String)>,
+}
+
+impl Importer {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read an entire export which has been extracted to a directory.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory or any file in it cannot be read, or if any file is not
+    /// valid (see [`read_users`](Self::read_users) and [`read_channel`](Self::read_channel)).
+    pub fn read_dir(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let users = path.join("users.json");
+        if users.is_file() {
+            self.read_users(BufReader::new(File::open(users)?))?;
+        }
+        for channel in sorted_entries(path)? {
+            if !channel.is_dir() {
+                continue;
+            }
+            let Some(name) = channel.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            for day in sorted_entries(&channel)? {
+                if day.extension().is_some_and(|extension| extension == "json") {
+                    self.read_channel(name, BufReader::new(File::open(&day)?))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the list of users in `users.json`, to find their colours.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Json`] if the file is not valid JSON, or [`Error::Import`] if it is not a
+    /// list.
+    pub fn read_users(&mut self, r: impl Read) -> Result<()> {
+        let users: Value = serde_json::from_reader(r)?;
+        let users = users
+            .as_array()
+            .ok_or(Error::Import("users are not a list"))?;
+        for user in users {
+            let id = user["id"].as_str();
+            let colour = user["color"].as_str().and_then(parse_colour);
+            if let (Some(id), Some(colour)) = (id, colour) {
+                self.colours.insert(id.to_owned(), colour);
+            }
+        }
+        Ok(())
+    }
+
+    /// Read one day of messages from a channel.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Json`] if the file is not valid JSON, or [`Error::Import`] if it is not a
+    /// list of messages.
+    pub fn read_channel(&mut self, channel: &str, r: impl Read) -> Result<()> {
+        let messages: Value = serde_json::from_reader(r)?;
+        let messages = messages
+            .as_array()
+            .ok_or(Error::Import("messages are not a list"))?;
+        for message in messages {
+            self.read_message(channel, message)?;
+        }
+        Ok(())
+    }
+
+    fn read_message(&mut self, channel: &str, message: &Value) -> Result<()> {
+        let subtype = message["subtype"].as_str().unwrap_or_default();
+        // Messages from bots and integrations don't have a user.
+        let Some(author) = message["user"].as_str() else {
+            return Ok(());
+        };
+        if IGNORED_SUBTYPES.contains(&subtype) {
+            return Ok(());
+        }
+        let ts = message["ts"]
+            .as_str()
+            .ok_or(Error::Import("message has no timestamp"))?;
+        let time = parse_ts(ts).ok_or(Error::Import("message has an invalid timestamp"))?;
+        self.authors
+            .insert((channel.to_owned(), ts.to_owned()), author.to_owned());
+
+        let thread_ts = message["thread_ts"].as_str();
+        if let Some(thread_ts) = thread_ts {
+            let thread = format!("{channel}/{thread_ts}");
+            self.timeline.mark_thread(&thread);
+            self.timeline.message(time, author.to_owned(), &thread);
+            if thread_ts != ts {
+                self.replies.push((
+                    time,
+                    author.to_owned(),
+                    channel.to_owned(),
+                    thread_ts.to_owned(),
+                ));
+            }
+        }
+        // Replies only appear in the channel itself if they were also sent to it.
+        if thread_ts.is_none_or(|thread_ts| thread_ts == ts) || subtype == "thread_broadcast" {
+            self.timeline.message(time, author.to_owned(), channel);
+        }
+
+        let text = message["text"].as_str().unwrap_or_default();
+        for mentioned in mentions(text) {
+            self.timeline.interact(
+                time,
+                author.to_owned(),
+                mentioned.to_owned(),
+                InteractionKind::Mention,
+            );
+        }
+        let reactions = message["reactions"].as_array().into_iter().flatten();
+        for user in
+            reactions.flat_map(|reaction| reaction["users"].as_array().into_iter().flatten())
+        {
+            if let Some(user) = user.as_str() {
+                self.timeline.interact(
+                    time,
+                    user.to_owned(),
+                    author.to_owned(),
+                    InteractionKind::Reaction,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Finish reading the export, and get the resulting timeline.
+    ///
+    /// Replies to threads whose first message was not in the export are ignored.
+    #[must_use]
+    pub fn finish(mut self) -> Timeline<String> {
+        for (time, author, channel, thread_ts) in self.replies {
+            if let Some(parent_author) = self.authors.get(&(channel, thread_ts)) {
+                self.timeline
+                    .interact(time, author, parent_author.clone(), InteractionKind::Reply);
+            }
+        }
+        for (id, colour) in &self.colours {
+            self.timeline.set_colour(id, *colour);
+        }
+        self.timeline
+    }
+}
+
+/// List the entries in a directory, sorted by name so that exports are always read in the same
+/// order.
+fn sorted_entries(path: &Path) -> Result<Vec<std::path::PathBuf>> {
+    let mut entries = fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort();
+    Ok(entries)
+}
+
+/// Parse a Slack timestamp, such as `1512085950.000216`, which is a number of seconds since the
+/// Unix epoch, into milliseconds.
+fn parse_ts(ts: &str) -> Option<i64> {
+    let (seconds, fraction) = ts.split_once('.').unwrap_or((ts, ""));
+    let seconds = seconds.parse::<i64>().ok()?;
+    Some(seconds * 1000 + parse_millis(fraction)?)
+}
+
+/// Find the IDs of the users mentioned in a message, which Slack writes as `<@U012AB3CD>` or
+/// `<@U012AB3CD|name>`.
+fn mentions(text: &str) -> impl Iterator<Item = &str> {
+    text.split("<@").skip(1).filter_map(|rest| {
+        let end = rest.find(['>', '|'])?;
+        Some(&rest[..end]).filter(|id| !id.is_empty())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{mentions, parse_ts, Importer};
+    use crate::import::{Op, Rules};
+
+    #[test]
+    fn parsing() {
+        assert_eq!(parse_ts("1512085950.000216"), Some(1_512_085_950_000));
+        assert_eq!(parse_ts("1512085950.5"), Some(1_512_085_950_500));
+        assert_eq!(parse_ts("nope"), None);
+        assert_eq!(parse_ts("1.\u{e9}\u{e9}"), None);
+        assert_eq!(
+            mentions("hi <@U1>, <@U2|bob> and <!here>").collect::<Vec<_>>(),
+            vec!["U1", "U2"]
+        );
+    }
+
+    #[test]
+    fn reads_channel() {
+        let users = r#"[
+            { "id": "U1", "name": "alice", "color": "9f69e7" },
+            { "id": "U2", "name": "bob" }
+        ]"#;
+        let day = r#"[
+            { "type": "message", "subtype": "channel_join", "user": "U3", "ts": "1000.000000" },
+            {
+                "type": "message", "user": "U1", "text": "anyone around?", "ts": "2000.000000",
+                "thread_ts": "2000.000000", "reply_count": 1,
+                "reactions": [{ "name": "eyes", "users": ["U2"], "count": 1 }]
+            },
+            {
+                "type": "message", "user": "U2", "text": "yes, <@U1>", "ts": "2000.500000",
+                "thread_ts": "2000.000000", "parent_user_id": "U1"
+            },
+            { "type": "message", "subtype": "bot_message", "bot_id": "B1", "ts": "2001.000000" }
+        ]"#;
+        let mut importer = Importer::new();
+        importer.read_channel("general", day.as_bytes()).unwrap();
+        importer.read_users(users.as_bytes()).unwrap();
+        let timeline = importer.finish();
+        assert_eq!(timeline.user_count(), 2);
+
+        let rules = Rules::default();
+        let ops = timeline
+            .events(&rules)
+            .into_iter()
+            .map(|event| event.op)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ops[..2],
+            [
+                Op::AddNode {
+                    id: "U1".to_owned(),
+                    colour: Some([0x9f, 0x69, 0xe7])
+                },
+                Op::AddNode {
+                    id: "U2".to_owned(),
+                    colour: None
+                },
+            ]
+        );
+        // A reaction, then a mention, a reply, taking part in the same thread, and posting in it
+        // within a second of each other.
+        let expected = rules.reaction.weight
+            + rules.mention.weight
+            + rules.reply.weight
+            + rules.thread.weight
+            + rules.proximity.weight;
+        assert_eq!(
+            ops.last(),
+            Some(&Op::SetWeight {
+                from: "U2".to_owned(),
+                to: "U1".to_owned(),
+                weight: expected
+            })
+        );
+    }
+}
@@ -0,0 +1,284 @@
+//! The forces which lay out a [`System`](crate::System).
+//!
+//! By default, the graph is treated as complete: every pair of nodes is joined by a spring, whose
+//! rest length is shorter the heavier the edge between them. This keeps unrelated nodes apart
+//! without any other force, but costs O(n²) per step.
+//!
+//! In [`Layout::Sparse`], only the edges which have been given a weight have springs, and nodes are
+//! kept apart by a global repulsion instead. The repulsion between distant groups of nodes is
+//! approximated with a [Barnes–Hut](https://en.wikipedia.org/wiki/Barnes%E2%80%93Hut_simulation)
+//! quadtree, so a step costs O(n log n + e) for n nodes and e edges. A weak gravity towards the
+//! centre stops disconnected parts of the graph from drifting away forever.
+use crate::Vec2d;
+
+/// Bodies closer than this are treated as this far apart, so that the repulsion between nodes
+/// which happen to be very close doesn't fling them across the canvas.
+const SOFTENING: f32 = 10.;
+/// Cells stop being split after this many levels, so that nodes at the same position don't
+/// recurse forever.
+const MAX_DEPTH: usize = 24;
+
+/// How nodes are laid out.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Layout {
+    /// Join every pair of nodes with a spring.
+    #[default]
+    Complete,
+    /// Only join nodes with an edge by springs, and push every node away from the others.
+    Sparse(Repulsion),
+}
+
+/// The settings for the forces used by [`Layout::Sparse`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Repulsion {
+    /// The force between two nodes is this divided by the square of the distance between them.
+    pub strength: f32,
+    /// How much to approximate: a group of nodes is treated as a single node when its width
+    /// divided by its distance is less than this. 0 calculates every force exactly.
+    pub theta: f32,
+    /// How strongly each node is pulled towards the centre, in proportion to its distance from
+    /// it.
+    pub gravity: f32,
+}
+
+impl Default for Repulsion {
+    fn default() -> Self {
+        Self {
+            strength: 5000.,
+            theta: 0.9,
+            gravity: 0.001,
+        }
+    }
+}
+
+/// A cell of the quadtree.
+#[derive(Debug)]
+struct Cell {
+    center: Vec2d,
+    half_width: f32,
+    // The total mass and centre of mass of every body in the cell.
+    mass: f32,
+    center_of_mass: Vec2d,
+    // The index of the first of the four child cells, if the cell has been split.
+    children: Option<usize>,
+    // The bodies directly in this cell, if it is a leaf.
+    bodies: Vec<usize>,
+}
+
+impl Cell {
+    const fn new(center: Vec2d, half_width: f32) -> Self {
+        Self {
+            center,
+            half_width,
+            mass: 0.,
+            center_of_mass: Vec2d::new(0., 0.),
+            children: None,
+            bodies: Vec::new(),
+        }
+    }
+
+    fn add_mass(&mut self, pos: Vec2d) {
+        self.center_of_mass = (self.center_of_mass * self.mass + pos) / (self.mass + 1.);
+        self.mass += 1.;
+    }
+
+    fn quadrant(&self, pos: Vec2d) -> usize {
+        usize::from(pos.x >= self.center.x) + 2 * usize::from(pos.y >= self.center.y)
+    }
+}
+
+/// A quadtree of bodies of equal mass, for approximating the repulsion between them.
+#[derive(Debug)]
+pub(crate) struct QuadTree<'a> {
+    cells: Vec<Cell>,
+    bodies: &'a [Vec2d],
+}
+
+impl<'a> QuadTree<'a> {
+    /// Build a tree containing every body.
+    pub(crate) fn new(bodies: &'a [Vec2d]) -> Self {
+        let (mut min, mut max) = (Vec2d::new(0., 0.), Vec2d::new(0., 0.));
+        if let Some(first) = bodies.first() {
+            (min, max) = (*first, *first);
+        }
+        for body in bodies {
+            min = Vec2d::new(min.x.min(body.x), min.y.min(body.y));
+            max = Vec2d::new(max.x.max(body.x), max.y.max(body.y));
+        }
+        let half_width = ((max.x - min.x).max(max.y - min.y) / 2.).max(1.);
+        let mut tree = Self {
+            cells: vec![Cell::new((min + max) / 2., half_width)],
+            bodies,
+        };
+        for index in 0..bodies.len() {
+            tree.insert(index);
+        }
+        tree
+    }
+
+    fn insert(&mut self, index: usize) {
+        let pos = self.bodies[index];
+        let mut cell = 0;
+        let mut depth = 0;
+        loop {
+            self.cells[cell].add_mass(pos);
+            let current = &self.cells[cell];
+            if let Some(first) = current.children {
+                cell = first + current.quadrant(pos);
+            } else if current.bodies.is_empty() || depth >= MAX_DEPTH {
+                self.cells[cell].bodies.push(index);
+                return;
+            } else {
+                // Move the body already here down a level, then carry on down with this one.
+                let first = self.split(cell);
+                for existing in std::mem::take(&mut self.cells[cell].bodies) {
+                    let existing_pos = self.bodies[existing];
+                    let child = first + self.cells[cell].quadrant(existing_pos);
+                    let child = &mut self.cells[child];
+                    child.add_mass(existing_pos);
+                    child.bodies.push(existing);
+                }
+                cell = first + self.cells[cell].quadrant(pos);
+            }
+            depth += 1;
+        }
+    }
+
+    /// Split a cell into four, returning the index of the first child.
+    fn split(&mut self, cell: usize) -> usize {
+        let Cell {
+            center, half_width, ..
+        } = self.cells[cell];
+        let quarter = half_width / 2.;
+        let first = self.cells.len();
+        self.cells[cell].children = Some(first);
+        // In the same order as `Cell::quadrant`.
+        for (dx, dy) in [(-1., -1.), (1., -1.), (-1., 1.), (1., 1.)] {
+            let child_center = center + Vec2d::new(dx * quarter, dy * quarter);
+            self.cells.push(Cell::new(child_center, quarter));
+        }
+        first
+    }
+
+    /// Get the total repulsion on a body from every other body.
+    pub(crate) fn repulsion(&self, index: usize, repulsion: &Repulsion) -> Vec2d {
+        let pos = self.bodies[index];
+        let mut force = Vec2d::new(0., 0.);
+        let mut stack = vec![0];
+        while let Some(cell) = stack.pop() {
+            let cell = &self.cells[cell];
+            if cell.mass == 0. {
+                continue;
+            }
+            if let Some(first) = cell.children {
+                let distance = pos.distance(cell.center_of_mass);
+                if 2. * cell.half_width < repulsion.theta * distance {
+                    force += push(pos, cell.center_of_mass, cell.mass, repulsion.strength);
+                } else {
+                    stack.extend(first..first + 4);
+                }
+            } else {
+                for &other in &cell.bodies {
+                    if other != index {
+                        force += push(pos, self.bodies[other], 1., repulsion.strength);
+                    }
+                }
+            }
+        }
+        force
+    }
+}
+
+/// Get the force pushing a body at `pos` away from a mass at `from`.
+fn push(pos: Vec2d, from: Vec2d, mass: f32, strength: f32) -> Vec2d {
+    let offset = pos - from;
+    let distance = offset.length();
+    if distance == 0. {
+        // There's no direction to push in. The bodies will be separated by other forces, or by
+        // the next body which comes near.
+        return Vec2d::new(0., 0.);
+    }
+    let distance = distance.max(SOFTENING);
+    offset.as_unit() * (strength * mass / distance.powi(2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{push, Layout, QuadTree, Repulsion};
+    use crate::{System, Vec2d};
+
+    #[test]
+    fn approximates_exact_repulsion() {
+        // A grid of bodies, with a few stacked on the same spot.
+        let mut bodies = Vec::new();
+        for x in 0..20 {
+            for y in 0..20 {
+                bodies.push(Vec2d::new(x as f32 * 37., y as f32 * 23.));
+            }
+        }
+        bodies.extend([Vec2d::new(100., 100.); 3]);
+
+        let exact = Repulsion {
+            theta: 0.,
+            ..Repulsion::default()
+        };
+        let approximate = Repulsion::default();
+        let tree = QuadTree::new(&bodies);
+        for index in [0, 57, 210, 399, 401] {
+            let pushes = bodies
+                .iter()
+                .enumerate()
+                .filter(|&(other, _)| other != index)
+                .map(|(_, &other)| push(bodies[index], other, 1., exact.strength))
+                .collect::<Vec<_>>();
+            let brute = pushes
+                .iter()
+                .fold(Vec2d::new(0., 0.), |total, &force| total + force);
+            // Forces from opposite sides mostly cancel out, so errors are measured relative to the
+            // total size of every push rather than the result.
+            let scale = pushes.iter().map(|force| force.length()).sum::<f32>();
+            assert!((tree.repulsion(index, &exact) - brute).length() < 1e-4 * scale);
+            assert!((tree.repulsion(index, &approximate) - brute).length() < 0.05 * scale);
+        }
+    }
+
+    #[test]
+    fn sparse_layout_settles() {
+        let mut system: System = System::with_seed(5);
+        system.set_layout(Layout::Sparse(Repulsion::default()));
+        for id in 0..6 {
+            system.add_node(id, None);
+        }
+        // Two triangles, joined by a single light edge.
+        for (from, to) in [(0, 1), (1, 2), (0, 2), (3, 4), (4, 5), (3, 5)] {
+            system.set_weight(from, to, 100.);
+        }
+        system.set_weight(2, 3, 10.);
+        system.many_steps(1000).unwrap();
+
+        let pos = |id| system.graph.get_node(&id).unwrap().pos;
+        for id in 0..6 {
+            assert!(pos(id).x.is_finite() && pos(id).y.is_finite());
+        }
+        let within = pos(0).distance(pos(1));
+        let between = pos(0).distance(pos(4));
+        assert!(within < between, "{within} >= {between}");
+        // Gravity keeps the graph near the middle of the canvas.
+        assert!(pos(0).distance(Vec2d::new(500., 500.)) < 500.);
+    }
+
+    #[test]
+    fn sparse_layout_ignores_self_loops() {
+        let mut system: System = System::with_seed(5);
+        system.set_layout(Layout::Sparse(Repulsion::default()));
+        system.add_node(0, None);
+        system.add_node(1, None);
+        system.set_weight(0, 0, 100.);
+        system.set_weight(0, 1, 100.);
+        system.many_steps(10).unwrap();
+        for id in 0..2 {
+            let pos = system.graph.get_node(&id).unwrap().pos;
+            assert!(pos.x.is_finite() && pos.y.is_finite());
+        }
+    }
+}
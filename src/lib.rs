@@ -5,26 +5,57 @@
     // missing_docs,
     // clippy::missing_docs_in_private_items
 )]
+#![allow(
+    // Rendering code converts between coordinates, pixel indices and colour channels constantly,
+    // and the values involved are always small enough for these casts to be harmless.
+    clippy::cast_possible_truncation,
+    clippy::cast_precision_loss,
+    clippy::cast_sign_loss
+)]
+use backbone::{BackboneFilter, EdgeSet};
+use colour::AutoColour;
+use community::CommunityDetection;
 use graph::{Graph, HasKey};
-use std::fs::File;
-use std::io::BufWriter;
-use vec2d::Vec2d;
+use hashbrown::{HashMap, HashSet};
+use layout::{Layout, QuadTree, Repulsion};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use std::fmt::Debug;
+use std::hash::Hash;
 
-#[cfg(feature = "raster")]
-use draw::{Drawing, Order};
+pub use error::{Error, Result};
+pub use output::{FrameBuffers, FrameOutputs, Output, SharedBuffer};
+pub use sink::FrameSink;
+pub use vec2d::Vec2d;
 
-mod graph;
+pub mod analytics;
+pub mod backbone;
+pub mod camera;
+pub mod colour;
+pub mod community;
+mod error;
+pub mod exchange;
+pub mod graph;
+pub mod import;
+pub mod layout;
 #[cfg(feature = "masquerade")]
-mod masquerade;
+pub mod masquerade;
+pub mod output;
+mod sink;
+pub mod snowflake;
+pub mod state;
 mod vec2d;
 
 #[cfg(feature = "raster")]
-mod draw;
+pub mod draw;
 
 #[cfg(feature = "lottie")]
 mod lottie;
 #[cfg(feature = "lottie")]
-mod lottie_graph;
+pub mod lottie_graph;
+
+#[cfg(feature = "svg")]
+pub mod svg;
 
 const SPRING_CONSTANT: f32 = 0.01;
 const TARGET_DENSITY: f32 = 150.;
@@ -33,173 +64,702 @@ const DAMPING: f32 = 0.9;
 const SIZE: f32 = 1000.;
 const STARTING_JITTER: f32 = 5.;
 
+/// Get the acceleration of a node at `pos` due to a spring joining it to a node at `other`.
+fn spring(pos: Vec2d, other: Vec2d, spring_length: f32) -> Vec2d {
+    let force = SPRING_CONSTANT * (pos.distance(other) - spring_length);
+    (other - pos).as_unit() * force
+}
+
+/// The requirements for a type to be used to identify nodes in a [`System`].
+///
+/// This is implemented for every type which satisfies the bounds, such as integers, strings, tuples
+/// of those, and UUIDs. `Debug` is needed so that errors can say which node they refer to.
+pub trait NodeKey: Hash + Eq + Clone + Debug {}
+
+impl<T: Hash + Eq + Clone + Debug> NodeKey for T {}
+
 #[derive(Clone, Debug)]
-struct Node {
-    id: u64,
+struct Node<K> {
+    id: K,
+    colour: [u8; 3],
+    // The community found for the node when communities were last found, if it existed then.
+    community: Option<usize>,
+    // How large the node is drawn, as a multiple of the default size.
+    size: f32,
+    // The contents of a PNG file to draw in place of the colour, if the node has an avatar.
+    avatar: Option<Vec<u8>>,
     pos: Vec2d,
     velocity: Vec2d,
-    #[cfg(feature = "raster")]
-    order: Order,
-    #[cfg(feature = "masquerade")]
-    palette_index: u8,
 }
 
-impl HasKey for Node {
-    type Key = u64;
+impl<K: NodeKey> HasKey for Node<K> {
+    type Key = K;
 
     fn key(&self) -> Self::Key {
-        self.id
+        self.id.clone()
     }
 }
 
-pub struct System {
-    graph: Graph<Node, f32>,
-    #[cfg(feature = "lottie")]
-    history: lottie_graph::History,
-    #[cfg(feature = "raster")]
-    drawing: Drawing,
-    #[cfg(feature = "gif")]
-    gif: gif::Encoder<BufWriter<File>>,
+/// A force-directed layout of a graph, which passes the position of every node on to its sinks at
+/// every step.
+///
+/// Nodes are identified by keys of type `K`, which defaults to `u64` for Discord snowflakes.
+pub struct System<K: NodeKey = u64> {
+    graph: Graph<Node<K>, f32>,
+    sinks: Vec<Box<dyn FrameSink<K>>>,
     steps: u64,
-    #[cfg(feature = "masquerade")]
-    im: masquerade::Image,
+    layout: Layout,
+    auto_colour: AutoColour,
+    community_detection: CommunityDetection,
+    backbone: Option<BackboneFilter>,
+    // The edges in the backbone when it was last extracted.
+    backbone_edges: EdgeSet<K>,
+    // A seedable RNG that we can save the state of is used, rather than `thread_rng`, so that
+    // simulations can be reproduced and resumed from a checkpoint.
+    rng: ChaCha8Rng,
 }
 
-impl System {
+impl<K: NodeKey> Default for System<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: NodeKey> System<K> {
     #[must_use]
     pub fn new() -> Self {
+        Self::with_rng(ChaCha8Rng::from_entropy())
+    }
+
+    /// Create a system whose random choices are determined by a seed, so that the same sequence
+    /// of operations always produces the same layout.
+    #[must_use]
+    pub fn with_seed(seed: u64) -> Self {
+        Self::with_rng(ChaCha8Rng::seed_from_u64(seed))
+    }
+
+    fn with_rng(rng: ChaCha8Rng) -> Self {
         Self {
             graph: Graph::new(),
-            #[cfg(feature = "lottie")]
-            history: lottie_graph::History::new(),
-            #[cfg(feature = "raster")]
-            drawing: Drawing::new(),
-            #[cfg(feature = "gif")]
-            gif: gif::Encoder::new(
-                BufWriter::new(File::create("out.gif").unwrap()),
-                SIZE as u16,
-                SIZE as u16,
-                &[],
-            )
-            .unwrap(),
+            sinks: Vec::new(),
             steps: 0,
-            #[cfg(feature = "masquerade")]
-            im: masquerade::Image::new(),
+            layout: Layout::default(),
+            auto_colour: AutoColour::default(),
+            community_detection: CommunityDetection::default(),
+            backbone: None,
+            backbone_edges: EdgeSet::new(),
+            rng,
+        }
+    }
+
+    /// Pass an event on to every sink, even if some of them fail, and return the first error.
+    fn broadcast(
+        &mut self,
+        mut event: impl FnMut(&mut dyn FrameSink<K>) -> Result<()>,
+    ) -> Result<()> {
+        let mut result = Ok(());
+        for sink in &mut self.sinks {
+            let sink_result = event(sink.as_mut());
+            if result.is_ok() {
+                result = sink_result;
+            }
+        }
+        result
+    }
+
+    /// Attach an output to the system.
+    ///
+    /// The sink is immediately told about every node that already exists, so sinks can be
+    /// attached at any point in the simulation.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the sink fails to handle one of the existing nodes. The sink is still
+    /// attached in this case.
+    pub fn add_sink(&mut self, sink: impl FrameSink<K> + 'static) -> Result<()> {
+        let mut sink = Box::new(sink);
+        let result = self.introduce_all(sink.as_mut());
+        self.sinks.push(sink);
+        result
+    }
+
+    /// Tell a sink about an existing node as if it had just been added.
+    fn introduce(&self, sink: &mut dyn FrameSink<K>, node: &Node<K>) -> Result<()> {
+        sink.node_added(&node.id, self.shown_colour(node))?;
+        if let Some(avatar) = &node.avatar {
+            sink.avatar_set(&node.id, avatar)?;
+        }
+        // Sinks start every node at the default size, so only other sizes need to be sent.
+        #[allow(clippy::float_cmp)]
+        if node.size != 1. {
+            sink.node_resized(&node.id, node.size)?;
+        }
+        Ok(())
+    }
+
+    /// Tell a sink about every existing node and edge, returning the first error but carrying on
+    /// regardless.
+    fn introduce_all(&self, sink: &mut dyn FrameSink<K>) -> Result<()> {
+        let mut result = Ok(());
+        for node in self.graph.nodes() {
+            let node_result = self.introduce(sink, node);
+            if result.is_ok() {
+                result = node_result;
+            }
+        }
+        for (from, to, &weight) in self.graph.edges_unique() {
+            if weight != 0. && self.is_drawn(&from.id, &to.id) {
+                let edge_result = sink.weight_changed(&from.id, &to.id, weight);
+                if result.is_ok() {
+                    result = edge_result;
+                }
+            }
         }
+        result
     }
 
-    pub fn add_node(&mut self, id: u64, colour: [u8; 3]) -> u64 {
+    /// Choose the forces used to lay out the graph from now on.
+    pub const fn set_layout(&mut self, layout: Layout) {
+        self.layout = layout;
+    }
+
+    /// Choose how colours are picked for nodes added without one from now on.
+    pub const fn set_auto_colour(&mut self, auto_colour: AutoColour) {
+        self.auto_colour = auto_colour;
+    }
+
+    /// Add a new node, returning its ID.
+    ///
+    /// The colour can be given as an `[r, g, b]` array, or `None` to derive one from the ID as
+    /// chosen by [`set_auto_colour`](Self::set_auto_colour). To give the node an avatar, use
+    /// [`add_node_with_avatar`](Self::add_node_with_avatar) instead.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a node with the same ID already exists, or if any sink fails. See
+    /// [`try_add_node`](Self::try_add_node) for a non-panicking version.
+    pub fn add_node(&mut self, id: K, colour: impl Into<Option<[u8; 3]>>) -> K {
+        self.try_add_node(id, colour).unwrap()
+    }
+
+    /// Add a new node, returning its ID. The colour is derived from the ID if not given, as in
+    /// [`add_node`](Self::add_node).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NodeExists`] if a node with the same ID already exists, or the first error
+    /// from any sink. The node is still added if only a sink fails.
+    pub fn try_add_node(&mut self, id: K, colour: impl Into<Option<[u8; 3]>>) -> Result<K> {
+        self.insert_node(id, colour.into(), None)
+    }
+
+    /// Add a new node with an avatar, given as the contents of a PNG file, returning its ID.
+    /// Sinks which can draw images show the avatar, clipped to the node's circle, in place of its
+    /// colour. The colour is derived from the ID if not given, as in [`add_node`](Self::add_node).
+    ///
+    /// # Panics
+    ///
+    /// Panics if a node with the same ID already exists, or if any sink fails, including if one
+    /// cannot decode the avatar. See [`try_add_node_with_avatar`](Self::try_add_node_with_avatar)
+    /// for a non-panicking version.
+    pub fn add_node_with_avatar(
+        &mut self,
+        id: K,
+        colour: impl Into<Option<[u8; 3]>>,
+        avatar: Vec<u8>,
+    ) -> K {
+        self.try_add_node_with_avatar(id, colour, avatar).unwrap()
+    }
+
+    /// Add a new node with an avatar, returning its ID, as in
+    /// [`add_node_with_avatar`](Self::add_node_with_avatar).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NodeExists`] if a node with the same ID already exists, or the first error
+    /// from any sink, such as [`Error::Avatar`] if the avatar is not a valid PNG image. The node is
+    /// still added if only a sink fails.
+    pub fn try_add_node_with_avatar(
+        &mut self,
+        id: K,
+        colour: impl Into<Option<[u8; 3]>>,
+        avatar: Vec<u8>,
+    ) -> Result<K> {
+        self.insert_node(id, colour.into(), Some(avatar))
+    }
+
+    fn insert_node(
+        &mut self,
+        id: K,
+        colour: Option<[u8; 3]>,
+        avatar: Option<Vec<u8>>,
+    ) -> Result<K> {
+        if self.graph.contains_node(&id) {
+            return Err(Error::node_exists(&id));
+        }
+        let colour = colour.unwrap_or_else(|| self.auto_colour.colour_for(&id));
         let center = Vec2d::new(SIZE / 2., SIZE / 2.);
-        let jitter = Vec2d::random_unit() * STARTING_JITTER;
+        let jitter = Vec2d::random_unit(&mut self.rng) * STARTING_JITTER;
         let pos = center + jitter;
         let velocity = Vec2d::new(0., 0.);
-        #[cfg(feature = "raster")]
-        let order = self.drawing.add_node(colour);
-        #[cfg(feature = "masquerade")]
-        let palette_index = self.im.add_node(colour);
+        // Sinks are told first so that the avatar can be moved into the node afterwards.
+        let result = self.broadcast(|sink| {
+            sink.node_added(&id, colour)?;
+            avatar
+                .as_ref()
+                .map_or(Ok(()), |avatar| sink.avatar_set(&id, avatar))
+        });
         self.graph.add_node(Node {
-            id,
+            id: id.clone(),
+            colour,
+            community: None,
+            size: 1.,
+            avatar,
             pos,
             velocity,
-            #[cfg(feature = "raster")]
-            order,
-            #[cfg(feature = "masquerade")]
-            palette_index,
         });
-        #[cfg(feature = "lottie")]
-        self.history.add_node(id, colour);
-        id
+        result.map(|()| id)
+    }
+
+    /// Remove a node, along with all of its edges. Nothing happens if the node does not exist.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any sink fails. See [`try_remove_node`](Self::try_remove_node) for a
+    /// non-panicking version.
+    pub fn remove_node(&mut self, id: &K) {
+        match self.try_remove_node(id) {
+            Ok(()) | Err(Error::NodeNotFound(_)) => {}
+            Err(e) => panic!("{e}"),
+        }
+    }
+
+    /// Remove a node, along with all of its edges.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NodeNotFound`] if the node does not exist, or the first error from any
+    /// sink. The node is still removed if only a sink fails.
+    pub fn try_remove_node(&mut self, id: &K) -> Result<()> {
+        if self.graph.remove_node(id).is_none() {
+            return Err(Error::node_not_found(id));
+        }
+        self.backbone_edges.remove_node(id);
+        self.broadcast(|sink| sink.node_removed(id))
     }
 
-    pub fn set_weight(&mut self, from: u64, to: u64, weight: f32) {
-        self.graph.set_weight(from, to, weight);
+    /// Set the weight of the edge between two nodes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either of the nodes does not exist, or if any sink fails. See
+    /// [`try_set_weight`](Self::try_set_weight) for a non-panicking version.
+    pub fn set_weight(&mut self, from: K, to: K, weight: f32) {
+        self.try_set_weight(from, to, weight).unwrap();
     }
 
-    pub fn step(&mut self) {
+    /// Set the weight of the edge between two nodes.
+    ///
+    /// If only the [backbone](Self::set_backbone) is drawn, sinks are only told about the new
+    /// weight if the edge was in the backbone when it was last extracted.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NodeNotFound`] if either of the nodes does not exist, in which case
+    /// nothing is changed, or the first error from any sink. The weight is still changed if only
+    /// a sink fails.
+    pub fn try_set_weight(&mut self, from: K, to: K, weight: f32) -> Result<()> {
+        if !self.graph.contains_node(&from) {
+            Err(Error::node_not_found(&from))
+        } else if !self.graph.contains_node(&to) {
+            Err(Error::node_not_found(&to))
+        } else {
+            let result = if self.is_drawn(&from, &to) {
+                self.broadcast(|sink| sink.weight_changed(&from, &to, weight))
+            } else {
+                Ok(())
+            };
+            self.graph.set_weight(from, to, weight);
+            result
+        }
+    }
+
+    /// Get the graph being laid out, for example to measure it with [`analytics`] or extract its
+    /// [`backbone`].
+    #[must_use]
+    pub const fn graph(&self) -> &Graph<impl HasKey<Key = K> + Clone, f32> {
+        &self.graph
+    }
+
+    /// Change the colour of a node. If the node is coloured by its community, the new colour is
+    /// only shown once it stops being.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NodeNotFound`] if the node does not exist, or the first error from any
+    /// sink. The colour is still changed if only a sink fails.
+    pub fn set_colour(&mut self, id: &K, colour: [u8; 3]) -> Result<()> {
+        let before = self.shown_colours();
+        self.graph
+            .get_node_mut(id)
+            .ok_or_else(|| Error::node_not_found(id))?
+            .colour = colour;
+        self.recolour(&before)
+    }
+
+    /// Change how large a node is drawn, as a multiple of the default size.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NodeNotFound`] if the node does not exist, or the first error from any
+    /// sink. The size is still changed if only a sink fails.
+    pub fn set_size(&mut self, id: &K, size: f32) -> Result<()> {
+        let node = self
+            .graph
+            .get_node_mut(id)
+            .ok_or_else(|| Error::node_not_found(id))?;
+        #[allow(clippy::float_cmp)]
+        if node.size == size {
+            return Ok(());
+        }
+        node.size = size;
+        self.broadcast(|sink| sink.node_resized(id, size))
+    }
+
+    /// Size nodes by a score, such as one from [`analytics`]. The node with the lowest score is
+    /// drawn at `smallest` times the default size, the one with the highest at `largest`, and the
+    /// rest in proportion. Nodes without a score keep their size.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error from any sink, or [`Error::NodeNotFound`] if a scored node does not
+    /// exist. Every other node is still resized.
+    pub fn size_by(&mut self, scores: &HashMap<K, f64>, smallest: f32, largest: f32) -> Result<()> {
+        let mut result = Ok(());
+        for (id, scaled) in analytics::normalise(scores) {
+            let size_result = self.set_size(&id, (largest - smallest).mul_add(scaled, smallest));
+            if result.is_ok() {
+                result = size_result;
+            }
+        }
+        result
+    }
+
+    /// Colour nodes by a score, such as one from [`analytics`], blending from `low` for the lowest
+    /// score to `high` for the highest. Nodes without a score keep their colour.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error from any sink, or [`Error::NodeNotFound`] if a scored node does not
+    /// exist. Every other node is still recoloured.
+    pub fn colour_by(
+        &mut self,
+        scores: &HashMap<K, f64>,
+        low: [u8; 3],
+        high: [u8; 3],
+    ) -> Result<()> {
+        let before = self.shown_colours();
+        let mut result = Ok(());
+        for (id, scaled) in analytics::normalise(scores) {
+            match self.graph.get_node_mut(&id) {
+                Some(node) => node.colour = colour::mix(low, high, scaled),
+                None if result.is_ok() => result = Err(Error::node_not_found(&id)),
+                None => {}
+            }
+        }
+        let recolour_result = self.recolour(&before);
+        result.and(recolour_result)
+    }
+
+    /// Advance the simulation by one step, and pass the new positions on to every sink.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error from any sink. The simulation itself still advances.
+    pub fn step(&mut self) -> Result<()> {
+        let mut result = Ok(());
+        if let Some(interval) = self.community_detection.interval {
+            if self.steps.is_multiple_of(interval.max(1)) {
+                result = self.detect_communities();
+            }
+        }
+        if let Some(interval) = self.backbone.and_then(|backbone| backbone.interval) {
+            if self.steps.is_multiple_of(interval.max(1)) {
+                let backbone_result = self.extract_backbone();
+                if result.is_ok() {
+                    result = backbone_result;
+                }
+            }
+        }
+
         // First calculate the acceleration for each node, then apply it.
         // This is necessary because the acceleration depends on the positions of all nodes.
-        let mut node_accel = Vec::with_capacity(self.graph.node_count());
-        for node in self.graph.nodes() {
-            node_accel.push((node.id, self.node_acceleration(node)));
-        }
-        #[cfg(feature = "masquerade")]
-        self.im.new_frame();
+        let node_accel = match self.layout {
+            Layout::Complete => self
+                .graph
+                .nodes()
+                .map(|node| (node.id.clone(), self.node_acceleration(node)))
+                .collect::<Vec<_>>(),
+            Layout::Sparse(repulsion) => self.sparse_accelerations(&repulsion),
+        };
         for (id, accel) in node_accel {
-            self.move_node(id, accel);
+            let move_result = self.move_node(&id, accel);
+            if result.is_ok() {
+                result = move_result;
+            }
         }
         self.steps += 1;
-        #[cfg(feature = "lottie")]
-        self.history.next_step();
-        #[cfg(feature = "raster")]
-        self.render_raster_frame();
+        let steps = self.steps;
+        let step_result = self.broadcast(|sink| sink.step_finished(steps));
+        result.and(step_result)
     }
 
-    fn node_acceleration(&self, node: &Node) -> Vec2d {
+    fn node_acceleration(&self, node: &Node<K>) -> Vec2d {
         let mut accel = Vec2d::new(0., 0.);
-        for (sibling, weight) in self.graph.edges(node.id) {
+        for (sibling, weight) in self.graph.edges(node.id.clone()) {
+            let weight = if self.is_laid_out(&node.id, &sibling.id) {
+                weight
+            } else {
+                0.
+            };
             let spring_length = (self.max_distance() - weight).max(MIN_SPRING_LENGTH);
-            let force = SPRING_CONSTANT * (node.pos.distance(sibling.pos) - spring_length);
-            let direction = (sibling.pos - node.pos).as_unit();
-            accel += direction * force;
+            accel += spring(node.pos, sibling.pos, spring_length);
         }
         accel
     }
 
-    fn move_node(&mut self, id: u64, accel: Vec2d) {
-        let node = self.graph.get_node_mut(&id).unwrap();
+    /// Calculate the acceleration of every node in a sparse layout, where only edges with a
+    /// positive weight have springs. Their rest length doesn't depend on the size of the graph,
+    /// since the repulsion keeps everything else apart.
+    fn sparse_accelerations(&self, repulsion: &Repulsion) -> Vec<(K, Vec2d)> {
+        let nodes = self.graph.nodes().collect::<Vec<_>>();
+        let positions = nodes.iter().map(|node| node.pos).collect::<Vec<_>>();
+        let tree = QuadTree::new(&positions);
+        let center = Vec2d::new(SIZE / 2., SIZE / 2.);
+        nodes
+            .iter()
+            .enumerate()
+            .map(|(index, node)| {
+                let mut accel =
+                    tree.repulsion(index, repulsion) + (center - node.pos) * repulsion.gravity;
+                for (sibling, &weight) in self.graph.sparse_edges(&node.id) {
+                    // A self-loop has no length to spring back to, so it is ignored, as in the
+                    // complete layout.
+                    if weight > 0.
+                        && sibling.id != node.id
+                        && self.is_laid_out(&node.id, &sibling.id)
+                    {
+                        let spring_length = (TARGET_DENSITY - weight).max(MIN_SPRING_LENGTH);
+                        accel += spring(node.pos, sibling.pos, spring_length);
+                    }
+                }
+                (node.id.clone(), accel)
+            })
+            .collect()
+    }
+
+    fn move_node(&mut self, id: &K, accel: Vec2d) -> Result<()> {
+        let node = self
+            .graph
+            .get_node_mut(id)
+            .ok_or_else(|| Error::node_not_found(id))?;
         node.velocity += accel;
         node.velocity *= DAMPING;
         node.pos += node.velocity;
-        #[cfg(feature = "lottie")]
-        self.history.set_position(id, node.pos);
-        #[cfg(feature = "raster")]
-        self.drawing.place_node(node.order, node.pos);
-        #[cfg(feature = "masquerade")]
-        self.im.place_node(node.palette_index, node.pos);
+        let pos = node.pos;
+        self.broadcast(|sink| sink.node_moved(id, pos))
     }
 
     fn max_distance(&self) -> f32 {
         (self.graph.node_count() as f32).sqrt() * TARGET_DENSITY
     }
 
-    pub fn many_steps(&mut self, count: u64) {
+    /// Advance the simulation by a number of steps.
+    ///
+    /// # Errors
+    ///
+    /// Stops and returns the error if any step fails.
+    pub fn many_steps(&mut self, count: u64) -> Result<()> {
         for _ in 0..count {
-            self.step();
+            self.step()?;
         }
+        Ok(())
     }
 
-    #[cfg(feature = "raster")]
-    fn render_raster_frame(&mut self) {
-        self.drawing.render_frame();
-        #[cfg(feature = "png")]
-        self.render_png_frame();
-        #[cfg(feature = "gif")]
-        self.render_gif_frame();
+    /// Change how communities are found, and how nodes are coloured by them.
+    ///
+    /// If the new settings colour nodes by community, communities are found straight away.
+    /// Otherwise, any nodes which were coloured by community go back to their own colours.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error from any sink. The settings are still changed.
+    pub fn set_community_detection(&mut self, detection: CommunityDetection) -> Result<()> {
+        let before = self.shown_colours();
+        self.community_detection = detection;
+        if self.community_detection.palette.is_some() {
+            self.find_communities();
+        }
+        self.recolour(&before)
     }
 
-    #[cfg(feature = "png")]
-    fn render_png_frame(&mut self) {
-        let mut file = File::create(format!("frames/frame{:04}.png", self.steps)).unwrap();
-        let mut buf_writer = BufWriter::new(&mut file);
-        self.drawing.frame_as_png(&mut buf_writer);
+    /// Find communities now, using the Louvain settings given to
+    /// [`set_community_detection`](Self::set_community_detection), and recolour any nodes whose
+    /// community colour changed.
+    ///
+    /// Communities keep the numbers they had last time where possible (see
+    /// [`community::match_previous`]), so that colours don't change needlessly.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error from any sink. The communities are still updated.
+    pub fn detect_communities(&mut self) -> Result<()> {
+        let before = self.shown_colours();
+        self.find_communities();
+        self.recolour(&before)
     }
 
-    #[cfg(feature = "gif")]
-    fn render_gif_frame(&mut self) {
-        let mut frame = self.drawing.frame_as_gif();
-        frame.delay = 2;
-        self.gif.write_frame(&frame).unwrap();
+    /// Get the community a node was in when communities were last found.
+    ///
+    /// Returns `None` if the node does not exist, or was added since then.
+    pub fn community(&self, id: &K) -> Option<usize> {
+        self.graph.get_node(id)?.community
     }
 
-    #[cfg(feature = "lottie")]
-    pub fn render_lottie(&self) {
-        println!("{}", self.history.render().as_json());
+    fn find_communities(&mut self) {
+        let previous = self
+            .graph
+            .nodes()
+            .filter_map(|node| Some((node.id.clone(), node.community?)))
+            .collect::<HashMap<_, _>>();
+        let next = self.community_detection.louvain.detect(&self.graph);
+        for (id, community) in community::match_previous(&previous, &next) {
+            if let Some(node) = self.graph.get_node_mut(&id) {
+                node.community = Some(community);
+            }
+        }
+    }
+
+    fn shown_colour(&self, node: &Node<K>) -> [u8; 3] {
+        node.community
+            .and_then(|community| self.community_detection.colour(community))
+            .unwrap_or(node.colour)
+    }
+
+    fn shown_colours(&self) -> HashMap<K, [u8; 3]> {
+        self.graph
+            .nodes()
+            .map(|node| (node.id.clone(), self.shown_colour(node)))
+            .collect()
+    }
+
+    /// Tell sinks about every node whose colour is no longer what it was in `before`.
+    fn recolour(&mut self, before: &HashMap<K, [u8; 3]>) -> Result<()> {
+        let changed = self
+            .graph
+            .nodes()
+            .map(|node| (node.id.clone(), self.shown_colour(node)))
+            .filter(|(id, colour)| before.get(id) != Some(colour))
+            .collect::<Vec<_>>();
+        let mut result = Ok(());
+        for (id, colour) in changed {
+            let recolour_result = self.broadcast(|sink| sink.node_recoloured(&id, colour));
+            if result.is_ok() {
+                result = recolour_result;
+            }
+        }
+        result
+    }
+
+    /// Choose how the [`backbone`] of the graph is used from now on, or stop using one with
+    /// `None`.
+    ///
+    /// The backbone is extracted straight away, and sinks are told about every edge which is
+    /// hidden or drawn again as a result, as if its weight had changed to 0 or back.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error from any sink. The settings are still changed.
+    pub fn set_backbone(&mut self, backbone: Option<BackboneFilter>) -> Result<()> {
+        let before = self.drawn_edges();
+        self.backbone = backbone;
+        self.find_backbone();
+        self.redraw(&before)
+    }
+
+    /// Extract the backbone now, using the settings given to
+    /// [`set_backbone`](Self::set_backbone), and tell sinks about every edge which is hidden or
+    /// drawn again as a result.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error from any sink. The backbone is still updated.
+    pub fn extract_backbone(&mut self) -> Result<()> {
+        let before = self.drawn_edges();
+        self.find_backbone();
+        self.redraw(&before)
+    }
+
+    fn find_backbone(&mut self) {
+        self.backbone_edges = self.backbone.map_or_else(EdgeSet::new, |backbone| {
+            backbone.backbone.edges(&self.graph)
+        });
+    }
+
+    /// Check whether the edge between two nodes pulls them together in the layout.
+    fn is_laid_out(&self, from: &K, to: &K) -> bool {
+        !self.backbone.is_some_and(|backbone| backbone.layout)
+            || self.backbone_edges.contains(from, to)
+    }
+
+    /// Check whether sinks are told about the edge between two nodes.
+    fn is_drawn(&self, from: &K, to: &K) -> bool {
+        !self.backbone.is_some_and(|backbone| backbone.draw)
+            || self.backbone_edges.contains(from, to)
+    }
+
+    /// Get every edge which sinks have been told about.
+    fn drawn_edges(&self) -> HashSet<(K, K)> {
+        self.graph
+            .edges_unique()
+            .filter(|&(from, to, &weight)| weight != 0. && self.is_drawn(&from.id, &to.id))
+            .map(|(from, to, _)| (from.id.clone(), to.id.clone()))
+            .collect()
+    }
+
+    /// Tell sinks about every edge which is no longer drawn, or is now drawn when it wasn't in
+    /// `before`.
+    fn redraw(&mut self, before: &HashSet<(K, K)>) -> Result<()> {
+        let changed = self
+            .graph
+            .edges_unique()
+            .filter_map(|(from, to, &weight)| {
+                let was_drawn = before.contains(&(from.id.clone(), to.id.clone()));
+                let drawn = weight != 0. && self.is_drawn(&from.id, &to.id);
+                let weight = match (was_drawn, drawn) {
+                    (true, false) => 0.,
+                    (false, true) => weight,
+                    _ => return None,
+                };
+                Some((from.id.clone(), to.id.clone(), weight))
+            })
+            .collect::<Vec<_>>();
+        let mut result = Ok(());
+        for (from, to, weight) in changed {
+            let redraw_result = self.broadcast(|sink| sink.weight_changed(&from, &to, weight));
+            if result.is_ok() {
+                result = redraw_result;
+            }
+        }
+        result
     }
 
-    #[cfg(feature = "masquerade")]
-    pub fn render_masquerade(&self) {
-        let file = File::create("test.gif").unwrap();
-        let mut buf_writer = BufWriter::new(file);
-        self.im.render(&mut buf_writer);
+    /// Tell every sink that the simulation is over, so they can write any buffered output.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error from any sink. Every sink is still finished.
+    pub fn finish(&mut self) -> Result<()> {
+        self.broadcast(|sink| sink.finish())
     }
 }
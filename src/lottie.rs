@@ -157,6 +157,7 @@ impl WriteJson for Ellipse {
 /// A path with just two points, and no curves.
 ///
 /// While Lottie supports paths with multiple points and bezier curves, this is all we need for now.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Segment(pub Coords, pub Coords);
 
 impl WriteJson for Segment {
@@ -164,11 +165,11 @@ impl WriteJson for Segment {
         // `c: false` means that the path does not form a closed loop.
         // The `i` and `o` fields define the way in which the path curves - the values here mean
         // that the path is straight.
-        s.push_str(r#"{"c":false,"i":[[0,0],[0,0]],"o":[[0,0],[0,0]],"v":["#);
+        s.push_str(r#"{"c":false,"v":["#);
         self.0.write_json(s);
         s.push(',');
         self.1.write_json(s);
-        s.push_str("]}");
+        s.push_str(r#"],"i":[[0,0],[0,0]],"o":[[0,0],[0,0]]}"#);
     }
 }
 
@@ -179,7 +180,7 @@ pub struct Line {
 
 impl WriteJson for Line {
     fn write_json(&self, s: &mut String) {
-        s.push_str(r#"{"ty":"fl","ks":"#);
+        s.push_str(r#"{"ty":"sh","ks":"#);
         self.segment.write_json(s);
         s.push('}');
     }
@@ -238,6 +239,17 @@ impl WriteJson for Stroke {
     }
 }
 
+/// A property type for the scale of a layer, as a percentage of its natural size in both
+/// directions.
+#[derive(Clone, Copy, Debug)]
+pub struct Scale(pub f32);
+
+impl WriteJson for Scale {
+    fn write_json(&self, s: &mut String) {
+        write!(s, "[{:.2},{:.2}]", self.0, self.0).unwrap();
+    }
+}
+
 /// A "shape" used to define part of a layer.
 ///
 /// In Lottie, "shape" refers to any vector related data. This includes actual shapes, as well as
@@ -262,11 +274,11 @@ impl WriteJson for Shape {
     }
 }
 
-/// A layer in a Lottie file.
+/// A layer made of vector shapes.
 ///
 /// For our purposes, a layer will typically include two or three "shapes": an actual shape,
 /// followed by a fill style and/or a stroke style.
-pub struct Layer {
+pub struct ShapeLayer {
     /// The first frame for which this layer should be visible.
     ///
     /// Note that this corresponds to the `ip` ("in point") field in Lottie, not
@@ -278,7 +290,7 @@ pub struct Layer {
     pub shapes: Vec<Shape>,
 }
 
-impl WriteJson for Layer {
+impl WriteJson for ShapeLayer {
     fn write_json(&self, s: &mut String) {
         s.push_str(r#"{"ip":"#);
         write!(s, "{}", self.start).unwrap();
@@ -290,17 +302,140 @@ impl WriteJson for Layer {
     }
 }
 
+/// A layer showing an image [`Asset`], clipped to the largest circle that fits inside it.
+pub struct ImageLayer {
+    /// The first frame for which this layer should be visible, as for [`ShapeLayer::start`].
+    pub start: u32,
+    /// The last frame for which this layer should be visible.
+    pub end: u32,
+    /// The ID of the asset to show.
+    pub asset: String,
+    /// The size of the image, in pixels.
+    pub size: Coords,
+    /// Where the centre of the image is placed.
+    pub position: Prop<Coords>,
+    /// How much the image is scaled by, around its centre.
+    pub scale: Prop<Scale>,
+}
+
+impl WriteJson for ImageLayer {
+    fn write_json(&self, s: &mut String) {
+        let Coords(width, height) = self.size;
+        write!(
+            s,
+            r#"{{"ty":2,"refId":"{}","ip":{},"op":{},"st":0,"ks":{{"a":"#,
+            self.asset, self.start, self.end
+        )
+        .unwrap();
+        Prop::Static(Coords(width / 2, height / 2)).write_json(s);
+        s.push_str(r#","p":"#);
+        self.position.write_json(s);
+        s.push_str(r#","s":"#);
+        self.scale.write_json(s);
+        // The mask is an alpha mask ("a") in the layer's own coordinates, so it is transformed
+        // along with the image.
+        s.push_str(r#"},"hasMask":true,"masksProperties":[{"mode":"a","o":{"a":0,"k":100},"pt":"#);
+        s.push_str(r#"{"a":0,"k":"#);
+        write_circle(
+            s,
+            width as f32 / 2.,
+            height as f32 / 2.,
+            width.min(height) as f32 / 2.,
+        );
+        s.push_str("}}]}");
+    }
+}
+
+/// Write a closed path approximating a circle with four cubic Bézier curves.
+fn write_circle(s: &mut String, x: f32, y: f32, radius: f32) {
+    // The distance of each control point from its vertex for the curves to best fit the circle.
+    let k = radius * 0.552_284_8;
+    write!(
+        s,
+        r#"{{"c":true,"v":[[{x},{top}],[{right},{y}],[{x},{bottom}],[{left},{y}]],"#,
+        top = y - radius,
+        right = x + radius,
+        bottom = y + radius,
+        left = x - radius,
+    )
+    .unwrap();
+    write!(
+        s,
+        r#""i":[[-{k:.2},0],[0,-{k:.2}],[{k:.2},0],[0,{k:.2}]],"o":[[{k:.2},0],[0,{k:.2}],[-{k:.2},0],[0,-{k:.2}]]}}"#
+    )
+    .unwrap();
+}
+
+/// A layer in a Lottie file.
+pub enum Layer {
+    Shape(ShapeLayer),
+    Image(ImageLayer),
+}
+
+impl WriteJson for Layer {
+    fn write_json(&self, s: &mut String) {
+        match self {
+            Self::Shape(layer) => layer.write_json(s),
+            Self::Image(layer) => layer.write_json(s),
+        }
+    }
+}
+
+/// An image embedded in a Lottie file, to be shown by an [`ImageLayer`].
+pub struct Asset {
+    pub id: String,
+    /// The size of the image, in pixels.
+    pub size: Coords,
+    /// The contents of a PNG file.
+    pub png: Vec<u8>,
+}
+
+impl WriteJson for Asset {
+    fn write_json(&self, s: &mut String) {
+        // `e: 1` marks the image as embedded in `p` as a data URL, rather than a separate file.
+        write!(
+            s,
+            r#"{{"id":"{}","w":{},"h":{},"u":"","p":"data:image/png;base64,"#,
+            self.id, self.size.0, self.size.1
+        )
+        .unwrap();
+        write_base64(s, &self.png);
+        s.push_str(r#"","e":1}"#);
+    }
+}
+
+/// Write data encoded as standard, padded base64.
+fn write_base64(s: &mut String, data: &[u8]) {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    for chunk in data.chunks(3) {
+        let bits = chunk
+            .iter()
+            .enumerate()
+            .fold(0, |bits, (i, &byte)| bits | u32::from(byte) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                s.push(ALPHABET[(bits >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                s.push('=');
+            }
+        }
+    }
+}
+
 /// A complete Lottie file.
 pub struct File {
     pub frame_rate: u32,
     pub width: u32,
     pub height: u32,
     pub length: u32,
+    /// The images used by image layers.
+    pub assets: Vec<Asset>,
     /// The layers, top to bottom.
     pub layers: Vec<Layer>,
 }
 
 impl File {
+    #[must_use]
     pub fn as_json(&self) -> String {
         let mut s = String::new();
         s.push_str(r#"{"fr":"#);
@@ -311,6 +446,11 @@ impl File {
         write!(s, "{}", self.width).unwrap();
         s.push_str(r#","h":"#);
         write!(s, "{}", self.height).unwrap();
+        if !self.assets.is_empty() {
+            s.push_str(r#","assets":["#);
+            self.assets.write_json(&mut s);
+            s.push(']');
+        }
         s.push_str(r#","layers":["#);
         self.layers.write_json(&mut s);
         s.push_str("]}");
@@ -346,13 +486,13 @@ macro_rules! shape {
 #[macro_export]
 macro_rules! layer {
     (($start:expr; $end:expr) $( $name:ident $props:tt )*) => {
-        $crate::lottie::Layer {
+        $crate::lottie::Layer::Shape($crate::lottie::ShapeLayer {
             start: $start,
             end: $end,
             shapes: vec![
                 $( shape!($name $props), )*
             ],
-        }
+        })
     };
 }
 
@@ -360,15 +500,32 @@ pub use {layer, prop, shape};
 
 #[cfg(test)]
 mod tests {
-    use super::{Colour, Coords, File, Segment};
+    use super::{write_base64, Colour, Coords, File, Segment};
+
+    #[test]
+    fn base64() {
+        let encode = |data: &[u8]| {
+            let mut s = String::new();
+            write_base64(&mut s, data);
+            s
+        };
+        assert_eq!(encode(b""), "");
+        assert_eq!(encode(b"f"), "Zg==");
+        assert_eq!(encode(b"fo"), "Zm8=");
+        assert_eq!(encode(b"foo"), "Zm9v");
+        assert_eq!(encode(b"foobar"), "Zm9vYmFy");
+        assert_eq!(encode(&[0xff, 0xfe]), "//4=");
+    }
 
     #[test]
+    #[allow(clippy::too_many_lines)]
     fn entire_file() {
         let file = File {
             frame_rate: 60,
             width: 512,
             height: 512,
             length: 120,
+            assets: Vec::new(),
             layers: vec![
                 layer! {
                     (0; 60)
@@ -472,7 +629,7 @@ mod tests {
         };
         assert_eq!(
             file.as_json(),
-            r#"{"fr":60,"ip":0,"op":120,"w":512,"h":512,"layers":[{"ip":0,"op":60,"st":0,"ks":{},"ty":4,"shapes":[{"ty":"sh","ks":{"a":0,"k":{"c":false,"v":[[128,256],[384,256]],"i":[[0,0],[0,0]],"o":[[0,0],[0,0]]}}},{"ty":"st","o":{"a":0,"k":100},"c":{"a":0,"k":[0,0,0]},"w":{"a":0,"k":1}}]},{"ip":0,"op":120,"st":0,"ks":{},"ty":4,"shapes":[{"ty":"sh","ks":{"a":1,"k":[{"t":0,"i":{"x":1,"y":1},"o":{"x":0,"y":0},"s":[{"c":false,"v":[[0,0],[512,512]],"i":[[0,0],[0,0]],"o":[[0,0],[0,0]]}]},{"t":30,"i":{"x":1,"y":1},"o":{"x":0,"y":0},"s":[{"c":false,"v":[[512,0],[0,512]],"i":[[0,0],[0,0]],"o":[[0,0],[0,0]]}]},{"t":60,"i":{"x":1,"y":1},"o":{"x":0,"y":0},"s":[{"c":false,"v":[[512,512],[0,0]],"i":[[0,0],[0,0]],"o":[[0,0],[0,0]]}]},{"t":90,"i":{"x":1,"y":1},"o":{"x":0,"y":0},"s":[{"c":false,"v":[[0,512],[512,0]],"i":[[0,0],[0,0]],"o":[[0,0],[0,0]]}]},{"t":120,"i":{"x":1,"y":1},"o":{"x":0,"y":0},"s":[{"c":false,"v":[[0,0],[512,512]],"i":[[0,0],[0,0]],"o":[[0,0],[0,0]]}]}]}},{"ty":"st","o":{"a":0,"k":100},"c":{"a":0,"k":[1,1,0]},"w":{"a":0,"k":16}}]},{"ip":30,"op":60,"st":0,"ks":{},"ty":4,"shapes":[{"ty":"el","p":{"a":1,"k":[{"t":30,"i":{"x":1,"y":1},"o":{"x":0,"y":0},"s":[64,64]},{"t":60,"i":{"x":1,"y":1},"o":{"x":0,"y":0},"s":[448,64]}]},"s":{"a":0,"k":[64,64]}},{"ty":"st","o":{"a":0,"k":100},"c":{"a":0,"k":[0,0,1]},"w":{"a":0,"k":8}},{"ty":"fl","o":{"a":0,"k":50},"c":{"a":0,"k":[0,1,0]}}]},{"ip":90,"op":120,"st":0,"ks":{},"ty":4,"shapes":[{"ty":"el","p":{"a":1,"k":[{"t":90,"i":{"x":1,"y":1},"o":{"x":0,"y":0},"s":[448,448]},{"t":120,"i":{"x":1,"y":1},"o":{"x":0,"y":0},"s":[64,448]}]},"s":{"a":0,"k":[64,64]}},{"ty":"st","o":{"a":0,"k":100},"c":{"a":0,"k":[0,1,0]},"w":{"a":0,"k":8}},{"ty":"fl","o":{"a":0,"k":50},"c":{"a":0,"k":[0,0,1]}}]},{"ip":0,"op":100,"st":0,"ks":{},"ty":4,"shapes":[{"ty":"el","p":{"a":0,"k":[256,256]},"s":{"a":1,"k":[{"t":0,"i":{"x":1,"y":1},"o":{"x":0,"y":0},"s":[0,0]},{"t":100,"i":{"x":1,"y":1},"o":{"x":0,"y":0},"s":[362,362]}]}},{"ty":"fl","o":{"a":1,"k":[{"t":0,"i":{"x":1,"y":1},"o":{"x":0,"y":0},"s":[0]},{"t":100,"i":{"x":1,"y":1},"o":{"x":0,"y":0},"s":[100]}]},"c":{"a":1,"k":[{"t":0,"i":{"x":1,"y":1},"o":{"x":0,"y":0},"s":[0,0,0]},{"t":100,"i":{"x":1,"y":1},"o":{"x":0,"y":0},"s":[1,1,1]}]}}]},{"ip":0,"op":120,"st":0,"ks":{},"ty":4,"shapes":[{"ty":"rc","p":{"a":0,"k":[256,256]},"s":{"a":0,"k":[512,512]},"r":{"a":0,"k":0}},{"ty":"fl","o":{"a":0,"k":100},"c":{"a":0,"k":[1,0,0]}}]}]}"#
+            r#"{"fr":60,"ip":0,"op":120,"w":512,"h":512,"layers":[{"ip":0,"op":60,"st":0,"ks":{},"ty":4,"shapes":[{"ty":"sh","ks":{"a":0,"k":{"c":false,"v":[[128,256],[384,256]],"i":[[0,0],[0,0]],"o":[[0,0],[0,0]]}}},{"ty":"st","o":{"a":0,"k":100},"c":{"a":0,"k":[0.000,0.000,0.000]},"w":{"a":0,"k":1}}]},{"ip":0,"op":120,"st":0,"ks":{},"ty":4,"shapes":[{"ty":"sh","ks":{"a":1,"k":[{"t":0,"i":{"x":1,"y":1},"o":{"x":0,"y":0},"s":[{"c":false,"v":[[0,0],[512,512]],"i":[[0,0],[0,0]],"o":[[0,0],[0,0]]}]},{"t":30,"i":{"x":1,"y":1},"o":{"x":0,"y":0},"s":[{"c":false,"v":[[512,0],[0,512]],"i":[[0,0],[0,0]],"o":[[0,0],[0,0]]}]},{"t":60,"i":{"x":1,"y":1},"o":{"x":0,"y":0},"s":[{"c":false,"v":[[512,512],[0,0]],"i":[[0,0],[0,0]],"o":[[0,0],[0,0]]}]},{"t":90,"i":{"x":1,"y":1},"o":{"x":0,"y":0},"s":[{"c":false,"v":[[0,512],[512,0]],"i":[[0,0],[0,0]],"o":[[0,0],[0,0]]}]},{"t":120,"i":{"x":1,"y":1},"o":{"x":0,"y":0},"s":[{"c":false,"v":[[0,0],[512,512]],"i":[[0,0],[0,0]],"o":[[0,0],[0,0]]}]}]}},{"ty":"st","o":{"a":0,"k":100},"c":{"a":0,"k":[1.000,1.000,0.000]},"w":{"a":0,"k":16}}]},{"ip":30,"op":60,"st":0,"ks":{},"ty":4,"shapes":[{"ty":"el","p":{"a":1,"k":[{"t":30,"i":{"x":1,"y":1},"o":{"x":0,"y":0},"s":[64,64]},{"t":60,"i":{"x":1,"y":1},"o":{"x":0,"y":0},"s":[448,64]}]},"s":{"a":0,"k":[64,64]}},{"ty":"st","o":{"a":0,"k":100},"c":{"a":0,"k":[0.000,0.000,1.000]},"w":{"a":0,"k":8}},{"ty":"fl","o":{"a":0,"k":50},"c":{"a":0,"k":[0.000,1.000,0.000]}}]},{"ip":90,"op":120,"st":0,"ks":{},"ty":4,"shapes":[{"ty":"el","p":{"a":1,"k":[{"t":90,"i":{"x":1,"y":1},"o":{"x":0,"y":0},"s":[448,448]},{"t":120,"i":{"x":1,"y":1},"o":{"x":0,"y":0},"s":[64,448]}]},"s":{"a":0,"k":[64,64]}},{"ty":"st","o":{"a":0,"k":100},"c":{"a":0,"k":[0.000,1.000,0.000]},"w":{"a":0,"k":8}},{"ty":"fl","o":{"a":0,"k":50},"c":{"a":0,"k":[0.000,0.000,1.000]}}]},{"ip":0,"op":100,"st":0,"ks":{},"ty":4,"shapes":[{"ty":"el","p":{"a":0,"k":[256,256]},"s":{"a":1,"k":[{"t":0,"i":{"x":1,"y":1},"o":{"x":0,"y":0},"s":[0,0]},{"t":100,"i":{"x":1,"y":1},"o":{"x":0,"y":0},"s":[362,362]}]}},{"ty":"fl","o":{"a":1,"k":[{"t":0,"i":{"x":1,"y":1},"o":{"x":0,"y":0},"s":[0]},{"t":100,"i":{"x":1,"y":1},"o":{"x":0,"y":0},"s":[100]}]},"c":{"a":1,"k":[{"t":0,"i":{"x":1,"y":1},"o":{"x":0,"y":0},"s":[0.000,0.000,0.000]},{"t":100,"i":{"x":1,"y":1},"o":{"x":0,"y":0},"s":[1.000,1.000,1.000]}]}}]},{"ip":0,"op":120,"st":0,"ks":{},"ty":4,"shapes":[{"ty":"rc","p":{"a":0,"k":[256,256]},"s":{"a":0,"k":[512,512]},"r":{"a":0,"k":0}},{"ty":"fl","o":{"a":0,"k":100},"c":{"a":0,"k":[1.000,0.000,0.000]}}]}]}"#
         );
     }
 }
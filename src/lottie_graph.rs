@@ -1,6 +1,14 @@
-use crate::lottie::{Colour, Coords, Ellipse, File, Fill, Keyframe, Layer, Prop, Shape};
-use crate::{Vec2d, SIZE};
+use crate::colour::EDGE_COLOUR;
+use crate::lottie::{
+    Asset, Colour, Coords, Ellipse, File, Fill, ImageLayer, Keyframe, Layer, Line, Prop, Scale,
+    Segment, Shape, ShapeLayer, Stroke, WriteJson,
+};
+use crate::output::Output;
+use crate::sink::set_at;
+use crate::state::{NodeTable, ReadState, WriteState};
+use crate::{Error, FrameSink, NodeKey, Result, Vec2d, SIZE};
 use hashbrown::HashMap;
+use std::io::{Read, Write};
 
 const NODE_SIZE: u32 = 20;
 
@@ -13,11 +21,86 @@ struct Frame {
 #[derive(Clone, Debug)]
 struct Node {
     start: u32,
-    colour: Colour,
+    // Each colour the node has had, with the step it was first shown at, starting with its
+    // original colour at `start`.
+    colours: Vec<(u32, Colour)>,
+    // Each diameter the node has had, in the same way.
+    sizes: Vec<(u32, u32)>,
     frames: Vec<Frame>,
+    // The contents of a PNG file shown over the node.
+    avatar: Option<Vec<u8>>,
+}
+
+/// Read the width and height of a PNG file from its header, if it is one.
+fn png_size(png: &[u8]) -> Option<Coords> {
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    // The header chunk must come first, after the signature and the chunk's length.
+    if !png.starts_with(SIGNATURE) || png.get(12..16) != Some(b"IHDR") {
+        return None;
+    }
+    let dimension = |offset: usize| {
+        Some(u32::from_be_bytes(
+            png.get(offset..offset + 4)?.try_into().ok()?,
+        ))
+    };
+    Some(Coords(dimension(16)?, dimension(20)?))
+        .filter(|&Coords(width, height)| width > 0 && height > 0)
 }
 
 impl Node {
+    fn save_state(&self, w: &mut dyn Write) -> Result<()> {
+        w.write_u32(self.start)?;
+        w.write_len(self.colours.len())?;
+        for (step, colour) in &self.colours {
+            w.write_u32(*step)?;
+            w.write_f32(colour.0)?;
+            w.write_f32(colour.1)?;
+            w.write_f32(colour.2)?;
+        }
+        w.write_len(self.sizes.len())?;
+        for (step, size) in &self.sizes {
+            w.write_u32(*step)?;
+            w.write_u32(*size)?;
+        }
+        w.write_len(self.frames.len())?;
+        for frame in &self.frames {
+            w.write_u32(frame.pos.0)?;
+            w.write_u32(frame.pos.1)?;
+            w.write_u32(frame.length)?;
+        }
+        // An empty avatar is not a valid PNG, so it can stand for no avatar.
+        w.write_bytes(self.avatar.as_deref().unwrap_or_default())?;
+        Ok(())
+    }
+
+    fn load_state(r: &mut dyn Read) -> Result<Self> {
+        let start = r.read_u32()?;
+        let mut colours = Vec::new();
+        for _ in 0..r.read_len()? {
+            let step = r.read_u32()?;
+            colours.push((step, Colour(r.read_f32()?, r.read_f32()?, r.read_f32()?)));
+        }
+        let mut sizes = Vec::new();
+        for _ in 0..r.read_len()? {
+            sizes.push((r.read_u32()?, r.read_u32()?));
+        }
+        let frame_count = r.read_len()?;
+        let mut frames = Vec::new();
+        for _ in 0..frame_count {
+            let pos = Coords(r.read_u32()?, r.read_u32()?);
+            let length = r.read_u32()?;
+            frames.push(Frame { pos, length });
+        }
+        let avatar = Some(r.read_bytes()?).filter(|avatar| !avatar.is_empty());
+        Ok(Self {
+            start,
+            colours,
+            sizes,
+            frames,
+            avatar,
+        })
+    }
+
     fn push_pos(&mut self, pos: Vec2d) {
         let pos = Coords(pos.x as u32, pos.y as u32);
         if let Some(last) = self.frames.last_mut() {
@@ -29,7 +112,8 @@ impl Node {
         self.frames.push(Frame { pos, length: 1 });
     }
 
-    fn render(&self) -> Layer {
+    /// The position of the node over time, and the frame after its last position.
+    fn positions(&self) -> (Prop<Coords>, u32) {
         let mut frames = Vec::new();
         let mut time = self.start;
         for frame in &self.frames {
@@ -39,75 +123,687 @@ impl Node {
             });
             time += frame.length;
         }
-        Layer {
+        (Prop::Animated(frames), time)
+    }
+
+    fn render(&self) -> ShapeLayer {
+        let (positions, end) = self.positions();
+        ShapeLayer {
             start: self.start,
-            end: time,
+            end,
             shapes: vec![
                 Shape::Ellipse(Ellipse {
-                    center: Prop::Animated(frames),
-                    size: Prop::Static(Coords(NODE_SIZE, NODE_SIZE)),
+                    center: positions,
+                    size: held_prop(
+                        self.start,
+                        &self
+                            .sizes
+                            .iter()
+                            .map(|&(step, size)| (step, Coords(size, size)))
+                            .collect::<Vec<_>>(),
+                        Coords(NODE_SIZE, NODE_SIZE),
+                    ),
                 }),
                 Shape::Fill(Fill {
-                    colour: Prop::Static(self.colour),
+                    colour: held_prop(self.start, &self.colours, Colour(0., 0., 0.)),
                     opacity: Prop::Static(100),
                 }),
             ],
         }
     }
+
+    /// Render the node's avatar as a layer to go above the node itself, scaled so that the
+    /// circle it is clipped to covers the node.
+    fn render_avatar(&self, asset: &Asset) -> ImageLayer {
+        let (position, end) = self.positions();
+        let Coords(width, height) = asset.size;
+        let scale = |diameter: u32| Scale(diameter as f32 / width.min(height) as f32 * 100.);
+        let scales: Vec<_> = self
+            .sizes
+            .iter()
+            .map(|&(step, diameter)| (step, scale(diameter)))
+            .collect();
+        ImageLayer {
+            start: self.start,
+            end,
+            asset: asset.id.clone(),
+            size: asset.size,
+            position,
+            scale: held_prop(self.start, &scales, scale(NODE_SIZE)),
+        }
+    }
+}
+
+/// The line between two nodes for as long as its weight is positive and both nodes exist.
+#[derive(Clone, Debug)]
+struct Edge {
+    // The step of the first frame, once there is one.
+    start: u32,
+    // Each weight the edge has had, with the step it was set at.
+    weights: Vec<(u32, f32)>,
+    // The positions of both ends, run-length encoded as for node positions.
+    frames: Vec<(Segment, u32)>,
+}
+
+impl Edge {
+    fn save_state(&self, w: &mut dyn Write) -> Result<()> {
+        w.write_u32(self.start)?;
+        w.write_len(self.weights.len())?;
+        for &(step, weight) in &self.weights {
+            w.write_u32(step)?;
+            w.write_f32(weight)?;
+        }
+        w.write_len(self.frames.len())?;
+        for (Segment(from, to), length) in &self.frames {
+            for value in [from.0, from.1, to.0, to.1, *length] {
+                w.write_u32(value)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn load_state(r: &mut dyn Read) -> Result<Self> {
+        let start = r.read_u32()?;
+        let mut weights = Vec::new();
+        for _ in 0..r.read_len()? {
+            weights.push((r.read_u32()?, r.read_f32()?));
+        }
+        let mut frames = Vec::new();
+        for _ in 0..r.read_len()? {
+            let from = Coords(r.read_u32()?, r.read_u32()?);
+            let to = Coords(r.read_u32()?, r.read_u32()?);
+            frames.push((Segment(from, to), r.read_u32()?));
+        }
+        Ok(Self {
+            start,
+            weights,
+            frames,
+        })
+    }
+
+    fn push_segment(&mut self, step: u32, segment: Segment) {
+        match self.frames.last_mut() {
+            Some((last, length)) if *last == segment => *length += 1,
+            Some(_) => self.frames.push((segment, 1)),
+            None => {
+                self.start = step;
+                self.frames.push((segment, 1));
+            }
+        }
+    }
+
+    /// Render the edge as a line whose width and opacity follow its weight, relative to
+    /// `max_weight`.
+    fn render(&self, max_weight: f32) -> ShapeLayer {
+        let mut keyframes = Vec::new();
+        let mut time = self.start;
+        for &(segment, length) in &self.frames {
+            keyframes.push(Keyframe {
+                time,
+                value: segment,
+            });
+            time += length;
+        }
+        let strengths: Vec<_> = self
+            .weights
+            .iter()
+            .map(|&(step, weight)| (step, (weight / max_weight).clamp(0., 1.)))
+            .collect();
+        let width = |strength: f32| strength.mul_add(3., 1.).round() as u32;
+        let opacity = |strength: f32| strength.mul_add(50., 30.).round() as u8;
+        ShapeLayer {
+            start: self.start,
+            end: time,
+            shapes: vec![
+                Shape::Line(Line {
+                    segment: Prop::Animated(keyframes),
+                }),
+                Shape::Stroke(Stroke {
+                    colour: Prop::Static(lottie_colour(EDGE_COLOUR)),
+                    opacity: held_prop(
+                        self.start,
+                        &strengths
+                            .iter()
+                            .map(|&(step, strength)| (step, opacity(strength)))
+                            .collect::<Vec<_>>(),
+                        opacity(1.),
+                    ),
+                    width: held_prop(
+                        self.start,
+                        &strengths
+                            .iter()
+                            .map(|&(step, strength)| (step, width(strength)))
+                            .collect::<Vec<_>>(),
+                        width(1.),
+                    ),
+                }),
+            ],
+        }
+    }
+}
+
+/// Build a property which holds each value from the step it was set until the next change, for a
+/// layer starting at `start`. The first value is used from the start, and `default` if there are
+/// none.
+fn held_prop<T: Copy + WriteJson>(start: u32, changes: &[(u32, T)], default: T) -> Prop<T> {
+    match changes {
+        [] => Prop::Static(default),
+        [(_, value)] => Prop::Static(*value),
+        [(_, first), rest @ ..] => {
+            // Keyframes are interpolated, so the old value is held until the frame before each
+            // change for the change to be instant.
+            let mut keyframes = vec![Keyframe {
+                time: start,
+                value: *first,
+            }];
+            let (mut last_time, mut previous) = (start, *first);
+            for &(step, value) in rest {
+                if step > last_time + 1 {
+                    keyframes.push(Keyframe {
+                        time: step - 1,
+                        value: previous,
+                    });
+                }
+                keyframes.push(Keyframe { time: step, value });
+                (last_time, previous) = (step, value);
+            }
+            Prop::Animated(keyframes)
+        }
+    }
+}
+
+fn lottie_colour(colour: [u8; 3]) -> Colour {
+    let [r, g, b] = colour;
+    Colour(
+        f32::from(r) / 255.,
+        f32::from(g) / 255.,
+        f32::from(b) / 255.,
+    )
 }
 
-#[derive(Clone, Debug, Default)]
-pub struct History {
-    open: HashMap<u64, Node>,
+/// A record of the trajectory of every node, and the weight of every edge, to be rendered as a
+/// Lottie animation.
+///
+/// Positions are run-length encoded, so nodes which have settled down cost very little.
+#[derive(Clone, Debug)]
+pub struct History<K = u64> {
+    open: HashMap<K, Node>,
     closed: Vec<Node>,
+    // Edges are keyed by the IDs of their nodes, in the order their weight was first set.
+    open_edges: HashMap<(K, K), Edge>,
+    closed_edges: Vec<Edge>,
     step: u32,
 }
 
-impl History {
+impl<K: NodeKey> Default for History<K> {
+    fn default() -> Self {
+        Self {
+            open: HashMap::new(),
+            closed: Vec::new(),
+            open_edges: HashMap::new(),
+            closed_edges: Vec::new(),
+            step: 0,
+        }
+    }
+}
+
+impl<K: NodeKey> History<K> {
+    #[must_use]
     pub fn new() -> Self {
         Self::default()
     }
 
-    pub fn add_node(&mut self, id: u64, colour: [u8; 3]) {
-        let [r, g, b] = colour;
-        let colour = Colour(r as f32 / 255., g as f32 / 255., b as f32 / 255.);
+    pub fn add_node(&mut self, id: K, colour: [u8; 3]) {
         self.open.insert(
             id,
             Node {
                 start: self.step,
-                colour,
+                colours: vec![(self.step, lottie_colour(colour))],
+                sizes: vec![(self.step, NODE_SIZE)],
                 frames: Vec::new(),
+                avatar: None,
             },
         );
     }
 
-    pub fn remove_node(&mut self, id: u64) {
-        if let Some(node) = self.open.remove(&id) {
+    /// Show a node in a new colour from the current step onwards.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NodeNotFound`] if the node has not been added, or has been removed.
+    pub fn set_colour(&mut self, id: &K, colour: [u8; 3]) -> Result<()> {
+        self.open
+            .get_mut(id)
+            .map(|node| set_at(&mut node.colours, self.step, lottie_colour(colour)))
+            .ok_or_else(|| Error::node_not_found(id))
+    }
+
+    /// Draw a node at a new size from the current step onwards, where 1 is the default size.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NodeNotFound`] if the node has not been added, or has been removed.
+    pub fn set_size(&mut self, id: &K, size: f32) -> Result<()> {
+        let diameter = (NODE_SIZE as f32 * size).round().max(0.) as u32;
+        self.open
+            .get_mut(id)
+            .map(|node| set_at(&mut node.sizes, self.step, diameter))
+            .ok_or_else(|| Error::node_not_found(id))
+    }
+
+    /// Show an avatar over a node, given as the contents of a PNG file. The image is embedded in
+    /// the animation, clipped to a circle covering the node.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Avatar`] if the image is not a PNG file, or [`Error::NodeNotFound`] if the
+    /// node has not been added, or has been removed.
+    pub fn set_avatar(&mut self, id: &K, png: &[u8]) -> Result<()> {
+        if png_size(png).is_none() {
+            return Err(Error::Avatar("invalid PNG header"));
+        }
+        self.open
+            .get_mut(id)
+            .ok_or_else(|| Error::node_not_found(id))?
+            .avatar = Some(png.to_vec());
+        Ok(())
+    }
+
+    /// Stop showing a node, along with its edges, from the current step onwards.
+    pub fn remove_node(&mut self, id: &K) {
+        if let Some(node) = self.open.remove(id) {
             self.closed.push(node);
         }
+        let touching: Vec<_> = self
+            .open_edges
+            .keys()
+            .filter(|(from, to)| from == id || to == id)
+            .cloned()
+            .collect();
+        for key in touching {
+            self.close_edge(&key);
+        }
+    }
+
+    /// Record the weight of the edge between two nodes from the current step onwards. The edge
+    /// is shown for as long as its weight is positive.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NodeNotFound`] if either node has not been added, or has been removed.
+    pub fn set_weight(&mut self, from: &K, to: &K, weight: f32) -> Result<()> {
+        for id in [from, to] {
+            if !self.open.contains_key(id) {
+                return Err(Error::node_not_found(id));
+            }
+        }
+        let reversed = (to.clone(), from.clone());
+        let key = if self.open_edges.contains_key(&reversed) {
+            reversed
+        } else {
+            (from.clone(), to.clone())
+        };
+        if weight <= 0. {
+            self.close_edge(&key);
+        } else if let Some(edge) = self.open_edges.get_mut(&key) {
+            set_at(&mut edge.weights, self.step, weight);
+        } else {
+            self.open_edges.insert(
+                key,
+                Edge {
+                    start: self.step,
+                    weights: vec![(self.step, weight)],
+                    frames: Vec::new(),
+                },
+            );
+        }
+        Ok(())
     }
 
-    pub fn set_position(&mut self, id: u64, pos: Vec2d) {
-        self.open.get_mut(&id).unwrap().push_pos(pos);
+    fn close_edge(&mut self, key: &(K, K)) {
+        // An edge which was never shown has nothing to render.
+        if let Some(edge) = self.open_edges.remove(key) {
+            if !edge.frames.is_empty() {
+                self.closed_edges.push(edge);
+            }
+        }
     }
 
+    /// Record the position of a node for the current step.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NodeNotFound`] if the node has not been added, or has been removed.
+    pub fn set_position(&mut self, id: &K, pos: Vec2d) -> Result<()> {
+        self.open
+            .get_mut(id)
+            .ok_or_else(|| Error::node_not_found(id))?
+            .push_pos(pos);
+        Ok(())
+    }
+
+    /// Finish the current step, recording where each edge is from the positions of its nodes.
     pub fn next_step(&mut self) {
+        for ((from, to), edge) in &mut self.open_edges {
+            let end = |id| Some(self.open.get(id)?.frames.last()?.pos);
+            if let (Some(from), Some(to)) = (end(from), end(to)) {
+                edge.push_segment(self.step, Segment(from, to));
+            }
+        }
         self.step += 1;
     }
 
+    /// Write the recorded history in the checkpoint format (see [`crate::state`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing fails, or if a node which has not been removed is missing from
+    /// `nodes`.
+    pub fn save_state(&self, w: &mut dyn Write, nodes: &NodeTable<K>) -> Result<()> {
+        w.write_u32(self.step)?;
+        w.write_len(self.open.len())?;
+        for (id, node) in &self.open {
+            w.write_u64(nodes.index(id)?)?;
+            node.save_state(w)?;
+        }
+        w.write_len(self.closed.len())?;
+        for node in &self.closed {
+            node.save_state(w)?;
+        }
+        w.write_len(self.open_edges.len())?;
+        for ((from, to), edge) in &self.open_edges {
+            w.write_u64(nodes.index(from)?)?;
+            w.write_u64(nodes.index(to)?)?;
+            edge.save_state(w)?;
+        }
+        w.write_len(self.closed_edges.len())?;
+        for edge in &self.closed_edges {
+            edge.save_state(w)?;
+        }
+        Ok(())
+    }
+
+    /// Read a history written by [`save_state`](Self::save_state).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading fails, the data is truncated, or it refers to a node which is
+    /// not in `nodes`.
+    pub fn load_state(r: &mut dyn Read, nodes: &NodeTable<K>) -> Result<Self> {
+        let step = r.read_u32()?;
+        let mut open = HashMap::new();
+        for _ in 0..r.read_len()? {
+            let id = nodes.key(r.read_u64()?)?.clone();
+            open.insert(id, Node::load_state(r)?);
+        }
+        let mut closed = Vec::new();
+        for _ in 0..r.read_len()? {
+            closed.push(Node::load_state(r)?);
+        }
+        let mut open_edges = HashMap::new();
+        for _ in 0..r.read_len()? {
+            let from = nodes.key(r.read_u64()?)?.clone();
+            let to = nodes.key(r.read_u64()?)?.clone();
+            open_edges.insert((from, to), Edge::load_state(r)?);
+        }
+        let mut closed_edges = Vec::new();
+        for _ in 0..r.read_len()? {
+            closed_edges.push(Edge::load_state(r)?);
+        }
+        Ok(Self {
+            open,
+            closed,
+            open_edges,
+            closed_edges,
+            step,
+        })
+    }
+
+    #[must_use]
     pub fn render(&self) -> File {
-        let layers = self
-            .closed
-            .iter()
-            .chain(self.open.values())
-            .map(|node| node.render())
-            .collect();
+        let mut assets: Vec<Asset> = Vec::new();
+        let mut layers = Vec::new();
+        for node in self.closed.iter().chain(self.open.values()) {
+            // Nodes with the same avatar share a single copy of it.
+            if let Some((png, size)) = node
+                .avatar
+                .as_ref()
+                .and_then(|png| Some((png, png_size(png)?)))
+            {
+                let index = assets
+                    .iter()
+                    .position(|asset| asset.png == *png)
+                    .unwrap_or_else(|| {
+                        assets.push(Asset {
+                            id: format!("avatar{}", assets.len()),
+                            size,
+                            png: png.clone(),
+                        });
+                        assets.len() - 1
+                    });
+                layers.push(Layer::Image(node.render_avatar(&assets[index])));
+            }
+            layers.push(Layer::Shape(node.render()));
+        }
+        // Edges go beneath every node, and are drawn relative to the heaviest weight of any edge
+        // at any time.
+        let edges = || self.closed_edges.iter().chain(self.open_edges.values());
+        let max_weight = edges()
+            .flat_map(|edge| &edge.weights)
+            .map(|&(_, weight)| weight)
+            .fold(0., f32::max);
+        layers.extend(
+            edges()
+                .filter(|edge| !edge.frames.is_empty())
+                .map(|edge| Layer::Shape(edge.render(max_weight))),
+        );
         File {
             frame_rate: 60,
             width: SIZE as u32,
             height: SIZE as u32,
             length: self.step,
+            assets,
             layers,
         }
     }
 }
+
+/// A sink which records a [`History`], and writes it as a Lottie JSON file when the simulation
+/// finishes.
+pub struct LottieAnimation<K = u64> {
+    history: History<K>,
+    // This is only `None` once the animation has been written.
+    output: Option<Box<dyn Write>>,
+}
+
+impl<K: NodeKey> LottieAnimation<K> {
+    /// Create a sink which will write to the given destination when finished.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the destination cannot be opened.
+    pub fn new(output: impl Into<Output>) -> Result<Self> {
+        Ok(Self {
+            history: History::new(),
+            output: Some(output.into().open()?),
+        })
+    }
+
+    /// Get the history recorded so far.
+    #[must_use]
+    pub const fn history(&self) -> &History<K> {
+        &self.history
+    }
+}
+
+impl<K: NodeKey> FrameSink<K> for LottieAnimation<K> {
+    fn node_added(&mut self, id: &K, colour: [u8; 3]) -> Result<()> {
+        self.history.add_node(id.clone(), colour);
+        Ok(())
+    }
+
+    fn avatar_set(&mut self, id: &K, png: &[u8]) -> Result<()> {
+        self.history.set_avatar(id, png)
+    }
+
+    fn node_recoloured(&mut self, id: &K, colour: [u8; 3]) -> Result<()> {
+        self.history.set_colour(id, colour)
+    }
+
+    fn node_resized(&mut self, id: &K, size: f32) -> Result<()> {
+        self.history.set_size(id, size)
+    }
+
+    fn node_removed(&mut self, id: &K) -> Result<()> {
+        self.history.remove_node(id);
+        Ok(())
+    }
+
+    fn weight_changed(&mut self, from: &K, to: &K, weight: f32) -> Result<()> {
+        self.history.set_weight(from, to, weight)
+    }
+
+    fn node_moved(&mut self, id: &K, pos: Vec2d) -> Result<()> {
+        self.history.set_position(id, pos)
+    }
+
+    fn step_finished(&mut self, _step: u64) -> Result<()> {
+        self.history.next_step();
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if let Some(mut output) = self.output.take() {
+            output.write_all(self.history.render().as_json().as_bytes())?;
+            output.flush()?;
+        }
+        Ok(())
+    }
+
+    fn save_state(&self, w: &mut dyn Write, nodes: &NodeTable<K>) -> Result<()> {
+        self.history.save_state(w, nodes)
+    }
+
+    fn load_state(&mut self, r: &mut dyn Read, nodes: &NodeTable<K>) -> Result<()> {
+        self.history = History::load_state(r, nodes)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{History, LottieAnimation};
+    use crate::lottie::{Coords, Segment};
+    use crate::state::NodeTable;
+    use crate::{Error, SharedBuffer, System, Vec2d};
+
+    /// The start of a 64 by 32 pixel PNG file, which is all that is needed to embed it.
+    const AVATAR: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR\0\0\0\x40\0\0\0\x20\x08\x06\0\0\0";
+
+    #[test]
+    fn state_round_trip() {
+        let mut history = History::new();
+        history.add_node("alice", [255, 0, 0]);
+        history.add_node("bob", [0, 0, 255]);
+        history.add_node("carol", [0, 0, 0]);
+        history.set_weight(&"alice", &"bob", 1.).unwrap();
+        history.set_weight(&"carol", &"bob", 2.).unwrap();
+        for step in 0..10 {
+            history
+                .set_position(&"alice", Vec2d::new(step as f32, 0.))
+                .unwrap();
+            history.set_position(&"bob", Vec2d::new(5., 5.)).unwrap();
+            history.set_position(&"carol", Vec2d::new(0., 5.)).unwrap();
+            history.next_step();
+        }
+        history.set_colour(&"bob", [0, 255, 0]).unwrap();
+        history.set_size(&"bob", 1.5).unwrap();
+        history.set_avatar(&"bob", AVATAR).unwrap();
+        history.set_weight(&"bob", &"carol", 3.).unwrap();
+        history.remove_node(&"alice");
+
+        let nodes = NodeTable::new(vec!["bob", "carol"]);
+        let mut state = Vec::new();
+        history.save_state(&mut state, &nodes).unwrap();
+        let loaded = History::load_state(&mut state.as_slice(), &nodes).unwrap();
+        assert_eq!(loaded.step, history.step);
+        assert_eq!(loaded.closed.len(), 1);
+        assert_eq!(loaded.open["bob"].frames.len(), 1);
+        assert_eq!(loaded.open["bob"].frames[0].length, 10);
+        assert_eq!(loaded.open["bob"].colours.len(), 2);
+        assert_eq!(loaded.open["bob"].colours[1].0, 10);
+        assert_eq!(loaded.open["bob"].sizes, [(0, 20), (10, 30)]);
+        assert_eq!(loaded.open["bob"].avatar.as_deref(), Some(AVATAR));
+        assert_eq!(loaded.closed[0].avatar, None);
+        assert_eq!(loaded.closed_edges.len(), 1);
+        assert_eq!(loaded.closed_edges[0].frames.len(), 10);
+        let edge = &loaded.open_edges[&("carol", "bob")];
+        assert_eq!(edge.weights, [(0, 2.), (10, 3.)]);
+        assert_eq!(edge.frames, [(Segment(Coords(0, 5), Coords(5, 5)), 10)]);
+    }
+
+    #[test]
+    fn embeds_avatars() {
+        let buffer = SharedBuffer::new();
+        let mut system = System::with_seed(1);
+        system
+            .add_sink(LottieAnimation::new(buffer.clone()).unwrap())
+            .unwrap();
+        system.add_node_with_avatar(1, [255, 0, 0], AVATAR.to_vec());
+        system.add_node_with_avatar(2, None, AVATAR.to_vec());
+        system.add_node(3, None);
+        assert!(matches!(
+            system.try_add_node_with_avatar(4, None, b"GIF89a".to_vec()),
+            Err(Error::Avatar(_))
+        ));
+        system.many_steps(5).unwrap();
+        system.finish().unwrap();
+
+        let json = String::from_utf8(buffer.take()).unwrap();
+        // Both nodes share one copy of the image.
+        assert_eq!(
+            json.matches(r#""p":"data:image/png;base64,iVBORw0KGgo"#)
+                .count(),
+            1
+        );
+        assert_eq!(json.matches(r#""ty":2,"refId":"avatar0""#).count(), 2);
+        assert_eq!(json.matches(r#""ty":4"#).count(), 4);
+        // The 64 by 32 image is clipped to a circle of radius 16 and scaled to a diameter of 20.
+        assert!(json.contains(r#""v":[[32,0],[48,16],[32,32],[16,16]]"#));
+        assert!(json.contains(r#""s":{"a":0,"k":[62.50,62.50]}"#));
+    }
+
+    #[test]
+    fn animates_edges() {
+        let buffer = SharedBuffer::new();
+        let mut system = System::with_seed(1);
+        system.add_node(1, [255, 0, 0]);
+        system.add_node(2, [0, 0, 255]);
+        system.add_node(3, None);
+        system.set_weight(1, 2, 10.);
+        system.set_weight(2, 3, 5.);
+        system.set_weight(1, 3, -5.);
+        system
+            .add_sink(LottieAnimation::new(buffer.clone()).unwrap())
+            .unwrap();
+        system.many_steps(3).unwrap();
+        system.set_weight(2, 3, 10.);
+        system.many_steps(3).unwrap();
+        system.remove_node(&3);
+        system.many_steps(3).unwrap();
+        system.finish().unwrap();
+
+        let json = String::from_utf8(buffer.take()).unwrap();
+        // Three nodes, and the two edges with a positive weight.
+        assert_eq!(json.matches(r#""ty":4"#).count(), 5);
+        assert_eq!(json.matches(r#""ty":"sh""#).count(), 2);
+        assert_eq!(json.matches(r#""ty":"st""#).count(), 2);
+        // The edge to the removed node ends with it, after getting heavier.
+        assert!(json.contains(r#"{"ip":0,"op":6,"st":0,"ks":{},"ty":4,"shapes":[{"ty":"sh""#));
+        assert!(json.contains(r#""w":{"a":1,"k":[{"t":0,"i":{"x":1,"y":1},"o":{"x":0,"y":0},"s":[3]},{"t":2,"i":{"x":1,"y":1},"o":{"x":0,"y":0},"s":[3]},{"t":3,"i":{"x":1,"y":1},"o":{"x":0,"y":0},"s":[4]}]}"#));
+        // The other edge is the heaviest throughout.
+        assert!(json.contains(
+            r#""o":{"a":0,"k":80},"c":{"a":0,"k":[0.576,0.631,0.631]},"w":{"a":0,"k":4}"#
+        ));
+    }
+}
@@ -1,49 +1,66 @@
-use snowball::System;
+use snowball::colour::{AutoColour, Palette};
+use snowball::community::CommunityDetection;
+use snowball::import::{Event, Op, Scheduler};
+use snowball::{Result, System};
 
-fn test1() {
-    let mut system = System::new();
-    system.add_node(0, [181, 137, 0]);
-    system.add_node(1, [203, 75, 22]);
-    system.add_node(2, [220, 50, 47]);
-    system.add_node(3, [211, 54, 130]);
-    system.add_node(4, [108, 113, 196]);
-    system.many_steps(150);
-    system.set_weight(0, 1, 50.);
-    system.many_steps(150);
-    system.set_weight(1, 2, 200.);
-    system.many_steps(150);
-    system.set_weight(1, 3, 70.);
-    system.many_steps(150);
-    system.set_weight(2, 4, 5000.);
-    system.many_steps(150);
-    system.set_weight(0, 3, 200.);
-    system.many_steps(150);
-    system.add_node(5, [38, 139, 210]);
-    system.many_steps(150);
-    system.add_node(6, [42, 161, 152]);
-    system.many_steps(150);
-    system.set_weight(5, 6, 60.);
-    system.many_steps(150);
-    system.add_node(7, [133, 153, 0]);
-    system.many_steps(150);
-    system.set_weight(6, 7, 200.);
-    system.many_steps(150);
-    system.set_weight(5, 7, 50.);
-    system.many_steps(150);
-    system.set_weight(1, 7, 5000.);
-    system.many_steps(400);
+const MINUTE: i64 = 60_000;
+
+/// A small community forming over a few hours, with something happening every 20 minutes.
+fn demo_events() -> Vec<Event<u64>> {
+    let add_node = |minutes, id| Event {
+        time: minutes * MINUTE,
+        op: Op::AddNode { id, colour: None },
+    };
+    let set_weight = |minutes, from, to, weight| Event {
+        time: minutes * MINUTE,
+        op: Op::SetWeight { from, to, weight },
+    };
+    vec![
+        add_node(0, 0),
+        add_node(0, 1),
+        add_node(0, 2),
+        add_node(0, 3),
+        add_node(0, 4),
+        set_weight(20, 0, 1, 50.),
+        set_weight(40, 1, 2, 200.),
+        set_weight(60, 1, 3, 70.),
+        set_weight(80, 2, 4, 5000.),
+        set_weight(100, 0, 3, 200.),
+        add_node(120, 5),
+        add_node(140, 6),
+        set_weight(160, 5, 6, 60.),
+        add_node(180, 7),
+        set_weight(200, 6, 7, 200.),
+        set_weight(220, 5, 7, 50.),
+        set_weight(240, 1, 7, 5000.),
+    ]
+}
+
+fn test1() -> Result<()> {
+    let mut system: System = System::new();
+    system.set_auto_colour(AutoColour::Palette(Palette::Solarized));
+    system.set_community_detection(CommunityDetection::colouring(Palette::Solarized, 100))?;
+    #[cfg(feature = "png")]
+    system.add_sink(snowball::draw::PngFrames::new("frames/frame%04d.png"))?;
+    #[cfg(feature = "gif")]
+    system.add_sink(snowball::draw::GifAnimation::new("out.gif")?)?;
     #[cfg(feature = "lottie")]
-    system.render_lottie();
+    system.add_sink(snowball::lottie_graph::LottieAnimation::new(
+        snowball::Output::stdout(),
+    )?)?;
     #[cfg(feature = "masquerade")]
-    system.render_masquerade();
+    system.add_sink(snowball::masquerade::Image::new("test.gif")?)?;
+    Scheduler::new().replay(&mut system, &demo_events())?;
+    system.finish()
 }
 
-fn main() {
+fn main() -> Result<()> {
     let n = std::env::args()
         .nth(1)
         .map(|s| s.parse().unwrap())
         .unwrap_or(1);
     for _ in 0..n {
-        test1();
+        test1()?;
     }
+    Ok(())
 }
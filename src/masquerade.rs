@@ -1,93 +1,242 @@
-use crate::Vec2d;
+use crate::colour::{mix, BACKGROUND_COLOUR, EDGE_COLOUR};
+use crate::output::Output;
+use crate::state::{NodeTable, ReadState, WriteState};
+use crate::{Error, FrameSink, NodeKey, Result, Vec2d};
+use hashbrown::HashMap;
 use rayon::prelude::*;
-use std::io::Write;
+use std::io::{Read, Write};
 
 const NODE_RADIUS: usize = 10;
 const IMAGE_SIZE: usize = 1000;
-const BACKGROUND_COLOUR: [u8; 3] = [238, 232, 213];
+/// The number of palette entries used for edges, each mixing the edge colour into the background
+/// a little more, from the lightest edges to the heaviest.
+const EDGE_SHADES: usize = 4;
 
-const NODE_MASK: [[bool; 2 * NODE_RADIUS]; 2 * NODE_RADIUS] = {
-    let mut mask = [[false; 2 * NODE_RADIUS]; 2 * NODE_RADIUS];
-    let mut y = 0;
-    while y < 2 * NODE_RADIUS {
-        let mut x = 0;
-        while x < 2 * NODE_RADIUS {
-            let dx = x.abs_diff(NODE_RADIUS);
-            let dy = y.abs_diff(NODE_RADIUS);
-            mask[y][x] = dx * dx + dy * dy <= NODE_RADIUS * NODE_RADIUS;
-            x += 1;
-        }
-        y += 1;
+/// Set a pixel, if it is inside the image.
+fn plot(image: &mut [u8], x: i64, y: i64, palette_index: u8) {
+    if let (Ok(x @ 0..IMAGE_SIZE), Ok(y @ 0..IMAGE_SIZE)) = (usize::try_from(x), usize::try_from(y))
+    {
+        image[x + y * IMAGE_SIZE] = palette_index;
     }
-    mask
-};
+}
 
+#[derive(Clone, Copy, Debug)]
 struct Node {
     palette_index: u8,
+    radius: u32,
     pos: Vec2d,
 }
 
 impl Node {
-    fn draw(&self, image: &mut [u8; IMAGE_SIZE * IMAGE_SIZE]) {
-        let start_x = (self.pos.x as usize).saturating_sub(NODE_RADIUS);
-        let start_y = (self.pos.y as usize).saturating_sub(NODE_RADIUS);
-        for x in 0..2 * NODE_RADIUS {
-            for y in 0..2 * NODE_RADIUS {
-                if NODE_MASK[y][x] {
-                    let image_x = start_x + x;
-                    let image_y = start_y + y;
-                    let index = image_x + image_y * IMAGE_SIZE;
-                    image[index] = self.palette_index;
+    fn draw(&self, image: &mut [u8]) {
+        let radius = i64::from(self.radius);
+        let (x, y) = (self.pos.x as i64, self.pos.y as i64);
+        for dy in -radius..radius {
+            for dx in -radius..radius {
+                if dx * dx + dy * dy <= radius * radius {
+                    plot(image, x + dx, y + dy, self.palette_index);
+                }
+            }
+        }
+    }
+}
+
+/// Clip a line to the image, extended by `margin` pixels on every side, using the Liang-Barsky
+/// algorithm. Returns `None` if none of the line is inside.
+fn clip(from: Vec2d, to: Vec2d, margin: f32) -> Option<(Vec2d, Vec2d)> {
+    let (min, max) = (-margin, IMAGE_SIZE as f32 + margin);
+    let delta = to - from;
+    // The fractions of the way along the line at which it enters and leaves the image.
+    let (mut enter, mut exit) = (0f32, 1f32);
+    for (towards, room) in [
+        (-delta.x, from.x - min),
+        (delta.x, max - from.x),
+        (-delta.y, from.y - min),
+        (delta.y, max - from.y),
+    ] {
+        if towards == 0. {
+            // The line is parallel to this side, so is either entirely inside or outside it.
+            if room < 0. {
+                return None;
+            }
+        } else if towards < 0. {
+            enter = enter.max(room / towards);
+        } else {
+            exit = exit.min(room / towards);
+        }
+    }
+    (enter <= exit).then(|| (from + delta * enter, from + delta * exit))
+}
+
+/// An edge as it is drawn in one frame.
+#[derive(Clone, Copy, Debug)]
+struct Edge {
+    from: Vec2d,
+    to: Vec2d,
+    weight: f32,
+}
+
+impl Edge {
+    /// Draw the edge with Bresenham's algorithm, thickened across whichever axis it changes least
+    /// along. Only the part near the image is walked, however far off it the nodes are.
+    fn draw(&self, image: &mut [u8], palette_index: u8, width: u32) {
+        let Some((from, to)) = clip(self.from, self.to, width as f32) else {
+            return;
+        };
+        let (mut x, mut y) = (from.x as i64, from.y as i64);
+        let (end_x, end_y) = (to.x as i64, to.y as i64);
+        let (dx, dy) = ((end_x - x).abs(), -(end_y - y).abs());
+        let (step_x, step_y) = ((end_x - x).signum(), (end_y - y).signum());
+        let steep = -dy > dx;
+        let width = i64::from(width);
+        let mut error = dx + dy;
+        loop {
+            for offset in (0..width).map(|i| i - width / 2) {
+                if steep {
+                    plot(image, x + offset, y, palette_index);
+                } else {
+                    plot(image, x, y + offset, palette_index);
                 }
             }
+            if x == end_x && y == end_y {
+                break;
+            }
+            let doubled = 2 * error;
+            if doubled >= dy {
+                error += dy;
+                x += step_x;
+            }
+            if doubled <= dx {
+                error += dx;
+                y += step_y;
+            }
         }
     }
 }
 
-pub struct Image {
-    frames: Vec<Vec<Node>>,
+/// Everything drawn in one frame. Edges are drawn first, beneath every node.
+#[derive(Clone, Debug, Default)]
+struct Frame {
+    edges: Vec<Edge>,
+    nodes: Vec<Node>,
+}
+
+/// How a node is currently drawn.
+#[derive(Clone, Copy, Debug)]
+struct Style {
+    palette_index: u8,
+    radius: u32,
+    // Where the node was last placed, which its edges are drawn from.
+    pos: Option<Vec2d>,
+}
+
+/// A sink which records every frame in memory, and renders them all as a GIF in parallel when the
+/// simulation finishes.
+///
+/// Unlike the `forma_render` based outputs, this uses a palette with one entry per distinct node
+/// colour, and stamps each node as a circle of whole pixels. Edges with a positive weight are
+/// drawn as lines beneath the nodes, thicker and darker the heavier they are relative to the
+/// heaviest edge in the whole animation, using a few more palette entries.
+pub struct Image<K = u64> {
+    frames: Vec<Frame>,
+    current: Frame,
     palette: Vec<[u8; 3]>,
+    nodes: HashMap<K, Style>,
+    edges: HashMap<(K, K), f32>,
+    // The palette index of the lightest edge shade, once the shades have been added to the
+    // palette when the first edge is shown.
+    first_shade: Option<u8>,
+    // This is only `None` once the GIF has been written.
+    output: Option<Box<dyn Write>>,
 }
 
-impl Image {
-    pub fn new() -> Self {
-        Self {
+impl<K: NodeKey> Image<K> {
+    /// Create a sink which will write to the given destination when finished.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the destination cannot be opened.
+    pub fn new(output: impl Into<Output>) -> Result<Self> {
+        Ok(Self {
             frames: Vec::new(),
+            current: Frame::default(),
             palette: vec![BACKGROUND_COLOUR],
-        }
+            nodes: HashMap::new(),
+            edges: HashMap::new(),
+            first_shade: None,
+            output: Some(output.into().open()?),
+        })
     }
 
-    pub fn add_node(&mut self, colour: [u8; 3]) -> u8 {
+    fn palette_index(&mut self, colour: [u8; 3]) -> Result<u8> {
         if let Some(index) = self.palette.iter().position(|c| *c == colour) {
-            index as u8
+            Ok(index as u8)
         } else {
-            let index = self.palette.len();
+            let index = u8::try_from(self.palette.len()).map_err(|_| Error::PaletteFull)?;
             self.palette.push(colour);
-            index as u8
+            Ok(index)
         }
     }
 
-    pub fn place_node(&mut self, palette_index: u8, pos: Vec2d) {
-        self.frames
-            .last_mut()
-            .unwrap()
-            .push(Node { palette_index, pos });
+    /// Add the edge shades to the palette, if they are not already in it, returning the index of
+    /// the lightest.
+    fn edge_shades(&mut self) -> Result<u8> {
+        if let Some(first_shade) = self.first_shade {
+            return Ok(first_shade);
+        }
+        let first_shade = u8::try_from(self.palette.len())
+            .ok()
+            .filter(|&first| usize::from(first) + EDGE_SHADES <= 256)
+            .ok_or(Error::PaletteFull)?;
+        for shade in 1..=EDGE_SHADES {
+            let strength = shade as f32 / EDGE_SHADES as f32;
+            self.palette.push(mix(
+                BACKGROUND_COLOUR,
+                EDGE_COLOUR,
+                strength.mul_add(0.5, 0.3),
+            ));
+        }
+        self.first_shade = Some(first_shade);
+        Ok(first_shade)
     }
 
-    pub fn new_frame(&mut self) {
-        self.frames.push(Vec::new());
-    }
+    /// Encode every recorded frame as a GIF.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the GIF cannot be encoded or written.
+    pub fn render(&self, w: impl Write) -> Result<()> {
+        let max_weight = self
+            .frames
+            .iter()
+            .flat_map(|frame| &frame.edges)
+            .map(|edge| edge.weight)
+            .fold(0., f32::max);
+        // Edges are only recorded once the shades are in the palette.
+        let first_shade = self.first_shade.unwrap_or_default();
+        // Pick the palette entry and width for an edge.
+        let edge_style = |weight: f32| {
+            let strength = (weight / max_weight).clamp(0., 1.);
+            let shade = ((strength * EDGE_SHADES as f32).ceil() as u8).clamp(1, EDGE_SHADES as u8);
+            (
+                first_shade + shade - 1,
+                strength.mul_add(3., 1.).round() as u32,
+            )
+        };
 
-    pub fn render(&self, w: impl Write) {
         let palette = self.palette.iter().flatten().copied().collect::<Vec<_>>();
-        let mut gif = gif::Encoder::new(w, IMAGE_SIZE as u16, IMAGE_SIZE as u16, &palette).unwrap();
-        let base_image = [0; IMAGE_SIZE * IMAGE_SIZE];
+        let mut gif = gif::Encoder::new(w, IMAGE_SIZE as u16, IMAGE_SIZE as u16, &palette)?;
+        let base_image = vec![0; IMAGE_SIZE * IMAGE_SIZE];
         let mut frames = Vec::with_capacity(self.frames.len());
         self.frames
             .par_iter()
             .map(|frame| {
                 let mut image = base_image.clone();
-                for node in frame {
+                for edge in &frame.edges {
+                    let (palette_index, width) = edge_style(edge.weight);
+                    edge.draw(&mut image, palette_index, width);
+                }
+                for node in &frame.nodes {
                     node.draw(&mut image);
                 }
                 let mut frame = gif::Frame::from_indexed_pixels(
@@ -101,8 +250,297 @@ impl Image {
                 frame
             })
             .collect_into_vec(&mut frames);
-        frames
-            .into_iter()
-            .for_each(|frame| gif.write_lzw_pre_encoded_frame(&frame).unwrap());
+        for frame in frames {
+            gif.write_lzw_pre_encoded_frame(&frame)?;
+        }
+        Ok(())
+    }
+}
+
+impl<K: NodeKey> FrameSink<K> for Image<K> {
+    fn node_added(&mut self, id: &K, colour: [u8; 3]) -> Result<()> {
+        let palette_index = self.palette_index(colour)?;
+        self.nodes.insert(
+            id.clone(),
+            Style {
+                palette_index,
+                radius: NODE_RADIUS as u32,
+                pos: None,
+            },
+        );
+        Ok(())
+    }
+
+    fn node_recoloured(&mut self, id: &K, colour: [u8; 3]) -> Result<()> {
+        let palette_index = self.palette_index(colour)?;
+        self.nodes
+            .get_mut(id)
+            .ok_or_else(|| Error::node_not_found(id))?
+            .palette_index = palette_index;
+        Ok(())
+    }
+
+    fn node_resized(&mut self, id: &K, size: f32) -> Result<()> {
+        self.nodes
+            .get_mut(id)
+            .ok_or_else(|| Error::node_not_found(id))?
+            .radius = (NODE_RADIUS as f32 * size).round().max(0.) as u32;
+        Ok(())
+    }
+
+    fn node_removed(&mut self, id: &K) -> Result<()> {
+        self.nodes.remove(id);
+        self.edges.retain(|(from, to), _| from != id && to != id);
+        Ok(())
+    }
+
+    fn weight_changed(&mut self, from: &K, to: &K, weight: f32) -> Result<()> {
+        for id in [from, to] {
+            if !self.nodes.contains_key(id) {
+                return Err(Error::node_not_found(id));
+            }
+        }
+        // Running out of colours is reported here rather than when the GIF is written.
+        if weight > 0. {
+            self.edge_shades()?;
+        }
+        // Edges are undirected, so the weight may have been set either way round before.
+        self.edges.remove(&(to.clone(), from.clone()));
+        if weight > 0. {
+            self.edges.insert((from.clone(), to.clone()), weight);
+        } else {
+            self.edges.remove(&(from.clone(), to.clone()));
+        }
+        Ok(())
+    }
+
+    fn node_moved(&mut self, id: &K, pos: Vec2d) -> Result<()> {
+        let style = self
+            .nodes
+            .get_mut(id)
+            .ok_or_else(|| Error::node_not_found(id))?;
+        style.pos = Some(pos);
+        self.current.nodes.push(Node {
+            palette_index: style.palette_index,
+            radius: style.radius,
+            pos,
+        });
+        Ok(())
+    }
+
+    fn step_finished(&mut self, _step: u64) -> Result<()> {
+        let pos = |id| self.nodes.get(id).and_then(|style: &Style| style.pos);
+        self.current.edges = self
+            .edges
+            .iter()
+            .filter_map(|((from, to), &weight)| {
+                Some(Edge {
+                    from: pos(from)?,
+                    to: pos(to)?,
+                    weight,
+                })
+            })
+            .collect();
+        self.frames.push(std::mem::take(&mut self.current));
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if let Some(mut output) = self.output.take() {
+            self.render(&mut output)?;
+            output.flush()?;
+        }
+        Ok(())
+    }
+
+    fn save_state(&self, w: &mut dyn Write, nodes: &NodeTable<K>) -> Result<()> {
+        w.write_len(self.palette.len())?;
+        for colour in &self.palette {
+            w.write_colour(*colour)?;
+        }
+        w.write_len(self.nodes.len())?;
+        for (id, style) in &self.nodes {
+            w.write_u64(nodes.index(id)?)?;
+            w.write_u8(style.palette_index)?;
+            w.write_u32(style.radius)?;
+        }
+        // The background is always first in the palette, so 0 can stand for no edge shades.
+        w.write_u8(self.first_shade.unwrap_or_default())?;
+        w.write_len(self.edges.len())?;
+        for ((from, to), weight) in &self.edges {
+            w.write_u64(nodes.index(from)?)?;
+            w.write_u64(nodes.index(to)?)?;
+            w.write_f32(*weight)?;
+        }
+        w.write_len(self.frames.len())?;
+        for frame in &self.frames {
+            w.write_len(frame.edges.len())?;
+            for edge in &frame.edges {
+                w.write_vec2d(edge.from)?;
+                w.write_vec2d(edge.to)?;
+                w.write_f32(edge.weight)?;
+            }
+            w.write_len(frame.nodes.len())?;
+            for node in &frame.nodes {
+                w.write_u8(node.palette_index)?;
+                w.write_u32(node.radius)?;
+                w.write_vec2d(node.pos)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn load_state(&mut self, r: &mut dyn Read, nodes: &NodeTable<K>) -> Result<()> {
+        let mut palette = Vec::new();
+        for _ in 0..r.read_len()? {
+            palette.push(r.read_colour()?);
+        }
+        let mut styles = HashMap::new();
+        for _ in 0..r.read_len()? {
+            let id = nodes.key(r.read_u64()?)?.clone();
+            let palette_index = r.read_u8()?;
+            let radius = r.read_u32()?;
+            styles.insert(
+                id,
+                Style {
+                    palette_index,
+                    radius,
+                    pos: None,
+                },
+            );
+        }
+        let first_shade = Some(r.read_u8()?).filter(|&first_shade| first_shade != 0);
+        let mut edges = HashMap::new();
+        for _ in 0..r.read_len()? {
+            let from = nodes.key(r.read_u64()?)?.clone();
+            let to = nodes.key(r.read_u64()?)?.clone();
+            edges.insert((from, to), r.read_f32()?);
+        }
+        let mut frames = Vec::new();
+        for _ in 0..r.read_len()? {
+            let mut frame = Frame::default();
+            for _ in 0..r.read_len()? {
+                let from = r.read_vec2d()?;
+                let to = r.read_vec2d()?;
+                let weight = r.read_f32()?;
+                frame.edges.push(Edge { from, to, weight });
+            }
+            for _ in 0..r.read_len()? {
+                let palette_index = r.read_u8()?;
+                let radius = r.read_u32()?;
+                let pos = r.read_vec2d()?;
+                frame.nodes.push(Node {
+                    palette_index,
+                    radius,
+                    pos,
+                });
+            }
+            frames.push(frame);
+        }
+        self.palette = palette;
+        self.nodes = styles;
+        self.edges = edges;
+        self.first_shade = first_shade;
+        self.frames = frames;
+        self.current = Frame::default();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Edge, Image, Node, EDGE_SHADES, IMAGE_SIZE};
+    use crate::{Error, FrameSink, SharedBuffer, System, Vec2d};
+
+    #[test]
+    fn clips_to_image() {
+        let mut image = vec![0; IMAGE_SIZE * IMAGE_SIZE];
+        for pos in [Vec2d::new(995., 998.), Vec2d::new(-5., 3.)] {
+            Node {
+                palette_index: 1,
+                radius: 10,
+                pos,
+            }
+            .draw(&mut image);
+        }
+        assert_eq!(image[IMAGE_SIZE * IMAGE_SIZE - 1], 1);
+        assert_eq!(image[0], 1);
+        // Only the part of the line near the image is drawn, so this doesn't take long.
+        Edge {
+            from: Vec2d::new(500., 500.),
+            to: Vec2d::new(1e9, 500.),
+            weight: 1.,
+        }
+        .draw(&mut image, 2, 3);
+        Edge {
+            from: Vec2d::new(-1e9, -10.),
+            to: Vec2d::new(1e9, -10.),
+            weight: 1.,
+        }
+        .draw(&mut image, 3, 3);
+        assert!(!image.contains(&3));
+        for y in 499..=501 {
+            assert_eq!(image[y * IMAGE_SIZE + 700], 2);
+        }
+        assert_eq!(image[498 * IMAGE_SIZE + 700], 0);
+        assert_eq!(image[500 * IMAGE_SIZE + IMAGE_SIZE - 1], 2);
+    }
+
+    #[test]
+    fn draws_edges() {
+        let buffer = SharedBuffer::new();
+        let mut system: System = System::with_seed(1);
+        system
+            .add_sink(Image::new(buffer.clone()).unwrap())
+            .unwrap();
+        system.add_node(1, [255, 0, 0]);
+        system.add_node(2, [0, 0, 255]);
+        system.add_node(3, None);
+        system.set_weight(1, 2, 10.);
+        system.set_weight(2, 3, 5.);
+        system.many_steps(5).unwrap();
+        system.remove_node(&3);
+        system.many_steps(5).unwrap();
+        system.finish().unwrap();
+
+        let gif = buffer.take();
+        let mut decoder = gif::DecodeOptions::new().read_info(gif.as_slice()).unwrap();
+        // The background, three node colours, and the shades of edges.
+        assert_eq!(
+            decoder.global_palette().unwrap().len(),
+            3 * (4 + EDGE_SHADES)
+        );
+        let mut frames = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            frames.push(frame.buffer.to_vec());
+        }
+        assert_eq!(frames.len(), 10);
+        // Once the nodes have spread out, both edges are drawn, in the heaviest shade and the one
+        // for half the weight, until the lighter edge goes with its node.
+        assert!(frames[4].contains(&7) && frames[4].contains(&5));
+        assert!(!frames[4].contains(&6));
+        assert!(frames[9].contains(&7));
+        assert!(!frames[9].contains(&3) && !frames[9].contains(&5));
+    }
+
+    #[test]
+    fn reports_full_palette() {
+        let mut image = Image::new(SharedBuffer::new()).unwrap();
+        for id in 0..252 {
+            image.node_added(&id, [id as u8, 0, 0]).unwrap();
+        }
+        // There is room for the shades of edges only while the palette has at most 252 entries.
+        assert!(matches!(
+            image.weight_changed(&0, &1, 1.),
+            Err(Error::PaletteFull)
+        ));
+        assert!(image.edges.is_empty());
+        let mut image = Image::new(SharedBuffer::new()).unwrap();
+        for id in 0..251 {
+            image.node_added(&id, [id as u8, 0, 0]).unwrap();
+        }
+        image.weight_changed(&0, &1, 1.).unwrap();
+        assert_eq!(image.first_shade, Some(252));
+        assert_eq!(image.palette.len(), 256);
     }
 }
@@ -0,0 +1,344 @@
+//! Destinations for the files written by the different sinks.
+//!
+//! Sinks which produce a single file (such as an animated GIF) take an [`Output`], which may be a
+//! path, any [`Write`] implementation, or a [`SharedBuffer`] that the caller can read once the
+//! simulation is finished. Sinks which produce one file per frame take [`FrameOutputs`] instead.
+//!
+//! Whenever a path is given, any missing parent directories are created.
+use std::fmt::{self, Debug, Write as _};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// Create a file for writing, along with any missing parent directories.
+fn create_file(path: &Path) -> io::Result<BufWriter<File>> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    Ok(BufWriter::new(File::create(path)?))
+}
+
+/// An in-memory buffer which can be handed to a sink while the caller keeps a handle to it.
+///
+/// Cloning a `SharedBuffer` gives another handle to the same underlying buffer.
+#[derive(Clone, Debug, Default)]
+pub struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<u8>> {
+        // A panic while holding the lock can't leave a `Vec<u8>` in an invalid state, so there is
+        // no reason to propagate poisoning.
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Take the contents of the buffer, leaving it empty.
+    #[must_use]
+    pub fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.lock())
+    }
+
+    /// Get the number of bytes currently in the buffer.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Check whether the buffer is currently empty.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.lock().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The destination for a sink which writes a single file.
+pub enum Output {
+    /// Write to a file at this path, creating parent directories as needed.
+    Path(PathBuf),
+    /// Write to an arbitrary writer.
+    Writer(Box<dyn Write>),
+}
+
+impl Output {
+    /// Write to a file at the given path.
+    pub fn path(path: impl Into<PathBuf>) -> Self {
+        Self::Path(path.into())
+    }
+
+    /// Write to an arbitrary writer.
+    pub fn writer(writer: impl Write + 'static) -> Self {
+        Self::Writer(Box::new(writer))
+    }
+
+    /// Write to standard output.
+    #[must_use]
+    pub fn stdout() -> Self {
+        Self::writer(io::stdout())
+    }
+
+    /// Open the destination for writing.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a file or its parent directories could not be created.
+    pub fn open(self) -> io::Result<Box<dyn Write>> {
+        match self {
+            Self::Path(path) => Ok(Box::new(create_file(&path)?)),
+            Self::Writer(writer) => Ok(writer),
+        }
+    }
+}
+
+impl Debug for Output {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Path(path) => f.debug_tuple("Path").field(path).finish(),
+            Self::Writer(_) => f.write_str("Writer(..)"),
+        }
+    }
+}
+
+impl From<&str> for Output {
+    fn from(path: &str) -> Self {
+        Self::path(path)
+    }
+}
+
+impl From<String> for Output {
+    fn from(path: String) -> Self {
+        Self::path(path)
+    }
+}
+
+impl From<&Path> for Output {
+    fn from(path: &Path) -> Self {
+        Self::path(path)
+    }
+}
+
+impl From<PathBuf> for Output {
+    fn from(path: PathBuf) -> Self {
+        Self::Path(path)
+    }
+}
+
+impl From<SharedBuffer> for Output {
+    fn from(buffer: SharedBuffer) -> Self {
+        Self::writer(buffer)
+    }
+}
+
+/// A collection of in-memory buffers, one per frame, shared between a sink and the caller.
+///
+/// Cloning a `FrameBuffers` gives another handle to the same underlying collection.
+#[derive(Clone, Debug, Default)]
+pub struct FrameBuffers(Arc<Mutex<Vec<Vec<u8>>>>);
+
+impl FrameBuffers {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<Vec<u8>>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Take every frame written so far, leaving the collection empty.
+    #[must_use]
+    pub fn take(&self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut *self.lock())
+    }
+
+    /// Get the number of frames currently stored.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Check whether any frames are currently stored.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+}
+
+/// A writer which appends its contents to a [`FrameBuffers`] as a new frame when dropped.
+struct FrameBufferWriter {
+    frames: FrameBuffers,
+    buffer: Vec<u8>,
+}
+
+impl Write for FrameBufferWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for FrameBufferWriter {
+    fn drop(&mut self) {
+        let buffer = std::mem::take(&mut self.buffer);
+        self.frames.lock().push(buffer);
+    }
+}
+
+/// The destination for a sink which writes a separate file for every frame.
+pub enum FrameOutputs {
+    /// Write each frame to a file whose path is given by a pattern.
+    ///
+    /// The pattern should contain a `printf`-style integer placeholder, which will be replaced by
+    /// the step number: `%d` for no padding, or for example `%04d` to pad with zeros to four
+    /// digits. This is the same syntax `ffmpeg` uses for image sequences. A literal `%` can be
+    /// written as `%%`. Parent directories are created as needed.
+    Pattern(String),
+    /// Call a function with the step number to get the writer for each frame.
+    Writers(Box<dyn FnMut(u64) -> io::Result<Box<dyn Write>>>),
+    /// Store each frame in memory.
+    Buffers(FrameBuffers),
+}
+
+impl FrameOutputs {
+    /// Write each frame to a file whose path is given by a pattern. See [`Self::Pattern`].
+    pub fn pattern(pattern: impl Into<String>) -> Self {
+        Self::Pattern(pattern.into())
+    }
+
+    /// Call a function with the step number to get the writer for each frame.
+    pub fn writers(f: impl FnMut(u64) -> io::Result<Box<dyn Write>> + 'static) -> Self {
+        Self::Writers(Box::new(f))
+    }
+
+    /// Open the destination for a given step.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a file or its parent directories could not be created, or if the
+    /// writer function fails.
+    pub fn open(&mut self, step: u64) -> io::Result<Box<dyn Write>> {
+        match self {
+            Self::Pattern(pattern) => {
+                let path = format_pattern(pattern, step);
+                Ok(Box::new(create_file(Path::new(&path))?))
+            }
+            Self::Writers(f) => f(step),
+            Self::Buffers(frames) => Ok(Box::new(FrameBufferWriter {
+                frames: frames.clone(),
+                buffer: Vec::new(),
+            })),
+        }
+    }
+}
+
+impl Debug for FrameOutputs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pattern(pattern) => f.debug_tuple("Pattern").field(pattern).finish(),
+            Self::Writers(_) => f.write_str("Writers(..)"),
+            Self::Buffers(frames) => f.debug_tuple("Buffers").field(frames).finish(),
+        }
+    }
+}
+
+impl From<&str> for FrameOutputs {
+    fn from(pattern: &str) -> Self {
+        Self::pattern(pattern)
+    }
+}
+
+impl From<String> for FrameOutputs {
+    fn from(pattern: String) -> Self {
+        Self::Pattern(pattern)
+    }
+}
+
+impl From<FrameBuffers> for FrameOutputs {
+    fn from(frames: FrameBuffers) -> Self {
+        Self::Buffers(frames)
+    }
+}
+
+/// Substitute a step number into a `printf`-style pattern. See [`FrameOutputs::Pattern`].
+///
+/// Anything after a `%` which is not a valid placeholder is left as it is.
+fn format_pattern(pattern: &str, step: u64) -> String {
+    let mut out = String::with_capacity(pattern.len() + 8);
+    let mut rest = pattern;
+    while let Some(index) = rest.find('%') {
+        out.push_str(&rest[..index]);
+        rest = &rest[index + 1..];
+        if let Some(after) = rest.strip_prefix('%') {
+            out.push('%');
+            rest = after;
+            continue;
+        }
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        if rest[digits..].starts_with('d') {
+            let width = rest[..digits].parse().unwrap_or(0);
+            write!(out, "{step:0width$}").unwrap();
+            rest = &rest[digits + 1..];
+        } else {
+            out.push('%');
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_pattern, FrameBuffers, FrameOutputs, Output, SharedBuffer};
+    use std::io::Write;
+
+    #[test]
+    fn patterns() {
+        assert_eq!(
+            format_pattern("frames/frame%04d.png", 7),
+            "frames/frame0007.png"
+        );
+        assert_eq!(format_pattern("frame%d.png", 12345), "frame12345.png");
+        assert_eq!(format_pattern("%02d-%%-%d", 123), "123-%-123");
+        assert_eq!(format_pattern("100%.png", 1), "100%.png");
+        assert_eq!(format_pattern("no placeholder", 1), "no placeholder");
+    }
+
+    #[test]
+    fn shared_buffer() {
+        let buffer = SharedBuffer::new();
+        let mut writer = Output::from(buffer.clone()).open().unwrap();
+        writer.write_all(b"hello").unwrap();
+        drop(writer);
+        assert_eq!(buffer.take(), b"hello");
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn frame_buffers() {
+        let frames = FrameBuffers::new();
+        let mut outputs = FrameOutputs::from(frames.clone());
+        for step in 0..3 {
+            let mut writer = outputs.open(step).unwrap();
+            write!(writer, "frame {step}").unwrap();
+        }
+        assert_eq!(frames.take(), vec![b"frame 0", b"frame 1", b"frame 2"]);
+    }
+}
@@ -0,0 +1,118 @@
+use crate::state::NodeTable;
+use crate::{Result, Vec2d};
+use std::io::{Read, Write};
+
+/// A consumer of the changes a [`System`](crate::System) makes to its layout.
+///
+/// The type parameter `K` is the type of the keys identifying nodes in the system. Most sinks are
+/// generic over it.
+///
+/// Every output format is implemented as a sink, and any number of sinks can be attached to a
+/// system with [`System::add_sink`](crate::System::add_sink). The system calls each sink in the
+/// order they were attached:
+///
+/// - [`node_added`](Self::node_added) when a node is created (and, for sinks attached part way
+///   through a simulation, once for every node that already exists),
+/// - [`avatar_set`](Self::avatar_set) straight after `node_added` for a node added with an
+///   avatar,
+/// - [`node_recoloured`](Self::node_recoloured) when a node's colour changes, such as when it
+///   joins a new community (see [`crate::community`]),
+/// - [`node_resized`](Self::node_resized) when a node's size changes (and, for new sinks, once for
+///   every existing node whose size is not the default),
+/// - [`weight_changed`](Self::weight_changed) when the weight of an edge is set (and, for new
+///   sinks, once for every existing edge whose weight is not 0),
+/// - [`node_moved`](Self::node_moved) once per step for every node, with its new position,
+/// - [`step_finished`](Self::step_finished) after every node has been moved for a step,
+/// - [`node_removed`](Self::node_removed) when a node is deleted,
+/// - [`finish`](Self::finish) once, when the caller is done with the simulation.
+///
+/// Sinks which accumulate output in memory can also implement [`save_state`](Self::save_state) and
+/// [`load_state`](Self::load_state), so that they can be resumed along with the system from a
+/// checkpoint.
+///
+/// All methods have empty default implementations, so a sink only needs to implement the events
+/// it cares about. If a sink returns an error, the system still passes the event on to every other
+/// sink before reporting the first error to its caller.
+// The paragraph above covers errors for every method, so repeating it for each would just be noise.
+#[allow(clippy::missing_errors_doc)]
+pub trait FrameSink<K = u64> {
+    /// A node has been added to the system.
+    fn node_added(&mut self, _id: &K, _colour: [u8; 3]) -> Result<()> {
+        Ok(())
+    }
+
+    /// A node has been given an avatar, as the contents of a PNG file. Sinks which can draw
+    /// images show it in place of the node's colour.
+    fn avatar_set(&mut self, _id: &K, _png: &[u8]) -> Result<()> {
+        Ok(())
+    }
+
+    /// A node has been removed from the system.
+    fn node_removed(&mut self, _id: &K) -> Result<()> {
+        Ok(())
+    }
+
+    /// A node should be drawn in a new colour from now on.
+    fn node_recoloured(&mut self, _id: &K, _colour: [u8; 3]) -> Result<()> {
+        Ok(())
+    }
+
+    /// A node should be drawn at a new size from now on, as a multiple of the default size.
+    fn node_resized(&mut self, _id: &K, _size: f32) -> Result<()> {
+        Ok(())
+    }
+
+    /// The weight of the edge between two nodes has been set. When a node is removed, its edges
+    /// go with it without any further events.
+    fn weight_changed(&mut self, _from: &K, _to: &K, _weight: f32) -> Result<()> {
+        Ok(())
+    }
+
+    /// A node has been moved to a new position during the current step.
+    fn node_moved(&mut self, _id: &K, _pos: Vec2d) -> Result<()> {
+        Ok(())
+    }
+
+    /// Every node has been moved for the current step. `step` is the number of steps completed
+    /// so far, including this one.
+    fn step_finished(&mut self, _step: u64) -> Result<()> {
+        Ok(())
+    }
+
+    /// The simulation is over, and any buffered output should be written.
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Write everything needed to resume this sink later. Writing nothing means the sink has no
+    /// state to restore, which is the default.
+    ///
+    /// The helpers in [`crate::state`] can be used to encode the state. Nodes should be referred
+    /// to using `nodes`, since keys are not necessarily serialisable themselves.
+    fn save_state(&self, _w: &mut dyn Write, _nodes: &NodeTable<K>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Restore the state written by [`save_state`](Self::save_state), replacing anything the sink
+    /// has recorded so far. This is only called if the saved state was not empty.
+    fn load_state(&mut self, _r: &mut dyn Read, _nodes: &NodeTable<K>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Whether the sink still needs to be told about every node and edge after
+    /// [`load_state`](Self::load_state), as a sink which saved no state is. This is for sinks
+    /// which wrap another sink, when the wrapped sink saved nothing of its own.
+    fn needs_introduction(&self) -> bool {
+        false
+    }
+}
+
+/// Record that a property changes to `value` at `step`, replacing any change already made then.
+/// This is shared by the sinks which record a history of every node.
+#[cfg(any(feature = "lottie", feature = "gexf", feature = "svg"))]
+pub fn set_at<T>(changes: &mut Vec<(u32, T)>, step: u32, value: T) {
+    match changes.last_mut() {
+        Some(last) if last.0 == step => last.1 = value,
+        _ => changes.push((step, value)),
+    }
+}
@@ -0,0 +1,124 @@
+//! Decoding Discord IDs.
+//!
+//! Every Discord ID is a [snowflake](https://discord.com/developers/docs/reference#snowflakes): a
+//! 64-bit integer whose top 42 bits are the number of milliseconds since the start of 2015, followed
+//! by 5 bits each for the internal worker and process that created it, and a 12-bit sequence
+//! number. This means the creation time of any user, message or channel can be read straight from
+//! its ID.
+use std::fmt::{self, Display};
+use std::num::ParseIntError;
+use std::str::FromStr;
+
+/// The start of 2015, which Discord measures timestamps from, in milliseconds since the Unix epoch.
+pub const DISCORD_EPOCH: i64 = 1_420_070_400_000;
+
+const TIMESTAMP_SHIFT: u32 = 22;
+const WORKER_SHIFT: u32 = 17;
+const PROCESS_SHIFT: u32 = 12;
+
+/// A Discord ID.
+///
+/// Snowflakes are ordered by creation time, since the timestamp makes up the most significant
+/// bits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Snowflake(pub u64);
+
+impl Snowflake {
+    /// Build a snowflake from its parts. Any bits which don't fit in a part are discarded.
+    #[must_use]
+    pub const fn from_parts(timestamp: i64, worker: u8, process: u8, sequence: u16) -> Self {
+        let millis = (timestamp - DISCORD_EPOCH) as u64 & ((1 << 42) - 1);
+        Self(
+            millis << TIMESTAMP_SHIFT
+                | ((worker & 0x1f) as u64) << WORKER_SHIFT
+                | ((process & 0x1f) as u64) << PROCESS_SHIFT
+                | (sequence & 0xfff) as u64,
+        )
+    }
+
+    /// Get the smallest snowflake created at the given time, in milliseconds since the Unix
+    /// epoch. This is useful for finding everything created after a point in time.
+    #[must_use]
+    pub const fn from_timestamp(timestamp: i64) -> Self {
+        Self::from_parts(timestamp, 0, 0, 0)
+    }
+
+    /// Get the time the snowflake was created, in milliseconds since the Unix epoch.
+    #[must_use]
+    // The timestamp is only 42 bits, so it always fits in an `i64`.
+    #[allow(clippy::cast_possible_wrap)]
+    pub const fn timestamp(self) -> i64 {
+        (self.0 >> TIMESTAMP_SHIFT) as i64 + DISCORD_EPOCH
+    }
+
+    /// Get the ID of the worker which created the snowflake.
+    #[must_use]
+    pub const fn worker(self) -> u8 {
+        ((self.0 >> WORKER_SHIFT) & 0x1f) as u8
+    }
+
+    /// Get the ID of the process which created the snowflake.
+    #[must_use]
+    pub const fn process(self) -> u8 {
+        ((self.0 >> PROCESS_SHIFT) & 0x1f) as u8
+    }
+
+    /// Get the sequence number of the snowflake, which is incremented for every ID generated by
+    /// the same process.
+    #[must_use]
+    pub const fn sequence(self) -> u16 {
+        (self.0 & 0xfff) as u16
+    }
+}
+
+impl From<u64> for Snowflake {
+    fn from(id: u64) -> Self {
+        Self(id)
+    }
+}
+
+impl From<Snowflake> for u64 {
+    fn from(snowflake: Snowflake) -> Self {
+        snowflake.0
+    }
+}
+
+impl Display for Snowflake {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromStr for Snowflake {
+    type Err = ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(Self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Snowflake, DISCORD_EPOCH};
+
+    #[test]
+    fn decode() {
+        // The example from Discord's documentation.
+        let snowflake: Snowflake = "175928847299117063".parse().unwrap();
+        assert_eq!(snowflake.timestamp(), 1_462_015_105_796);
+        assert_eq!(snowflake.worker(), 1);
+        assert_eq!(snowflake.process(), 0);
+        assert_eq!(snowflake.sequence(), 7);
+        assert_eq!(Snowflake::from_parts(1_462_015_105_796, 1, 0, 7), snowflake);
+        assert_eq!(snowflake.to_string(), "175928847299117063");
+    }
+
+    #[test]
+    fn ordering() {
+        let earlier = Snowflake::from_parts(DISCORD_EPOCH + 1000, 31, 31, 4095);
+        let later = Snowflake::from_timestamp(DISCORD_EPOCH + 1001);
+        assert!(earlier < later);
+        assert_eq!(later.timestamp(), DISCORD_EPOCH + 1001);
+        assert_eq!(Snowflake::from_timestamp(DISCORD_EPOCH), Snowflake(0));
+    }
+}
@@ -0,0 +1,633 @@
+//! Saving and resuming simulations.
+//!
+//! [`System::save_state`] writes a checkpoint containing the graph, the position, velocity, size,
+//! avatar and community of every node, the step counter, the state of the random number generator, and
+//! the state of any attached sinks which record their output in memory (such as the Lottie
+//! history).
+//! [`System::load_state`] reads it back, so a long simulation can be continued later.
+//!
+//! Everything is written little-endian with no padding or alignment. The [`WriteState`] and
+//! [`ReadState`] traits are public so that third-party [`FrameSink`](crate::FrameSink)s can use the
+//! same encoding for their own state.
+//!
+//! Only systems whose keys implement [`StateKey`] can be saved. Each key is written once, and
+//! everything else in the checkpoint (including sink state) refers to nodes by their index in the
+//! [`NodeTable`].
+use crate::backbone::EdgeSet;
+use crate::{Error, Graph, Node, NodeKey, Result, System, Vec2d};
+use hashbrown::HashMap;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use std::io::{Read, Write};
+
+/// Identifies a snowball checkpoint.
+const MAGIC: &[u8; 8] = b"SNOWBALL";
+/// The version of the checkpoint format, to be incremented on any incompatible change.
+const VERSION: u32 = 1;
+/// Written in place of the community of a node which has not been assigned one yet.
+const NO_COMMUNITY: u64 = u64::MAX;
+
+/// Write primitive values in the checkpoint format. Implemented for every [`Write`].
+pub trait WriteState: Write {
+    /// Write a single byte.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying writer fails.
+    fn write_u8(&mut self, value: u8) -> Result<()> {
+        self.write_all(&[value])?;
+        Ok(())
+    }
+
+    /// Write a 32-bit unsigned integer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying writer fails.
+    fn write_u32(&mut self, value: u32) -> Result<()> {
+        self.write_all(&value.to_le_bytes())?;
+        Ok(())
+    }
+
+    /// Write a 64-bit unsigned integer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying writer fails.
+    fn write_u64(&mut self, value: u64) -> Result<()> {
+        self.write_all(&value.to_le_bytes())?;
+        Ok(())
+    }
+
+    /// Write a 128-bit unsigned integer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying writer fails.
+    fn write_u128(&mut self, value: u128) -> Result<()> {
+        self.write_all(&value.to_le_bytes())?;
+        Ok(())
+    }
+
+    /// Write a 32-bit float.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying writer fails.
+    fn write_f32(&mut self, value: f32) -> Result<()> {
+        self.write_all(&value.to_le_bytes())?;
+        Ok(())
+    }
+
+    /// Write a length or count, as a 64-bit unsigned integer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying writer fails.
+    fn write_len(&mut self, len: usize) -> Result<()> {
+        self.write_u64(len as u64)
+    }
+
+    /// Write a vector, as its two components.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying writer fails.
+    fn write_vec2d(&mut self, value: Vec2d) -> Result<()> {
+        self.write_f32(value.x)?;
+        self.write_f32(value.y)
+    }
+
+    /// Write an RGB colour.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying writer fails.
+    fn write_colour(&mut self, colour: [u8; 3]) -> Result<()> {
+        self.write_all(&colour)?;
+        Ok(())
+    }
+
+    /// Write a byte string, prefixed by its length.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying writer fails.
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        self.write_len(bytes.len())?;
+        self.write_all(bytes)?;
+        Ok(())
+    }
+}
+
+impl<W: Write + ?Sized> WriteState for W {}
+
+/// Read primitive values in the checkpoint format. Implemented for every [`Read`].
+///
+/// Running out of data is reported as [`Error::Checkpoint`] rather than an I/O error, since it
+/// means the checkpoint is truncated.
+pub trait ReadState: Read {
+    /// Read exactly enough bytes to fill an array.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying reader fails or runs out of data.
+    fn read_fixed<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut buf = [0; N];
+        self.read_exact(&mut buf).map_err(|e| {
+            if e.kind() == std::io::ErrorKind::UnexpectedEof {
+                Error::Checkpoint("unexpected end of data")
+            } else {
+                Error::Io(e)
+            }
+        })?;
+        Ok(buf)
+    }
+
+    /// Read a single byte.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying reader fails or runs out of data.
+    fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.read_fixed::<1>()?[0])
+    }
+
+    /// Read a 32-bit unsigned integer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying reader fails or runs out of data.
+    fn read_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.read_fixed()?))
+    }
+
+    /// Read a 64-bit unsigned integer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying reader fails or runs out of data.
+    fn read_u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.read_fixed()?))
+    }
+
+    /// Read a 128-bit unsigned integer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying reader fails or runs out of data.
+    fn read_u128(&mut self) -> Result<u128> {
+        Ok(u128::from_le_bytes(self.read_fixed()?))
+    }
+
+    /// Read a 32-bit float.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying reader fails or runs out of data.
+    fn read_f32(&mut self) -> Result<f32> {
+        Ok(f32::from_le_bytes(self.read_fixed()?))
+    }
+
+    /// Read a length or count.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying reader fails or runs out of data, or if the value does
+    /// not fit in a `usize`.
+    fn read_len(&mut self) -> Result<usize> {
+        usize::try_from(self.read_u64()?).map_err(|_| Error::Checkpoint("length too large"))
+    }
+
+    /// Read a vector.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying reader fails or runs out of data.
+    fn read_vec2d(&mut self) -> Result<Vec2d> {
+        let x = self.read_f32()?;
+        let y = self.read_f32()?;
+        Ok(Vec2d::new(x, y))
+    }
+
+    /// Read an RGB colour.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying reader fails or runs out of data.
+    fn read_colour(&mut self) -> Result<[u8; 3]> {
+        self.read_fixed()
+    }
+
+    /// Read a length-prefixed byte string.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying reader fails or runs out of data.
+    fn read_bytes(&mut self) -> Result<Vec<u8>> {
+        let len = self.read_len()?;
+        let mut bytes = Vec::new();
+        <&mut Self as Read>::take(self, len as u64).read_to_end(&mut bytes)?;
+        if bytes.len() == len {
+            Ok(bytes)
+        } else {
+            Err(Error::Checkpoint("unexpected end of data"))
+        }
+    }
+}
+
+impl<R: Read + ?Sized> ReadState for R {}
+
+/// A node key which can be written to a checkpoint.
+///
+/// This is implemented for integers, strings, and pairs and triples of those. Other key types can
+/// implement it using the helpers in [`WriteState`] and [`ReadState`].
+pub trait StateKey: NodeKey {
+    /// Write the key.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying writer fails.
+    fn write_key(&self, w: &mut dyn Write) -> Result<()>;
+
+    /// Read a key written by [`write_key`](Self::write_key).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying reader fails or the data is invalid.
+    fn read_key(r: &mut dyn Read) -> Result<Self>;
+}
+
+macro_rules! impl_state_key_for_integers {
+    ($($int:ty),*) => {
+        $(
+            impl StateKey for $int {
+                fn write_key(&self, w: &mut dyn Write) -> Result<()> {
+                    w.write_all(&self.to_le_bytes())?;
+                    Ok(())
+                }
+
+                fn read_key(r: &mut dyn Read) -> Result<Self> {
+                    Ok(Self::from_le_bytes(r.read_fixed()?))
+                }
+            }
+        )*
+    };
+}
+
+impl_state_key_for_integers!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+impl StateKey for String {
+    fn write_key(&self, w: &mut dyn Write) -> Result<()> {
+        w.write_bytes(self.as_bytes())
+    }
+
+    fn read_key(r: &mut dyn Read) -> Result<Self> {
+        Self::from_utf8(r.read_bytes()?).map_err(|_| Error::Checkpoint("key is not valid UTF-8"))
+    }
+}
+
+impl<A: StateKey, B: StateKey> StateKey for (A, B) {
+    fn write_key(&self, w: &mut dyn Write) -> Result<()> {
+        self.0.write_key(w)?;
+        self.1.write_key(w)
+    }
+
+    fn read_key(r: &mut dyn Read) -> Result<Self> {
+        Ok((A::read_key(r)?, B::read_key(r)?))
+    }
+}
+
+impl<A: StateKey, B: StateKey, C: StateKey> StateKey for (A, B, C) {
+    fn write_key(&self, w: &mut dyn Write) -> Result<()> {
+        self.0.write_key(w)?;
+        self.1.write_key(w)?;
+        self.2.write_key(w)
+    }
+
+    fn read_key(r: &mut dyn Read) -> Result<Self> {
+        Ok((A::read_key(r)?, B::read_key(r)?, C::read_key(r)?))
+    }
+}
+
+/// The nodes in a checkpoint, numbered in the order they were written.
+///
+/// Sinks are given this when saving and loading their state, so that they can refer to nodes by
+/// index without needing to know how to serialise keys.
+#[derive(Clone, Debug)]
+pub struct NodeTable<K> {
+    keys: Vec<K>,
+    indices: HashMap<K, u64>,
+}
+
+impl<K: NodeKey> NodeTable<K> {
+    pub(crate) fn new(keys: Vec<K>) -> Self {
+        let indices = keys.iter().cloned().zip(0..).collect();
+        Self { keys, indices }
+    }
+
+    /// Get the index of a node.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NodeNotFound`] if the node is not in the table.
+    pub fn index(&self, key: &K) -> Result<u64> {
+        self.indices
+            .get(key)
+            .copied()
+            .ok_or_else(|| Error::node_not_found(key))
+    }
+
+    /// Get the key of the node with the given index.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Checkpoint`] if there is no node with that index.
+    pub fn key(&self, index: u64) -> Result<&K> {
+        usize::try_from(index)
+            .ok()
+            .and_then(|index| self.keys.get(index))
+            .ok_or(Error::Checkpoint("node index out of range"))
+    }
+
+    /// Get the number of nodes in the table.
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Check whether the table has no nodes.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+impl<K: StateKey> System<K> {
+    /// Write a checkpoint of the simulation, including the state of every attached sink.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing fails, or if any sink fails to save its state.
+    pub fn save_state(&self, mut w: impl Write) -> Result<()> {
+        w.write_all(MAGIC)?;
+        w.write_u32(VERSION)?;
+        w.write_u64(self.steps)?;
+        w.write_all(&self.rng.get_seed())?;
+        w.write_u64(self.rng.get_stream())?;
+        w.write_u128(self.rng.get_word_pos())?;
+
+        w.write_len(self.graph.node_count())?;
+        let mut keys = Vec::with_capacity(self.graph.node_count());
+        for node in self.graph.nodes() {
+            node.id.write_key(&mut w)?;
+            w.write_colour(node.colour)?;
+            w.write_u64(
+                node.community
+                    .map_or(NO_COMMUNITY, |community| community as u64),
+            )?;
+            w.write_f32(node.size)?;
+            // An empty avatar is not a valid PNG, so it can stand for no avatar.
+            w.write_bytes(node.avatar.as_deref().unwrap_or_default())?;
+            w.write_vec2d(node.pos)?;
+            w.write_vec2d(node.velocity)?;
+            keys.push(node.id.clone());
+        }
+        let nodes = NodeTable::new(keys);
+
+        // Each edge is stored once, and edges with the default weight are left out entirely.
+        let mut edges = Vec::new();
+        for (from, to, &weight) in self.graph.edges_unique() {
+            if weight != 0. {
+                edges.push((nodes.index(&from.id)?, nodes.index(&to.id)?, weight));
+            }
+        }
+        w.write_len(edges.len())?;
+        for (from, to, weight) in edges {
+            w.write_u64(from)?;
+            w.write_u64(to)?;
+            w.write_f32(weight)?;
+        }
+
+        // The backbone is only extracted every so often, so the edges in it are saved as well.
+        let mut backbone = Vec::new();
+        for (from, to) in self.backbone_edges.iter() {
+            let (from, to) = (nodes.index(from)?, nodes.index(to)?);
+            if from <= to {
+                backbone.push((from, to));
+            }
+        }
+        // Sets are in hash order, so the edges are sorted to keep checkpoints repeatable.
+        backbone.sort_unstable();
+        w.write_len(backbone.len())?;
+        for (from, to) in backbone {
+            w.write_u64(from)?;
+            w.write_u64(to)?;
+        }
+
+        w.write_len(self.sinks.len())?;
+        for sink in &self.sinks {
+            let mut buf = Vec::new();
+            sink.save_state(&mut buf, &nodes)?;
+            w.write_bytes(&buf)?;
+        }
+        Ok(())
+    }
+
+    /// Resume a simulation from a checkpoint written by [`save_state`](Self::save_state).
+    ///
+    /// This must be called on a new system, after attaching the same kinds of sinks in the same
+    /// order as the system the checkpoint was saved from, and giving it the same
+    /// [layout](Self::set_layout), [community detection](Self::set_community_detection) and
+    /// [backbone](Self::set_backbone) settings. Sinks which saved some state have it restored, and every other sink (along with
+    /// any which [still needs it](crate::FrameSink::needs_introduction)) is told about the nodes
+    /// and edges in the checkpoint as if they had just been added.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Checkpoint`] if the system already has nodes or has been stepped, if the
+    /// data is not a valid checkpoint, or if the number of sinks does not match. Otherwise returns
+    /// the first error from reading, or from any sink.
+    pub fn load_state(&mut self, mut r: impl Read) -> Result<()> {
+        if self.steps != 0 || self.graph.node_count() != 0 {
+            return Err(Error::Checkpoint("system is not new"));
+        }
+        if r.read_fixed()? != *MAGIC {
+            return Err(Error::Checkpoint("not a snowball checkpoint"));
+        }
+        if r.read_u32()? != VERSION {
+            return Err(Error::Checkpoint("unsupported checkpoint version"));
+        }
+        let steps = r.read_u64()?;
+        let mut rng = ChaCha8Rng::from_seed(r.read_fixed()?);
+        rng.set_stream(r.read_u64()?);
+        rng.set_word_pos(r.read_u128()?);
+
+        let mut graph = Graph::new();
+        let mut keys = Vec::new();
+        for _ in 0..r.read_len()? {
+            let id = K::read_key(&mut r)?;
+            let colour = r.read_colour()?;
+            let community = match r.read_u64()? {
+                NO_COMMUNITY => None,
+                community => Some(
+                    usize::try_from(community)
+                        .map_err(|_| Error::Checkpoint("community out of range"))?,
+                ),
+            };
+            let size = r.read_f32()?;
+            let avatar = Some(r.read_bytes()?).filter(|avatar| !avatar.is_empty());
+            let pos = r.read_vec2d()?;
+            let velocity = r.read_vec2d()?;
+            if graph.contains_node(&id) {
+                return Err(Error::Checkpoint("duplicate node"));
+            }
+            keys.push(id.clone());
+            graph.add_node(Node {
+                id,
+                colour,
+                community,
+                size,
+                avatar,
+                pos,
+                velocity,
+            });
+        }
+        let nodes = NodeTable::new(keys);
+        for _ in 0..r.read_len()? {
+            let from = nodes.key(r.read_u64()?)?.clone();
+            let to = nodes.key(r.read_u64()?)?.clone();
+            let weight = r.read_f32()?;
+            graph.set_weight(from, to, weight);
+        }
+        let mut backbone_edges = EdgeSet::new();
+        for _ in 0..r.read_len()? {
+            backbone_edges.insert(nodes.key(r.read_u64()?)?, nodes.key(r.read_u64()?)?);
+        }
+
+        let sink_count = r.read_len()?;
+        if sink_count != self.sinks.len() {
+            return Err(Error::Checkpoint("number of sinks does not match"));
+        }
+        let mut sink_states = Vec::with_capacity(sink_count);
+        for _ in 0..sink_count {
+            sink_states.push(r.read_bytes()?);
+        }
+
+        self.graph = graph;
+        self.backbone_edges = backbone_edges;
+        self.steps = steps;
+        self.rng = rng;
+        let mut sinks = std::mem::take(&mut self.sinks);
+        let mut result = Ok(());
+        for (sink, state) in sinks.iter_mut().zip(sink_states) {
+            let sink_result = if state.is_empty() {
+                self.introduce_all(sink.as_mut())
+            } else {
+                sink.load_state(&mut state.as_slice(), &nodes)
+                    .and_then(|()| {
+                        if sink.needs_introduction() {
+                            self.introduce_all(sink.as_mut())
+                        } else {
+                            Ok(())
+                        }
+                    })
+            };
+            if result.is_ok() {
+                result = sink_result;
+            }
+        }
+        self.sinks = sinks;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Error, NodeKey, System};
+
+    // Nodes are iterated in hash order, which may differ between two graphs with the same
+    // contents, so forces can be summed in a different order. Positions are therefore compared
+    // approximately.
+    fn assert_same_positions<K: NodeKey>(a: &System<K>, b: &System<K>) {
+        assert_eq!(a.graph.node_count(), b.graph.node_count());
+        for node in a.graph.nodes() {
+            let other = b.graph.get_node(&node.id).unwrap();
+            assert!(
+                node.pos.distance(other.pos) < 0.01,
+                "node {:?} moved",
+                node.id
+            );
+        }
+    }
+
+    #[test]
+    fn resume_matches_uninterrupted() {
+        let mut original: System = System::with_seed(7);
+        original.add_node(0, [255, 0, 0]);
+        original.add_node(1, [0, 255, 0]);
+        // Avatars are only checked by sinks which draw them, so any bytes will do.
+        original.add_node_with_avatar(2, [0, 0, 255], b"avatar".to_vec());
+        original.set_weight(0, 1, 100.);
+        original.many_steps(20).unwrap();
+
+        let mut checkpoint = Vec::new();
+        original.save_state(&mut checkpoint).unwrap();
+        let mut resumed = System::new();
+        resumed.load_state(checkpoint.as_slice()).unwrap();
+        assert_same_positions(&original, &resumed);
+        assert_eq!(resumed.steps, 20);
+        assert_eq!(
+            resumed.graph.get_node(&2).unwrap().avatar.as_deref(),
+            Some(&b"avatar"[..])
+        );
+        assert_eq!(resumed.graph.get_node(&0).unwrap().avatar, None);
+
+        // Adding a node uses the RNG, so this checks that its state was restored too.
+        for system in [&mut original, &mut resumed] {
+            system.add_node(3, [0, 0, 0]);
+            system.set_weight(1, 3, 50.);
+            system.many_steps(20).unwrap();
+        }
+        assert_same_positions(&original, &resumed);
+    }
+
+    #[test]
+    fn rejects_invalid_checkpoints() {
+        let mut system: System = System::new();
+        assert!(matches!(
+            system.load_state(&b"NOTSNOWB"[..]),
+            Err(Error::Checkpoint(_))
+        ));
+        assert!(matches!(
+            system.load_state(&b"SNOWBALL"[..]),
+            Err(Error::Checkpoint(_))
+        ));
+
+        let mut checkpoint = Vec::new();
+        System::<u64>::new().save_state(&mut checkpoint).unwrap();
+        system.add_node(0, [0, 0, 0]);
+        assert!(matches!(
+            system.load_state(checkpoint.as_slice()),
+            Err(Error::Checkpoint(_))
+        ));
+    }
+
+    #[test]
+    fn resume_with_string_keys() {
+        let mut original = System::with_seed(3);
+        original.add_node(("guild".to_string(), 1), [255, 0, 0]);
+        original.add_node(("guild".to_string(), 2), [0, 255, 0]);
+        original.set_weight(("guild".to_string(), 1), ("guild".to_string(), 2), 100.);
+        original.many_steps(10).unwrap();
+
+        let mut checkpoint = Vec::new();
+        original.save_state(&mut checkpoint).unwrap();
+        let mut resumed = System::new();
+        resumed.load_state(checkpoint.as_slice()).unwrap();
+        for system in [&mut original, &mut resumed] {
+            system.many_steps(10).unwrap();
+        }
+        assert_same_positions(&original, &resumed);
+    }
+}
@@ -0,0 +1,977 @@
+//! Rendering the layout as SVG, which stays crisp at any size and needs no raster dependencies.
+//!
+//! The image has the same size and background as the raster outputs. Edges with a positive weight
+//! are drawn as lines beneath the nodes, thicker and more opaque the heavier they are, and each
+//! node can optionally be labelled.
+//!
+//! [`SvgSnapshot`] writes the layout as it is at the end of the simulation, and [`SvgAnimation`]
+//! records the whole simulation as an animated image which plays in any browser.
+use crate::colour::{BACKGROUND_COLOUR, EDGE_COLOUR, LABEL_COLOUR};
+use crate::exchange::hex_colour;
+use crate::graph::{Graph, HasKey};
+use crate::output::Output;
+use crate::sink::set_at;
+use crate::state::{NodeTable, ReadState, WriteState};
+use crate::{Error, FrameSink, NodeKey, Result, Vec2d, SIZE};
+use hashbrown::HashMap;
+use std::io::{Read, Write};
+
+const NODE_RADIUS: f32 = 10.;
+const LABEL_SIZE: f32 = 12.;
+
+/// Escape text for use in SVG content or attributes.
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Get the stroke width and opacity of an edge, relative to the heaviest edge.
+pub(crate) fn edge_style(weight: f32, max_weight: f32) -> (f32, f32) {
+    let strength = (weight / max_weight).clamp(0., 1.);
+    (strength.mul_add(3., 1.), strength.mul_add(0.5, 0.3))
+}
+
+/// Write the start of an SVG document, including the background.
+pub(crate) fn write_header(w: &mut impl Write) -> Result<()> {
+    writeln!(
+        w,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{SIZE}" height="{SIZE}" viewBox="0 0 {SIZE} {SIZE}">"#
+    )?;
+    writeln!(
+        w,
+        r#"  <rect width="{SIZE}" height="{SIZE}" fill="{}"/>"#,
+        hex_colour(BACKGROUND_COLOUR)
+    )?;
+    Ok(())
+}
+
+/// A node as it is currently drawn.
+#[derive(Clone, Debug)]
+struct SceneNode<K> {
+    id: K,
+    colour: [u8; 3],
+    size: f32,
+    // This is `None` until the node is first placed.
+    pos: Option<Vec2d>,
+    label: Option<String>,
+    // Nodes are drawn in the order they were added, so newer nodes appear on top.
+    order: u64,
+}
+
+impl<K: NodeKey> HasKey for SceneNode<K> {
+    type Key = K;
+
+    fn key(&self) -> K {
+        self.id.clone()
+    }
+}
+
+/// A vector canvas holding the current state of every node and edge, which can be written as an
+/// SVG image at any time.
+///
+/// Nodes are identified by the same IDs as in the [`System`](crate::System), and are only drawn
+/// once they have been placed.
+#[derive(Clone, Debug)]
+pub struct Scene<K: NodeKey = u64> {
+    graph: Graph<SceneNode<K>, f32>,
+    next_order: u64,
+}
+
+impl<K: NodeKey> Default for Scene<K> {
+    fn default() -> Self {
+        Self {
+            graph: Graph::new(),
+            next_order: 0,
+        }
+    }
+}
+
+impl<K: NodeKey> Scene<K> {
+    /// Create an empty canvas.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn node_mut(&mut self, id: &K) -> Result<&mut SceneNode<K>> {
+        self.graph
+            .get_node_mut(id)
+            .ok_or_else(|| Error::node_not_found(id))
+    }
+
+    /// Add a node, drawn in the given colour above every existing node. If the node already
+    /// exists, it is replaced.
+    pub fn add_node(&mut self, id: K, colour: [u8; 3]) {
+        self.graph.remove_node(&id);
+        self.graph.add_node(SceneNode {
+            id,
+            colour,
+            size: 1.,
+            pos: None,
+            label: None,
+            order: self.next_order,
+        });
+        self.next_order += 1;
+    }
+
+    /// Draw a node in a new colour.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NodeNotFound`] if the node does not exist.
+    pub fn recolour_node(&mut self, id: &K, colour: [u8; 3]) -> Result<()> {
+        self.node_mut(id)?.colour = colour;
+        Ok(())
+    }
+
+    /// Draw a node at a new size, as a multiple of the default radius.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NodeNotFound`] if the node does not exist.
+    pub fn resize_node(&mut self, id: &K, size: f32) -> Result<()> {
+        self.node_mut(id)?.size = size;
+        Ok(())
+    }
+
+    /// Show a label next to a node, or remove its label if `label` is `None`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NodeNotFound`] if the node does not exist.
+    pub fn set_label(&mut self, id: &K, label: Option<String>) -> Result<()> {
+        self.node_mut(id)?.label = label;
+        Ok(())
+    }
+
+    /// Remove a node and its edges from the canvas. Nothing happens if the node does not exist.
+    pub fn remove_node(&mut self, id: &K) {
+        self.graph.remove_node(id);
+    }
+
+    /// Set the weight of the edge between two nodes. Only edges with a positive weight are drawn.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NodeNotFound`] if either node does not exist.
+    pub fn set_weight(&mut self, from: &K, to: &K, weight: f32) -> Result<()> {
+        for id in [from, to] {
+            if !self.graph.contains_node(id) {
+                return Err(Error::node_not_found(id));
+            }
+        }
+        self.graph.set_weight(from.clone(), to.clone(), weight);
+        Ok(())
+    }
+
+    /// Move a node so that it is centered on the given point.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NodeNotFound`] if the node does not exist.
+    pub fn place_node(&mut self, id: &K, center: Vec2d) -> Result<()> {
+        self.node_mut(id)?.pos = Some(center);
+        Ok(())
+    }
+
+    /// Write the current state of the canvas as an SVG image.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing fails.
+    pub fn write_svg(&self, mut w: impl Write) -> Result<()> {
+        write_header(&mut w)?;
+
+        let edges = self
+            .graph
+            .edges_unique()
+            .filter_map(|(from, to, &weight)| Some((from.pos?, to.pos?, weight)))
+            .filter(|&(_, _, weight)| weight > 0.)
+            .collect::<Vec<_>>();
+        let max_weight = edges
+            .iter()
+            .map(|&(_, _, weight)| weight)
+            .fold(0., f32::max);
+        writeln!(
+            w,
+            r#"  <g stroke="{}" stroke-linecap="round">"#,
+            hex_colour(EDGE_COLOUR)
+        )?;
+        for (from, to, weight) in edges {
+            let (width, opacity) = edge_style(weight, max_weight);
+            writeln!(
+                w,
+                r#"    <line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke-width="{width:.2}" stroke-opacity="{opacity:.2}"/>"#,
+                from.x, from.y, to.x, to.y
+            )?;
+        }
+        writeln!(w, "  </g>")?;
+
+        let mut nodes = self
+            .graph
+            .nodes()
+            .filter_map(|node| Some((node, node.pos?)))
+            .collect::<Vec<_>>();
+        nodes.sort_by_key(|(node, _)| node.order);
+        writeln!(w, "  <g>")?;
+        for (node, pos) in &nodes {
+            writeln!(
+                w,
+                r#"    <circle cx="{:.1}" cy="{:.1}" r="{:.1}" fill="{}"/>"#,
+                pos.x,
+                pos.y,
+                NODE_RADIUS * node.size,
+                hex_colour(node.colour)
+            )?;
+        }
+        writeln!(w, "  </g>")?;
+
+        // Labels go above every node, so that they are never hidden by a neighbour.
+        writeln!(
+            w,
+            r#"  <g font-family="sans-serif" font-size="{LABEL_SIZE}" fill="{}" dominant-baseline="central">"#,
+            hex_colour(LABEL_COLOUR)
+        )?;
+        for (node, pos) in &nodes {
+            if let Some(label) = &node.label {
+                writeln!(
+                    w,
+                    r#"    <text x="{:.1}" y="{:.1}">{}</text>"#,
+                    NODE_RADIUS.mul_add(node.size, pos.x + 2.),
+                    pos.y,
+                    escape(label)
+                )?;
+            }
+        }
+        writeln!(w, "  </g>")?;
+        writeln!(w, "</svg>")?;
+        Ok(())
+    }
+}
+
+/// Chooses the label of each node, if any.
+type Labeller<K> = Box<dyn Fn(&K) -> Option<String>>;
+
+/// A sink which keeps track of the layout, and writes it as an SVG image when the simulation
+/// finishes.
+pub struct SvgSnapshot<K: NodeKey = u64> {
+    scene: Scene<K>,
+    labels: Option<Labeller<K>>,
+    // This is only `None` once the image has been written.
+    output: Option<Box<dyn Write>>,
+}
+
+impl<K: NodeKey> SvgSnapshot<K> {
+    /// Create a sink which will write to the given destination when finished.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the destination cannot be opened.
+    pub fn new(output: impl Into<Output>) -> Result<Self> {
+        Ok(Self {
+            scene: Scene::new(),
+            labels: None,
+            output: Some(output.into().open()?),
+        })
+    }
+
+    /// Label each node with the text returned by `labels`, such as a display name, or leave it
+    /// unlabelled if that is `None`.
+    #[must_use]
+    pub fn with_labels(mut self, labels: impl Fn(&K) -> Option<String> + 'static) -> Self {
+        self.labels = Some(Box::new(labels));
+        self
+    }
+
+    /// Get the canvas as it currently stands.
+    #[must_use]
+    pub const fn scene(&self) -> &Scene<K> {
+        &self.scene
+    }
+}
+
+impl<K: NodeKey> FrameSink<K> for SvgSnapshot<K> {
+    fn node_added(&mut self, id: &K, colour: [u8; 3]) -> Result<()> {
+        self.scene.add_node(id.clone(), colour);
+        if let Some(labels) = &self.labels {
+            self.scene.set_label(id, labels(id))?;
+        }
+        Ok(())
+    }
+
+    fn node_recoloured(&mut self, id: &K, colour: [u8; 3]) -> Result<()> {
+        self.scene.recolour_node(id, colour)
+    }
+
+    fn node_resized(&mut self, id: &K, size: f32) -> Result<()> {
+        self.scene.resize_node(id, size)
+    }
+
+    fn node_removed(&mut self, id: &K) -> Result<()> {
+        self.scene.remove_node(id);
+        Ok(())
+    }
+
+    fn weight_changed(&mut self, from: &K, to: &K, weight: f32) -> Result<()> {
+        self.scene.set_weight(from, to, weight)
+    }
+
+    fn node_moved(&mut self, id: &K, pos: Vec2d) -> Result<()> {
+        self.scene.place_node(id, pos)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if let Some(mut output) = self.output.take() {
+            self.scene.write_svg(&mut output)?;
+            output.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// The number of steps shown per second in an animation, as in the Lottie output.
+const FRAME_RATE: u32 = 60;
+
+/// A position held for a number of steps, rounded to whole pixels so that nodes which have
+/// settled down make long runs.
+#[derive(Clone, Copy, Debug)]
+struct Frame {
+    pos: Vec2d,
+    length: u32,
+}
+
+/// The recorded appearance of a node over one lifetime.
+#[derive(Clone, Debug)]
+struct Track {
+    start: u32,
+    // This is `None` until the node is removed.
+    end: Option<u32>,
+    // Each colour the node has had, with the step it was first shown at, starting with its
+    // original colour at `start`.
+    colours: Vec<(u32, [u8; 3])>,
+    // Each size the node has had, in the same way.
+    sizes: Vec<(u32, f32)>,
+    frames: Vec<Frame>,
+    label: Option<String>,
+    // Nodes are drawn in the order they were added, so newer nodes appear on top.
+    order: u64,
+}
+
+impl Track {
+    fn save_state(&self, w: &mut dyn Write) -> Result<()> {
+        w.write_u32(self.start)?;
+        w.write_u8(u8::from(self.end.is_some()))?;
+        w.write_u32(self.end.unwrap_or_default())?;
+        w.write_len(self.colours.len())?;
+        for &(step, colour) in &self.colours {
+            w.write_u32(step)?;
+            w.write_colour(colour)?;
+        }
+        w.write_len(self.sizes.len())?;
+        for &(step, size) in &self.sizes {
+            w.write_u32(step)?;
+            w.write_f32(size)?;
+        }
+        w.write_len(self.frames.len())?;
+        for frame in &self.frames {
+            w.write_vec2d(frame.pos)?;
+            w.write_u32(frame.length)?;
+        }
+        w.write_u8(u8::from(self.label.is_some()))?;
+        w.write_bytes(self.label.as_deref().unwrap_or_default().as_bytes())?;
+        w.write_u64(self.order)?;
+        Ok(())
+    }
+
+    fn load_state(r: &mut dyn Read) -> Result<Self> {
+        let start = r.read_u32()?;
+        let has_end = r.read_u8()? != 0;
+        let end = Some(r.read_u32()?).filter(|_| has_end);
+        let mut colours = Vec::new();
+        for _ in 0..r.read_len()? {
+            colours.push((r.read_u32()?, r.read_colour()?));
+        }
+        let mut sizes = Vec::new();
+        for _ in 0..r.read_len()? {
+            sizes.push((r.read_u32()?, r.read_f32()?));
+        }
+        let mut frames = Vec::new();
+        for _ in 0..r.read_len()? {
+            let pos = r.read_vec2d()?;
+            frames.push(Frame {
+                pos,
+                length: r.read_u32()?,
+            });
+        }
+        let has_label = r.read_u8()? != 0;
+        let label = String::from_utf8(r.read_bytes()?)
+            .map_err(|_| Error::Checkpoint("label is not valid UTF-8"))?;
+        Ok(Self {
+            start,
+            end,
+            colours,
+            sizes,
+            frames,
+            label: has_label.then_some(label),
+            order: r.read_u64()?,
+        })
+    }
+
+    fn push_pos(&mut self, pos: Vec2d) {
+        let pos = Vec2d::new(pos.x.round(), pos.y.round());
+        if let Some(last) = self.frames.last_mut() {
+            if last.pos == pos {
+                last.length += 1;
+                return;
+            }
+        }
+        self.frames.push(Frame { pos, length: 1 });
+    }
+
+    /// Get the points the node moves through, holding each position until the end of its run
+    /// before moving smoothly to the next. Linear animations must cover the whole duration, so
+    /// the first and last positions are also held before and after the node is moving.
+    fn path(&self, length: u32) -> Vec<(u32, Vec2d)> {
+        let mut points = Vec::new();
+        let mut time = self.start;
+        for frame in &self.frames {
+            points.push((time, frame.pos));
+            if frame.length > 1 {
+                points.push((time + frame.length - 1, frame.pos));
+            }
+            time += frame.length;
+        }
+        if let Some(&(time, pos)) = points.first() {
+            if time > 0 {
+                points.insert(0, (0, pos));
+            }
+        }
+        if let Some(&(time, pos)) = points.last() {
+            if time < length {
+                points.push((length, pos));
+            }
+        }
+        points
+    }
+
+    /// Write the node as an animated group. Nodes which were never placed are left out.
+    fn write(&self, w: &mut impl Write, timing: &Timing) -> Result<()> {
+        let Some(first) = self.frames.first() else {
+            return Ok(());
+        };
+
+        let mut visibility = Vec::new();
+        if self.start > 0 {
+            visibility.push((0, "hidden".to_string()));
+        }
+        visibility.push((self.start, "visible".to_string()));
+        if let Some(end) = self.end {
+            visibility.push((end, "hidden".to_string()));
+        }
+        writeln!(
+            w,
+            r#"    <g transform="translate({},{})" visibility="{}">"#,
+            first.pos.x, first.pos.y, visibility[0].1
+        )?;
+        timing.write_discrete(w, "      ", "visibility", &visibility)?;
+        if self.frames.len() > 1 {
+            let path = self.path(timing.length);
+            let values = path
+                .iter()
+                .map(|(_, pos)| format!("{},{}", pos.x, pos.y))
+                .collect::<Vec<_>>();
+            writeln!(
+                w,
+                r#"      <animateTransform attributeName="transform" type="translate" values="{}" keyTimes="{}" {}/>"#,
+                values.join(";"),
+                timing.key_times(path.iter().map(|&(step, _)| step)),
+                timing.attributes()
+            )?;
+        }
+
+        let radius = |size: f32| format!("{:.1}", NODE_RADIUS * size);
+        let colours = self
+            .colours
+            .iter()
+            .map(|&(step, colour)| (step, hex_colour(colour)))
+            .collect::<Vec<_>>();
+        let sizes = self
+            .sizes
+            .iter()
+            .map(|&(step, size)| (step, radius(size)))
+            .collect::<Vec<_>>();
+        writeln!(
+            w,
+            r#"      <circle r="{}" fill="{}">"#,
+            sizes[0].1, colours[0].1
+        )?;
+        timing.write_discrete(w, "        ", "fill", &colours)?;
+        timing.write_discrete(w, "        ", "r", &sizes)?;
+        writeln!(w, "      </circle>")?;
+
+        if let Some(label) = &self.label {
+            let largest = self.sizes.iter().map(|&(_, size)| size).fold(0., f32::max);
+            writeln!(
+                w,
+                r#"      <text x="{:.1}">{}</text>"#,
+                NODE_RADIUS.mul_add(largest, 2.),
+                escape(label)
+            )?;
+        }
+        writeln!(w, "    </g>")?;
+        Ok(())
+    }
+}
+
+/// The timing shared by every animation in a recording, which all loop together.
+struct Timing {
+    length: u32,
+}
+
+impl Timing {
+    fn attributes(&self) -> String {
+        format!(
+            r#"dur="{:.3}s" repeatCount="indefinite""#,
+            f64::from(self.length) / f64::from(FRAME_RATE)
+        )
+    }
+
+    fn key_times(&self, steps: impl IntoIterator<Item = u32>) -> String {
+        steps
+            .into_iter()
+            .map(|step| format!("{:.5}", f64::from(step) / f64::from(self.length)))
+            .collect::<Vec<_>>()
+            .join(";")
+    }
+
+    /// Write an animation which switches an attribute to each value at the step it was set,
+    /// unless the value never changes. The first value is shown from the very start.
+    fn write_discrete(
+        &self,
+        w: &mut impl Write,
+        indent: &str,
+        attribute: &str,
+        changes: &[(u32, String)],
+    ) -> Result<()> {
+        if changes.len() < 2 {
+            return Ok(());
+        }
+        let values = changes
+            .iter()
+            .map(|(_, value)| value.as_str())
+            .collect::<Vec<_>>();
+        let steps = std::iter::once(0).chain(changes.iter().skip(1).map(|&(step, _)| step));
+        writeln!(
+            w,
+            r#"{indent}<animate attributeName="{attribute}" values="{}" keyTimes="{}" calcMode="discrete" {}/>"#,
+            values.join(";"),
+            self.key_times(steps),
+            self.attributes()
+        )?;
+        Ok(())
+    }
+}
+
+/// A record of the trajectory of every node, to be written as an animated SVG image.
+///
+/// Positions are run-length encoded, so nodes which have settled down cost very little. The
+/// animation uses SMIL, so it plays in any browser without a separate player, and loops forever.
+/// Edges are not drawn.
+#[derive(Clone, Debug)]
+pub struct Recording<K = u64> {
+    open: HashMap<K, Track>,
+    closed: Vec<Track>,
+    step: u32,
+    next_order: u64,
+}
+
+impl<K: NodeKey> Default for Recording<K> {
+    fn default() -> Self {
+        Self {
+            open: HashMap::new(),
+            closed: Vec::new(),
+            step: 0,
+            next_order: 0,
+        }
+    }
+}
+
+impl<K: NodeKey> Recording<K> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn track_mut(&mut self, id: &K) -> Result<&mut Track> {
+        self.open
+            .get_mut(id)
+            .ok_or_else(|| Error::node_not_found(id))
+    }
+
+    /// Start recording a node, which appears from the current step onwards. If the node already
+    /// exists, it is removed first.
+    pub fn add_node(&mut self, id: K, colour: [u8; 3]) {
+        self.remove_node(&id);
+        self.open.insert(
+            id,
+            Track {
+                start: self.step,
+                end: None,
+                colours: vec![(self.step, colour)],
+                sizes: vec![(self.step, 1.)],
+                frames: Vec::new(),
+                label: None,
+                order: self.next_order,
+            },
+        );
+        self.next_order += 1;
+    }
+
+    /// Show a node in a new colour from the current step onwards.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NodeNotFound`] if the node has not been added, or has been removed.
+    pub fn set_colour(&mut self, id: &K, colour: [u8; 3]) -> Result<()> {
+        let step = self.step;
+        set_at(&mut self.track_mut(id)?.colours, step, colour);
+        Ok(())
+    }
+
+    /// Draw a node at a new size from the current step onwards, where 1 is the default size.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NodeNotFound`] if the node has not been added, or has been removed.
+    pub fn set_size(&mut self, id: &K, size: f32) -> Result<()> {
+        let step = self.step;
+        set_at(&mut self.track_mut(id)?.sizes, step, size);
+        Ok(())
+    }
+
+    /// Show a label next to a node for its whole lifetime, or remove its label if `label` is
+    /// `None`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NodeNotFound`] if the node has not been added, or has been removed.
+    pub fn set_label(&mut self, id: &K, label: Option<String>) -> Result<()> {
+        self.track_mut(id)?.label = label;
+        Ok(())
+    }
+
+    /// Hide a node from the current step onwards. Nothing happens if the node does not exist.
+    pub fn remove_node(&mut self, id: &K) {
+        if let Some(mut track) = self.open.remove(id) {
+            track.end = Some(self.step);
+            self.closed.push(track);
+        }
+    }
+
+    /// Record the position of a node for the current step.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NodeNotFound`] if the node has not been added, or has been removed.
+    pub fn set_position(&mut self, id: &K, pos: Vec2d) -> Result<()> {
+        self.track_mut(id)?.push_pos(pos);
+        Ok(())
+    }
+
+    pub const fn next_step(&mut self) {
+        self.step += 1;
+    }
+
+    /// Write the recording in the checkpoint format (see [`crate::state`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing fails, or if a node which has not been removed is missing from
+    /// `nodes`.
+    pub fn save_state(&self, w: &mut dyn Write, nodes: &NodeTable<K>) -> Result<()> {
+        w.write_u32(self.step)?;
+        w.write_u64(self.next_order)?;
+        w.write_len(self.open.len())?;
+        for (id, track) in &self.open {
+            w.write_u64(nodes.index(id)?)?;
+            track.save_state(w)?;
+        }
+        w.write_len(self.closed.len())?;
+        for track in &self.closed {
+            track.save_state(w)?;
+        }
+        Ok(())
+    }
+
+    /// Read a recording written by [`save_state`](Self::save_state).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading fails, the data is truncated, or it refers to a node which is
+    /// not in `nodes`.
+    pub fn load_state(r: &mut dyn Read, nodes: &NodeTable<K>) -> Result<Self> {
+        let step = r.read_u32()?;
+        let next_order = r.read_u64()?;
+        let mut open = HashMap::new();
+        for _ in 0..r.read_len()? {
+            let id = nodes.key(r.read_u64()?)?.clone();
+            open.insert(id, Track::load_state(r)?);
+        }
+        let mut closed = Vec::new();
+        for _ in 0..r.read_len()? {
+            closed.push(Track::load_state(r)?);
+        }
+        Ok(Self {
+            open,
+            closed,
+            step,
+            next_order,
+        })
+    }
+
+    /// Write the recording as an animated SVG image.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing fails.
+    pub fn write_svg(&self, mut w: impl Write) -> Result<()> {
+        write_header(&mut w)?;
+        let mut tracks = self
+            .closed
+            .iter()
+            .chain(self.open.values())
+            .collect::<Vec<_>>();
+        tracks.sort_by_key(|track| track.order);
+        writeln!(
+            w,
+            r#"  <g font-family="sans-serif" font-size="{LABEL_SIZE}" dominant-baseline="central">"#
+        )?;
+        let timing = Timing {
+            length: self.step.max(1),
+        };
+        for track in tracks {
+            track.write(&mut w, &timing)?;
+        }
+        writeln!(w, "  </g>")?;
+        writeln!(w, "</svg>")?;
+        Ok(())
+    }
+}
+
+/// A sink which records a [`Recording`], and writes it as an animated SVG image when the
+/// simulation finishes.
+pub struct SvgAnimation<K = u64> {
+    recording: Recording<K>,
+    labels: Option<Labeller<K>>,
+    // This is only `None` once the animation has been written.
+    output: Option<Box<dyn Write>>,
+}
+
+impl<K: NodeKey> SvgAnimation<K> {
+    /// Create a sink which will write to the given destination when finished.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the destination cannot be opened.
+    pub fn new(output: impl Into<Output>) -> Result<Self> {
+        Ok(Self {
+            recording: Recording::new(),
+            labels: None,
+            output: Some(output.into().open()?),
+        })
+    }
+
+    /// Label each node with the text returned by `labels`, as in
+    /// [`SvgSnapshot::with_labels`].
+    #[must_use]
+    pub fn with_labels(mut self, labels: impl Fn(&K) -> Option<String> + 'static) -> Self {
+        self.labels = Some(Box::new(labels));
+        self
+    }
+
+    /// Get the recording made so far.
+    #[must_use]
+    pub const fn recording(&self) -> &Recording<K> {
+        &self.recording
+    }
+}
+
+impl<K: NodeKey> FrameSink<K> for SvgAnimation<K> {
+    fn node_added(&mut self, id: &K, colour: [u8; 3]) -> Result<()> {
+        self.recording.add_node(id.clone(), colour);
+        if let Some(labels) = &self.labels {
+            self.recording.set_label(id, labels(id))?;
+        }
+        Ok(())
+    }
+
+    fn node_recoloured(&mut self, id: &K, colour: [u8; 3]) -> Result<()> {
+        self.recording.set_colour(id, colour)
+    }
+
+    fn node_resized(&mut self, id: &K, size: f32) -> Result<()> {
+        self.recording.set_size(id, size)
+    }
+
+    fn node_removed(&mut self, id: &K) -> Result<()> {
+        self.recording.remove_node(id);
+        Ok(())
+    }
+
+    fn node_moved(&mut self, id: &K, pos: Vec2d) -> Result<()> {
+        self.recording.set_position(id, pos)
+    }
+
+    fn step_finished(&mut self, _step: u64) -> Result<()> {
+        self.recording.next_step();
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if let Some(mut output) = self.output.take() {
+            self.recording.write_svg(&mut output)?;
+            output.flush()?;
+        }
+        Ok(())
+    }
+
+    fn save_state(&self, w: &mut dyn Write, nodes: &NodeTable<K>) -> Result<()> {
+        self.recording.save_state(w, nodes)
+    }
+
+    fn load_state(&mut self, r: &mut dyn Read, nodes: &NodeTable<K>) -> Result<()> {
+        self.recording = Recording::load_state(r, nodes)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Recording, SvgAnimation, SvgSnapshot};
+    use crate::state::NodeTable;
+    use crate::{SharedBuffer, System, Vec2d};
+
+    #[test]
+    fn snapshot() {
+        let buffer = SharedBuffer::new();
+        let mut system: System = System::with_seed(1);
+        system
+            .add_sink(
+                SvgSnapshot::new(buffer.clone())
+                    .unwrap()
+                    .with_labels(|&id| (id != 3).then(|| format!("<user {id}>"))),
+            )
+            .unwrap();
+        system.add_node(1, [255, 0, 0]);
+        system.add_node(2, [0, 0, 255]);
+        system.add_node(3, None);
+        system.set_weight(1, 2, 10.);
+        system.set_weight(2, 3, 5.);
+        system.set_weight(1, 3, -5.);
+        system.set_size(&2, 2.).unwrap();
+        system.many_steps(3).unwrap();
+        system.remove_node(&3);
+        system.finish().unwrap();
+
+        let svg = String::from_utf8(buffer.take()).unwrap();
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        assert!(svg.contains(r##"<rect width="1000" height="1000" fill="#eee8d5"/>"##));
+        assert_eq!(svg.matches("<circle ").count(), 2);
+        assert!(svg.contains(r##"r="20.0" fill="#0000ff""##));
+        // Only the edge between the remaining nodes, as the heaviest, at full strength.
+        assert_eq!(svg.matches("<line ").count(), 1);
+        assert!(svg.contains(r#"stroke-width="4.00" stroke-opacity="0.80""#));
+        assert!(svg.contains(">&lt;user 1&gt;</text>"));
+        assert_eq!(svg.matches("<text ").count(), 2);
+    }
+
+    #[test]
+    fn animation() {
+        let buffer = SharedBuffer::new();
+        let mut system: System = System::with_seed(1);
+        system.add_node(1, [255, 0, 0]);
+        system.add_node(2, [0, 0, 255]);
+        system.set_weight(1, 2, 10.);
+        system
+            .add_sink(
+                SvgAnimation::new(buffer.clone())
+                    .unwrap()
+                    .with_labels(|id| Some(format!("user {id}"))),
+            )
+            .unwrap();
+        system.many_steps(10).unwrap();
+        system.add_node(3, [0, 255, 0]);
+        system.set_colour(&1, [0, 0, 0]).unwrap();
+        system.many_steps(10).unwrap();
+        system.remove_node(&2);
+        system.many_steps(10).unwrap();
+        system.finish().unwrap();
+
+        let svg = String::from_utf8(buffer.take()).unwrap();
+        assert_eq!(svg.matches("<circle ").count(), 3);
+        assert_eq!(svg.matches("<text ").count(), 3);
+        assert_eq!(svg.matches("<animateTransform ").count(), 3);
+        assert!(svg.contains(r#"dur="0.500s" repeatCount="indefinite""#));
+        // Node 2 disappears two thirds of the way through, and node 3 appears a third of the way.
+        assert!(svg.contains(r#"values="visible;hidden" keyTimes="0.00000;0.66667""#));
+        assert!(svg.contains(r#"values="hidden;visible" keyTimes="0.00000;0.33333""#));
+        assert!(svg.contains(
+            r##"<animate attributeName="fill" values="#ff0000;#000000" keyTimes="0.00000;0.33333""##
+        ));
+        // Every position animation covers the whole duration.
+        for line in svg
+            .lines()
+            .filter(|line| line.contains("<animateTransform"))
+        {
+            let key_times = line.split("keyTimes=\"").nth(1).unwrap();
+            assert!(key_times.starts_with("0.00000;"), "{line}");
+            assert!(key_times.contains(";1.00000\""), "{line}");
+        }
+    }
+
+    #[test]
+    fn recording_state_round_trip() {
+        let mut recording = Recording::new();
+        recording.add_node("alice", [255, 0, 0]);
+        recording.add_node("bob", [0, 0, 255]);
+        recording
+            .set_label(&"bob", Some("Bob".to_string()))
+            .unwrap();
+        for step in 0..10 {
+            recording
+                .set_position(&"alice", Vec2d::new(step as f32, 0.))
+                .unwrap();
+            recording.set_position(&"bob", Vec2d::new(5., 5.)).unwrap();
+            recording.next_step();
+        }
+        recording.set_colour(&"bob", [0, 255, 0]).unwrap();
+        recording.set_size(&"bob", 1.5).unwrap();
+        recording.remove_node(&"alice");
+
+        let nodes = NodeTable::new(vec!["bob"]);
+        let mut state = Vec::new();
+        recording.save_state(&mut state, &nodes).unwrap();
+        let loaded = Recording::load_state(&mut state.as_slice(), &nodes).unwrap();
+        assert_eq!(loaded.step, 10);
+        assert_eq!(loaded.closed.len(), 1);
+        assert_eq!(loaded.closed[0].end, Some(10));
+        assert_eq!(loaded.open["bob"].frames.len(), 1);
+        assert_eq!(loaded.open["bob"].frames[0].length, 10);
+        assert_eq!(loaded.open["bob"].label.as_deref(), Some("Bob"));
+        let (mut a, mut b) = (Vec::new(), Vec::new());
+        recording.write_svg(&mut a).unwrap();
+        loaded.write_svg(&mut b).unwrap();
+        assert_eq!(a, b);
+    }
+}
@@ -8,25 +8,29 @@ pub struct Vec2d {
 }
 
 impl Vec2d {
+    #[must_use]
     pub const fn new(x: f32, y: f32) -> Self {
         Self { x, y }
     }
 
-    pub fn random_unit() -> Self {
-        let mut rng = rand::thread_rng();
+    #[must_use]
+    pub fn random_unit(rng: &mut impl Rng) -> Self {
         let x: f32 = rng.gen_range(-1.0..1.0);
         let y = x.mul_add(-x, 1.).sqrt(); // sqrt(1 - x^2)
         Self { x, y }
     }
 
+    #[must_use]
     pub fn length(self) -> f32 {
         self.x.hypot(self.y)
     }
 
+    #[must_use]
     pub fn distance(self, other: Self) -> f32 {
         (self - other).length()
     }
 
+    #[must_use]
     pub fn as_unit(self) -> Self {
         let length = self.length();
         Self {